@@ -21,13 +21,19 @@
     if systemConfig.static
     then pkgs.pkgsStatic.stdenv.cc
     else pkgs.stdenv.cc;
+  # attestation sources are picked by cargo features
+  build = features:
+    naersk'.buildPackage {
+      # builds against the sdk in the monorepo
+      src = ../..;
+      root = ./.;
+      CARGO_BUILD_TARGET = target;
+      TARGET_CC = "${cc}/bin/${cc.targetPrefix}cc";
+      nativeBuildInputs = [cc];
+      cargoBuildOptions = x: x ++ ["--no-default-features" "--features" features];
+    };
 in rec {
-  uncompressed = naersk'.buildPackage {
-    src = ./.;
-    CARGO_BUILD_TARGET = target;
-    TARGET_CC = "${cc}/bin/${cc.targetPrefix}cc";
-    nativeBuildInputs = [cc];
-  };
+  uncompressed = build "nsm";
 
   compressed =
     pkgs.runCommand "compressed" {
@@ -39,5 +45,20 @@ in rec {
       upx $out/bin/*
     '';
 
+  mock = build "mock";
+
+  docker = pkgs.dockerTools.buildImage {
+    name = "marlinorg/attestation-server-mock";
+    copyToRoot = pkgs.buildEnv {
+      name = "image-root";
+      paths = [mock];
+      pathsToLink = ["/bin"];
+    };
+    config = {
+      Entrypoint = ["/bin/oyster-attestation-server"];
+      Cmd = ["--custom-ip-addr" "0.0.0.0:1350"];
+    };
+  };
+
   default = compressed;
 }
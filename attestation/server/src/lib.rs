@@ -1,27 +1,280 @@
-use aws_nitro_enclaves_nsm_api::api::{Request, Response};
-use aws_nitro_enclaves_nsm_api::driver as nsm_driver;
-use serde_bytes::ByteBuf;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-pub fn get_attestation_doc(pub_key: &[u8]) -> Vec<u8> {
-    let public_key = ByteBuf::from(pub_key);
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
 
-    let request = Request::Attestation {
-        public_key: Some(public_key),
-        user_data: None,
-        nonce: None,
-    };
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "nsm")]
+mod nsm;
 
-    let nsm_fd = nsm_driver::nsm_init();
-    let response = nsm_driver::nsm_process_request(nsm_fd, request);
-    nsm_driver::nsm_exit(nsm_fd);
+#[cfg(feature = "mock")]
+pub use mock::MockSource;
+#[cfg(feature = "nsm")]
+pub use nsm::NsmSource;
 
-    match response {
-        Response::Attestation { document } => document,
-        _ => panic!("nsm driver returned invalid response: {:?}", response),
+/// Source of attestation documents served by the routes
+pub trait AttestationSource: Send + Sync {
+    /// Attestation document with the given fields set
+    fn get_attestation_doc(
+        &self,
+        public_key: Option<&[u8]>,
+        user_data: Option<&[u8]>,
+        nonce: Option<&[u8]>,
+    ) -> Result<Vec<u8>, String>;
+
+    /// Hex encoded attestation document with the given fields set
+    fn get_hex_attestation_doc(
+        &self,
+        public_key: Option<&[u8]>,
+        user_data: Option<&[u8]>,
+        nonce: Option<&[u8]>,
+    ) -> Result<String, String> {
+        let attestation = self.get_attestation_doc(public_key, user_data, nonce);
+        attestation.map(hex::encode)
+    }
+}
+
+type Source = Arc<dyn AttestationSource>;
+
+/// Longest public key the Nitro Secure Module includes in attestations
+pub const MAX_PUBLIC_KEY_LEN: usize = 1024;
+/// Longest user data the Nitro Secure Module includes in attestations
+pub const MAX_USER_DATA_LEN: usize = 512;
+/// Longest nonce the Nitro Secure Module includes in attestations
+pub const MAX_NONCE_LEN: usize = 512;
+
+// fields the nsm would reject, checked by every source so they fail alike
+#[cfg_attr(not(any(feature = "mock", feature = "nsm")), allow(dead_code))]
+fn check_lengths(
+    public_key: Option<&[u8]>,
+    user_data: Option<&[u8]>,
+    nonce: Option<&[u8]>,
+) -> Result<(), String> {
+    for (name, field, max) in [
+        ("public key", public_key, MAX_PUBLIC_KEY_LEN),
+        ("user data", user_data, MAX_USER_DATA_LEN),
+        ("nonce", nonce, MAX_NONCE_LEN),
+    ] {
+        let len = field.map_or(0, <[u8]>::len);
+        if len > max {
+            return Err(format!("{name} is too long, {len} bytes exceeds {max}"));
+        }
     }
+
+    Ok(())
+}
+
+fn internal_error(e: String) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to generate attestation doc: {e:?}"),
+    )
+}
+
+/// Routes serving attestations with the given public key and no other fields set
+///
+/// Safe to expose, callers cannot influence the contents of the attestations.
+pub fn fixed_routes(source: Source, public_key: &'static [u8]) -> Router {
+    Router::new()
+        .route(
+            "/attestation/raw",
+            get(move |State(source): State<Source>| async move {
+                source
+                    .get_attestation_doc(Some(public_key), None, None)
+                    .map_err(internal_error)
+            }),
+        )
+        .route(
+            "/attestation/hex",
+            get(move |State(source): State<Source>| async move {
+                source
+                    .get_hex_attestation_doc(Some(public_key), None, None)
+                    .map_err(internal_error)
+            }),
+        )
+        .with_state(source)
 }
 
-pub fn get_hex_attestation_doc(pub_key: &[u8]) -> String {
-    let attestation = get_attestation_doc(pub_key);
-    return hex::encode(attestation);
+fn extract(
+    query: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<Vec<u8>>, (StatusCode, String)> {
+    query
+        .get(key)
+        .map(|x| hex::decode(x.as_bytes()))
+        .transpose()
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Failed to decode {key}: {e:?}"),
+            )
+        })
+}
+
+async fn handle_raw(
+    State(source): State<Source>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Vec<u8>, (StatusCode, String)> {
+    let public_key = extract(&query, "public_key")?;
+    let user_data = extract(&query, "user_data")?;
+    let nonce = extract(&query, "nonce")?;
+
+    source
+        .get_attestation_doc(
+            public_key.as_deref(),
+            user_data.as_deref(),
+            nonce.as_deref(),
+        )
+        .map_err(internal_error)
+}
+
+async fn handle_hex(
+    State(source): State<Source>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<String, (StatusCode, String)> {
+    let public_key = extract(&query, "public_key")?;
+    let user_data = extract(&query, "user_data")?;
+    let nonce = extract(&query, "nonce")?;
+
+    source
+        .get_hex_attestation_doc(
+            public_key.as_deref(),
+            user_data.as_deref(),
+            nonce.as_deref(),
+        )
+        .map_err(internal_error)
+}
+
+/// Routes serving attestations with the public key, user data and nonce given by the
+/// caller as hex encoded query parameters
+///
+/// IMPORTANT: Only expose to trusted applications inside the enclave, anyone who can
+/// reach these routes can get attestations for keys and data they control.
+pub fn custom_routes(source: Source) -> Router {
+    Router::new()
+        .route("/attestation/raw", get(handle_raw))
+        .route("/attestation/hex", get(handle_hex))
+        .with_state(source)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use oyster::attestation::{AttestationDocument, AttestationPolicy, TrustAnchors};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn source() -> Source {
+        Arc::new(MockSource::new().unwrap())
+    }
+
+    async fn get(router: Router, uri: &str) -> (StatusCode, Vec<u8>) {
+        let response = router
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, body.to_vec())
+    }
+
+    fn verify(attestation: &[u8]) -> AttestationDocument {
+        AttestationPolicy::new()
+            .trust_anchors(TrustAnchors::oyster_mock())
+            .pcr(0, [0u8; 48])
+            .pcr(2, [2u8; 48])
+            .verify(attestation)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fixed_routes() {
+        let router = fixed_routes(source(), &[1; 64]);
+
+        let (status, raw) = get(router.clone(), "/attestation/raw").await;
+        assert_eq!(status, StatusCode::OK);
+        let doc = verify(&raw);
+        assert_eq!(doc.public_key.unwrap().as_slice(), [1; 64]);
+        assert!(doc.user_data.is_none());
+        assert!(doc.nonce.is_none());
+
+        // callers cannot change the contents
+        let (status, raw) = get(router.clone(), "/attestation/raw?nonce=0202").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(verify(&raw).nonce.is_none());
+
+        let (status, hex) = get(router, "/attestation/hex").await;
+        assert_eq!(status, StatusCode::OK);
+        let doc = verify(&hex::decode(hex).unwrap());
+        assert_eq!(doc.public_key.unwrap().as_slice(), [1; 64]);
+    }
+
+    #[tokio::test]
+    async fn test_custom_routes() {
+        let router = custom_routes(source());
+        let query = "public_key=0101&user_data=0202&nonce=0303";
+
+        let (status, raw) = get(router.clone(), &format!("/attestation/raw?{query}")).await;
+        assert_eq!(status, StatusCode::OK);
+        let doc = verify(&raw);
+        assert_eq!(doc.public_key.unwrap().as_slice(), [1, 1]);
+        assert_eq!(doc.user_data.unwrap().as_slice(), [2, 2]);
+        assert_eq!(doc.nonce.unwrap().as_slice(), [3, 3]);
+
+        let (status, hex) = get(router.clone(), &format!("/attestation/hex?{query}")).await;
+        assert_eq!(status, StatusCode::OK);
+        let doc = verify(&hex::decode(hex).unwrap());
+        assert_eq!(doc.public_key.unwrap().as_slice(), [1, 1]);
+        assert_eq!(doc.user_data.unwrap().as_slice(), [2, 2]);
+        assert_eq!(doc.nonce.unwrap().as_slice(), [3, 3]);
+
+        // every field is optional
+        let (status, raw) = get(router.clone(), "/attestation/raw?nonce=0303").await;
+        assert_eq!(status, StatusCode::OK);
+        let doc = verify(&raw);
+        assert!(doc.public_key.is_none());
+        assert!(doc.user_data.is_none());
+
+        let (status, _) = get(router, "/attestation/raw?nonce=zz").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_field_limits() {
+        let router = custom_routes(source());
+
+        // largest fields the nsm accepts
+        let query = format!(
+            "public_key={}&user_data={}&nonce={}",
+            "01".repeat(MAX_PUBLIC_KEY_LEN),
+            "02".repeat(MAX_USER_DATA_LEN),
+            "03".repeat(MAX_NONCE_LEN),
+        );
+        let (status, raw) = get(router.clone(), &format!("/attestation/raw?{query}")).await;
+        assert_eq!(status, StatusCode::OK);
+        let doc = verify(&raw);
+        assert_eq!(doc.public_key.unwrap().len(), MAX_PUBLIC_KEY_LEN);
+        assert_eq!(doc.user_data.unwrap().len(), MAX_USER_DATA_LEN);
+        assert_eq!(doc.nonce.unwrap().len(), MAX_NONCE_LEN);
+
+        // one byte more is rejected, like the nsm does
+        for (field, max) in [
+            ("public_key", MAX_PUBLIC_KEY_LEN),
+            ("user_data", MAX_USER_DATA_LEN),
+            ("nonce", MAX_NONCE_LEN),
+        ] {
+            let uri = format!("/attestation/hex?{field}={}", "00".repeat(max + 1));
+            let (status, body) = get(router.clone(), &uri).await;
+            assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR, "{field}");
+            let body = String::from_utf8(body).unwrap();
+            assert!(body.contains(&format!("exceeds {max}")), "{body}");
+        }
+    }
 }
@@ -1,42 +1,90 @@
 use std::error::Error;
+use std::sync::Arc;
 
-use axum::{routing::get, Router};
-use clap::Parser;
+use clap::{ArgGroup, Parser, ValueEnum};
+use oyster_attestation_server::{custom_routes, fixed_routes, AttestationSource};
+use tokio::task::JoinSet;
+
+#[cfg(not(any(feature = "nsm", feature = "mock")))]
+compile_error!("at least one of the nsm and mock features has to be enabled");
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Backend {
+    /// nitro secure module, only works inside enclaves
+    #[cfg(feature = "nsm")]
+    Nsm,
+    /// hardcoded mock certificate chain, NOT secure, for local development and tests
+    #[cfg(feature = "mock")]
+    Mock,
+}
+
+#[cfg(feature = "nsm")]
+const DEFAULT_BACKEND: &str = "nsm";
+#[cfg(not(feature = "nsm"))]
+const DEFAULT_BACKEND: &str = "mock";
 
 /// http server for handling attestation document requests
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+// at least one set of routes has to be served
+#[command(group(
+    ArgGroup::new("routes")
+        .required(true)
+        .multiple(true)
+        .args(["ip_addr", "custom_ip_addr"])
+))]
 struct Cli {
-    /// ip address of the server (e.g. 127.0.0.1:1300)
-    #[arg(short, long)]
-    ip_addr: String,
+    /// ip address of the fixed key routes (e.g. 127.0.0.1:1300)
+    #[arg(short, long, requires = "pub_key")]
+    ip_addr: Option<String>,
+
+    /// path to public key file included in attestations of the fixed key routes (e.g. /app/id.pub)
+    #[arg(short, long, requires = "ip_addr")]
+    pub_key: Option<String>,
 
-    /// path to public key file (e.g. /app/id.pub)
+    /// ip address of the customizable routes (e.g. 127.0.0.1:1350), do not expose outside the enclave
     #[arg(short, long)]
-    pub_key: String,
+    custom_ip_addr: Option<String>,
+
+    /// source of attestation documents
+    #[arg(short, long, value_enum, default_value = DEFAULT_BACKEND)]
+    backend: Backend,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
 
-    // leak in order to get a static slice
-    // okay to do since it will get cleaned up on exit
-    let pub_key = std::fs::read(cli.pub_key)?.leak::<'static>();
-    println!("pub key: {:02x?}", pub_key);
-
-    let app = Router::new()
-        .route(
-            "/attestation/raw",
-            get(|| async { oyster_attestation_server::get_attestation_doc(pub_key) }),
-        )
-        .route(
-            "/attestation/hex",
-            get(|| async { oyster_attestation_server::get_hex_attestation_doc(pub_key) }),
-        );
-    let listener = tokio::net::TcpListener::bind(&cli.ip_addr).await?;
-
-    axum::serve(listener, app).await?;
+    let source: Arc<dyn AttestationSource> = match cli.backend {
+        #[cfg(feature = "nsm")]
+        Backend::Nsm => Arc::new(oyster_attestation_server::NsmSource),
+        #[cfg(feature = "mock")]
+        Backend::Mock => Arc::new(oyster_attestation_server::MockSource::new()?),
+    };
+
+    let mut servers = JoinSet::new();
+
+    if let (Some(ip_addr), Some(pub_key)) = (cli.ip_addr, cli.pub_key) {
+        // leak in order to get a static slice
+        // okay to do since it will get cleaned up on exit
+        let pub_key = std::fs::read(pub_key)?.leak::<'static>();
+        println!("pub key: {:02x?}", pub_key);
+
+        let app = fixed_routes(source.clone(), pub_key);
+        let listener = tokio::net::TcpListener::bind(&ip_addr).await?;
+        servers.spawn(async move { axum::serve(listener, app).await });
+    }
+
+    if let Some(ip_addr) = cli.custom_ip_addr {
+        let app = custom_routes(source);
+        let listener = tokio::net::TcpListener::bind(&ip_addr).await?;
+        servers.spawn(async move { axum::serve(listener, app).await });
+    }
+
+    // servers only return on errors
+    while let Some(server) = servers.join_next().await {
+        server??;
+    }
 
     Ok(())
 }
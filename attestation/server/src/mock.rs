@@ -0,0 +1,61 @@
+use oyster::attestation::{AttestationBuilder, SigningKey};
+use p384::SecretKey;
+
+use crate::{check_lengths, AttestationSource};
+
+static ROOT_CERT: &[u8] = include_bytes!("./certs/root.crt");
+static LEAF_CERT: &[u8] = include_bytes!("./certs/leaf.crt");
+static LEAF_KEY: &[u8] = include_bytes!("./certs/leaf.key");
+
+/// Attestations signed by the hardcoded certificate chain in `certs`
+///
+/// IMPORTANT: The attestations are NOT real and NOT secure, the private keys of the
+/// chain are public. Meant for local development and tests only.
+#[derive(Debug, Clone)]
+pub struct MockSource {
+    signer: SigningKey,
+}
+
+impl MockSource {
+    /// Source signing with the leaf key of the chain
+    pub fn new() -> Result<Self, String> {
+        let signer = SecretKey::from_sec1_der(LEAF_KEY)
+            .map(SigningKey::from)
+            .map_err(|e| format!("failed to parse signer: {e:?}"))?;
+
+        Ok(Self { signer })
+    }
+}
+
+impl AttestationSource for MockSource {
+    fn get_attestation_doc(
+        &self,
+        public_key: Option<&[u8]>,
+        user_data: Option<&[u8]>,
+        nonce: Option<&[u8]>,
+    ) -> Result<Vec<u8>, String> {
+        check_lengths(public_key, user_data, nonce)?;
+
+        // pcr i is set to [i; 48]
+        let mut builder = (0..16)
+            .fold(AttestationBuilder::new(), |builder, i| {
+                builder.pcr(i, [i as u8; 48])
+            })
+            .module_id("i-0d69bec447a037a2a-enc01939aab191aadd2")
+            .certificate(LEAF_CERT)
+            .cabundle([ROOT_CERT]);
+        if let Some(public_key) = public_key {
+            builder = builder.public_key(public_key);
+        }
+        if let Some(user_data) = user_data {
+            builder = builder.user_data(user_data);
+        }
+        if let Some(nonce) = nonce {
+            builder = builder.nonce(nonce);
+        }
+
+        builder
+            .sign(&self.signer)
+            .map_err(|e| format!("failed to sign attestation: {e:?}"))
+    }
+}
@@ -23,7 +23,9 @@
     else pkgs.stdenv.cc;
 in rec {
   uncompressed = naersk'.buildPackage {
-    src = ./.;
+    # builds against the sdk in the monorepo
+    src = ../..;
+    root = ./.;
     CARGO_BUILD_TARGET = target;
     TARGET_CC = "${cc}/bin/${cc.targetPrefix}cc";
     nativeBuildInputs = [cc pkgs.perl];
@@ -9,6 +9,8 @@ use thiserror::Error;
 pub struct AppState {
     pub secp256k1_secret: secp256k1::SecretKey,
     pub secp256k1_public: [u8; 64],
+    // certificate validity is checked against it
+    pub clock: Box<dyn oyster::Clock>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -129,10 +131,12 @@ fn verify(
     attestation: Vec<u8>,
     secret: &secp256k1::SecretKey,
     public: &[u8; 64],
+    clock: &dyn oyster::Clock,
 ) -> actix_web::Result<impl Responder, UserError> {
     let parsed = oyster::decode_attestation(attestation.clone())
         .map_err(UserError::AttestationVerification)?;
-    oyster::verify_with_timestamp(attestation, parsed.pcrs, parsed.timestamp)
+    // any age is fine, the timestamp is part of the signed response
+    oyster::verify_with_clock(attestation, parsed.pcrs, usize::MAX, clock)
         .map_err(UserError::AttestationVerification)?;
 
     let requester_secp256k1_public = parsed.public_key.as_slice();
@@ -179,6 +183,7 @@ async fn verify_raw(
         req.to_vec(),
         &state.secp256k1_secret,
         &state.secp256k1_public,
+        state.clock.as_ref(),
     )
 }
 
@@ -193,10 +198,10 @@ async fn verify_hex(
         attestation,
         &state.secp256k1_secret,
         &state.secp256k1_public,
+        state.clock.as_ref(),
     )
 }
 
-// Update the sample attestations in the 'test/' directory before running tests for fresh timestamp
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -215,6 +220,8 @@ mod tests {
                 .app_data(web::Data::new(AppState {
                     secp256k1_secret,
                     secp256k1_public,
+                    // sample attestation is verified as of its own timestamp
+                    clock: Box::new(oyster::FixedClock(1723012689640)),
                 }))
                 .service(verify_raw),
         )
@@ -256,6 +263,8 @@ mod tests {
                 .app_data(web::Data::new(AppState {
                     secp256k1_secret,
                     secp256k1_public,
+                    // sample attestation is verified as of its own timestamp
+                    clock: Box::new(oyster::FixedClock(1723012992231)),
                 }))
                 .service(verify_hex),
         )
@@ -55,6 +55,7 @@ async fn main() -> Result<()> {
             .app_data(web::Data::new(handler::AppState {
                 secp256k1_secret,
                 secp256k1_public,
+                clock: Box::new(oyster::SystemClock),
             }))
             .service(handler::verify_raw)
             .service(handler::verify_hex)
@@ -0,0 +1,271 @@
+#ifndef OYSTER_SCALLOP_H
+#define OYSTER_SCALLOP_H
+
+/* Generated by cbindgen from src/lib.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Success
+ */
+#define SCALLOP_OK 0
+
+/**
+ * Null pointer, missing callback or invalid length
+ */
+#define SCALLOP_ERR_INVALID_ARGUMENT -1
+
+/**
+ * Reading from or writing to the connection failed, including socket timeouts
+ */
+#define SCALLOP_ERR_IO -2
+
+/**
+ * Remote violated the protocol or could not be authenticated
+ */
+#define SCALLOP_ERR_PROTOCOL -3
+
+/**
+ * Bug in the library, the connection should be freed
+ */
+#define SCALLOP_ERR_PANIC -4
+
+/**
+ * Size of the buffer attestations are written to by authers
+ */
+#define SCALLOP_MAX_AUTH 60000
+
+/**
+ * Size of the pcrs passed to auth stores, pcr0 | pcr1 | pcr2
+ */
+#define SCALLOP_PCRS_LEN 144
+
+/**
+ * Scallop connection, created by the handshake functions and freed with `scallop_free`
+ */
+typedef struct ScallopSession ScallopSession;
+
+/**
+ * Auth store callbacks, deciding which remotes need to send attestations and
+ * verifying them
+ */
+typedef struct {
+  /**
+   * Passed to every callback
+   */
+  void *ctx;
+  /**
+   * Whether the 32 byte static key `key` is already trusted, an attestation is
+   * requested otherwise
+   */
+  bool (*contains)(void *ctx, const uint8_t *key);
+  /**
+   * Verify `attestation` for the static key `key`, writes the pcrs of the remote to
+   * `pcrs` and returns true if it is valid
+   */
+  bool (*verify)(void *ctx,
+                 const uint8_t *attestation,
+                 size_t attestation_len,
+                 const uint8_t *key,
+                 uint8_t *pcrs);
+  /**
+   * Remember `key` as trusted with `pcrs`, called after successful verifications
+   */
+  void (*set)(void *ctx, const uint8_t *key, const uint8_t *pcrs);
+} ScallopAuthStoreCallbacks;
+
+/**
+ * Auther callbacks, producing attestations when the remote asks for one
+ */
+typedef struct {
+  /**
+   * Passed to every callback
+   */
+  void *ctx;
+  /**
+   * Write an attestation of the local static key to `buf`, which has room for
+   * `SCALLOP_MAX_AUTH` bytes, returns its length
+   *
+   * Lengths beyond `len` fail the handshake with `SCALLOP_ERR_INVALID_ARGUMENT`.
+   */
+  size_t (*new_auth)(void *ctx, uint8_t *buf, size_t len);
+} ScallopAutherCallbacks;
+
+/**
+ * Blocking I/O callbacks of a connection, errors can be returned to time out
+ */
+typedef struct {
+  /**
+   * Passed to every callback
+   */
+  void *ctx;
+  /**
+   * Read up to `len` bytes into `buf`, returns the number of bytes read, 0 at the end
+   * of the stream or a negative value on errors
+   */
+  ptrdiff_t (*read)(void *ctx, uint8_t *buf, size_t len);
+  /**
+   * Write up to `len` bytes from `buf`, returns the number of bytes written or a
+   * negative value on errors
+   */
+  ptrdiff_t (*write)(void *ctx, const uint8_t *buf, size_t len);
+} ScallopIoCallbacks;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Client handshake over the connected socket `fd`, which stays owned by the caller
+ * and has to outlive the session
+ *
+ * `auth_store` and `auther` can be null, the server is not authenticated and
+ * attestation requests fail the handshake respectively. Reads and writes block until
+ * the timeouts set with `SO_RCVTIMEO` and `SO_SNDTIMEO`, if any.
+ *
+ * # Safety
+ *
+ * `secret` points to 32 bytes, `session` is writable, `auth_store` and `auther` are
+ * null or valid, their callbacks can be called with their `ctx` until this returns.
+ */
+int32_t scallop_client_fd(int32_t fd,
+                          const uint8_t *secret,
+                          const ScallopAuthStoreCallbacks *auth_store,
+                          const ScallopAutherCallbacks *auther,
+                          ScallopSession **session);
+
+/**
+ * Server handshake over the connected socket `fd`, see `scallop_client_fd`
+ *
+ * # Safety
+ *
+ * Same as `scallop_client_fd`.
+ */
+int32_t scallop_server_fd(int32_t fd,
+                          const uint8_t *secret,
+                          const ScallopAuthStoreCallbacks *auth_store,
+                          const ScallopAutherCallbacks *auther,
+                          ScallopSession **session);
+
+/**
+ * Client handshake over I/O callbacks, which are copied and used until the session is
+ * freed, see `scallop_client_fd`
+ *
+ * # Safety
+ *
+ * Same as `scallop_client_fd`, and the I/O callbacks can be called with their `ctx`
+ * until the session is freed.
+ */
+int32_t scallop_client_io(const ScallopIoCallbacks *io,
+                          const uint8_t *secret,
+                          const ScallopAuthStoreCallbacks *auth_store,
+                          const ScallopAutherCallbacks *auther,
+                          ScallopSession **session);
+
+/**
+ * Server handshake over I/O callbacks, see `scallop_client_io`
+ *
+ * # Safety
+ *
+ * Same as `scallop_client_io`.
+ */
+int32_t scallop_server_io(const ScallopIoCallbacks *io,
+                          const uint8_t *secret,
+                          const ScallopAuthStoreCallbacks *auth_store,
+                          const ScallopAutherCallbacks *auther,
+                          ScallopSession **session);
+
+/**
+ * Read up to `len` bytes into `buf`, returns the number of bytes read, 0 once the
+ * remote has closed the connection, or a negative status
+ *
+ * # Safety
+ *
+ * `session` is a live session and `buf` is writable for `len` bytes.
+ */
+ptrdiff_t scallop_read(ScallopSession *session, uint8_t *buf, size_t len);
+
+/**
+ * Write and flush all `len` bytes of `buf`, returns `len` or a negative status
+ *
+ * # Safety
+ *
+ * `session` is a live session and `buf` is readable for `len` bytes.
+ */
+ptrdiff_t scallop_write(ScallopSession *session, const uint8_t *buf, size_t len);
+
+/**
+ * Tell the remote nothing more will be written, reads keep working
+ *
+ * # Safety
+ *
+ * `session` is a live session.
+ */
+int32_t scallop_shutdown(ScallopSession *session);
+
+/**
+ * Write the 32 byte static key of the remote to `key`
+ *
+ * # Safety
+ *
+ * `session` is a live session and `key` is writable for 32 bytes.
+ */
+int32_t scallop_remote_static(const ScallopSession *session, uint8_t *key);
+
+/**
+ * Copy up to `len` bytes of the channel binding of the session to `buf`, returns its
+ * full length or a negative status
+ *
+ * # Safety
+ *
+ * `session` is a live session and `buf` is writable for `len` bytes.
+ */
+ptrdiff_t scallop_channel_binding(const ScallopSession *session, uint8_t *buf, size_t len);
+
+/**
+ * Fill `out` with `out_len` bytes of keying material exported from the session for
+ * `label` and `context`
+ *
+ * # Safety
+ *
+ * `session` is a live session, `label`, `context` and `out` are valid for their
+ * lengths.
+ */
+int32_t scallop_export_keying_material(const ScallopSession *session,
+                                       const uint8_t *label,
+                                       size_t label_len,
+                                       const uint8_t *context,
+                                       size_t context_len,
+                                       uint8_t *out,
+                                       size_t out_len);
+
+/**
+ * Free the session without telling the remote, call `scallop_shutdown` before to close
+ * it cleanly, null is ignored
+ *
+ * # Safety
+ *
+ * `session` is null or a live session, which is no longer live afterwards.
+ */
+void scallop_free(ScallopSession *session);
+
+/**
+ * Copy the description of the last failure on this thread to `buf` as a nul
+ * terminated string, truncated to `len` bytes, returns the length of the full
+ * description without the nul
+ *
+ * # Safety
+ *
+ * `buf` is null or writable for `len` bytes.
+ */
+size_t scallop_last_error(char *buf, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* OYSTER_SCALLOP_H */
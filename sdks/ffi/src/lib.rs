@@ -0,0 +1,701 @@
+//! C bindings for Scallop connections
+//!
+//! Every call blocks the calling thread until it is done. Connections either run over a
+//! file descriptor owned by the caller, e.g. a connected TCP or vsock socket, or over
+//! read and write callbacks for anything else. Auth stores and authers are callbacks
+//! as well and are only called from within the handshake, on the calling thread.
+//!
+//! There are no timeouts, not even for the handshake. Set `SO_RCVTIMEO` and
+//! `SO_SNDTIMEO` on sockets, or return errors from the I/O callbacks after a while, so
+//! that an unresponsive remote does not block the thread forever.
+//!
+//! Functions return `SCALLOP_OK` or one of the negative `SCALLOP_ERR_*` statuses, a
+//! description of the last failure on the thread is available from
+//! `scallop_last_error`. The header is generated with
+//! `cbindgen --config cbindgen.toml --output include/oyster_scallop.h`.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void};
+use std::io::{self, Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use oyster::scallop::{
+    new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b,
+    new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b, ScallopAuthStore, ScallopAuther,
+    ScallopError, ScallopStream,
+};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::runtime::Runtime;
+
+/// Success
+pub const SCALLOP_OK: i32 = 0;
+/// Null pointer, missing callback or invalid length
+pub const SCALLOP_ERR_INVALID_ARGUMENT: i32 = -1;
+/// Reading from or writing to the connection failed, including socket timeouts
+pub const SCALLOP_ERR_IO: i32 = -2;
+/// Remote violated the protocol or could not be authenticated
+pub const SCALLOP_ERR_PROTOCOL: i32 = -3;
+/// Bug in the library, the connection should be freed
+pub const SCALLOP_ERR_PANIC: i32 = -4;
+
+/// Size of the buffer attestations are written to by authers
+pub const SCALLOP_MAX_AUTH: usize = 60000;
+/// Size of the pcrs passed to auth stores, pcr0 | pcr1 | pcr2
+pub const SCALLOP_PCRS_LEN: usize = 144;
+
+type Pcrs = ([u8; 48], [u8; 48], [u8; 48]);
+
+thread_local! {
+    static LAST_ERROR: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn fail(status: i32, msg: impl Into<String>) -> i32 {
+    LAST_ERROR.with(|x| *x.borrow_mut() = msg.into());
+    status
+}
+
+fn fail_with(err: ScallopError) -> i32 {
+    let status = match err {
+        ScallopError::TransportError(_) | ScallopError::HandshakeTimeout => SCALLOP_ERR_IO,
+        ScallopError::InvalidExporterParameters => SCALLOP_ERR_INVALID_ARGUMENT,
+        _ => SCALLOP_ERR_PROTOCOL,
+    };
+
+    // messages of the sources are more useful than the generic ones
+    let mut msg = err.to_string();
+    let mut source = std::error::Error::source(&err);
+    while let Some(err) = source {
+        msg = format!("{msg}: {err}");
+        source = err.source();
+    }
+
+    fail(status, msg)
+}
+
+// statuses are returned as i32, or as isize alongside lengths
+trait Status {
+    fn from_status(status: i32) -> Self;
+}
+
+impl Status for i32 {
+    fn from_status(status: i32) -> Self {
+        status
+    }
+}
+
+impl Status for isize {
+    fn from_status(status: i32) -> Self {
+        status as isize
+    }
+}
+
+// never unwind into C
+fn guard<T: Status>(f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| T::from_status(fail(SCALLOP_ERR_PANIC, "panic")))
+}
+
+/// Blocking I/O callbacks of a connection, errors can be returned to time out
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ScallopIoCallbacks {
+    /// Passed to every callback
+    pub ctx: *mut c_void,
+    /// Read up to `len` bytes into `buf`, returns the number of bytes read, 0 at the end
+    /// of the stream or a negative value on errors
+    pub read: Option<unsafe extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize>,
+    /// Write up to `len` bytes from `buf`, returns the number of bytes written or a
+    /// negative value on errors
+    pub write: Option<unsafe extern "C" fn(ctx: *mut c_void, buf: *const u8, len: usize) -> isize>,
+}
+
+/// Auth store callbacks, deciding which remotes need to send attestations and
+/// verifying them
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ScallopAuthStoreCallbacks {
+    /// Passed to every callback
+    pub ctx: *mut c_void,
+    /// Whether the 32 byte static key `key` is already trusted, an attestation is
+    /// requested otherwise
+    pub contains: Option<unsafe extern "C" fn(ctx: *mut c_void, key: *const u8) -> bool>,
+    /// Verify `attestation` for the static key `key`, writes the pcrs of the remote to
+    /// `pcrs` and returns true if it is valid
+    pub verify: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            attestation: *const u8,
+            attestation_len: usize,
+            key: *const u8,
+            pcrs: *mut u8,
+        ) -> bool,
+    >,
+    /// Remember `key` as trusted with `pcrs`, called after successful verifications
+    pub set: Option<unsafe extern "C" fn(ctx: *mut c_void, key: *const u8, pcrs: *const u8)>,
+}
+
+/// Auther callbacks, producing attestations when the remote asks for one
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ScallopAutherCallbacks {
+    /// Passed to every callback
+    pub ctx: *mut c_void,
+    /// Write an attestation of the local static key to `buf`, which has room for
+    /// `SCALLOP_MAX_AUTH` bytes, returns its length
+    ///
+    /// Lengths beyond `len` fail the handshake with `SCALLOP_ERR_INVALID_ARGUMENT`.
+    pub new_auth: Option<unsafe extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> usize>,
+}
+
+struct AuthStore {
+    callbacks: ScallopAuthStoreCallbacks,
+    // keeps the pcrs of `set` around for `get`
+    pcrs: HashMap<[u8; 32], Pcrs>,
+}
+
+impl ScallopAuthStore for AuthStore {
+    fn contains(&self, key: &[u8; 32]) -> bool {
+        // safe to unwrap since callbacks are checked before the handshake
+        unsafe { self.callbacks.contains.unwrap()(self.callbacks.ctx, key.as_ptr()) }
+    }
+
+    fn get(&self, key: &[u8; 32]) -> Option<&Pcrs> {
+        self.pcrs.get(key)
+    }
+
+    fn set(&mut self, key: [u8; 32], pcrs: Pcrs) {
+        let flat = [pcrs.0, pcrs.1, pcrs.2].concat();
+        unsafe { self.callbacks.set.unwrap()(self.callbacks.ctx, key.as_ptr(), flat.as_ptr()) };
+        self.pcrs.insert(key, pcrs);
+    }
+
+    fn verify(&mut self, attestation: &[u8], key: &[u8; 32]) -> Option<Pcrs> {
+        let mut pcrs = [0u8; SCALLOP_PCRS_LEN];
+        let valid = unsafe {
+            self.callbacks.verify.unwrap()(
+                self.callbacks.ctx,
+                attestation.as_ptr(),
+                attestation.len(),
+                key.as_ptr(),
+                pcrs.as_mut_ptr(),
+            )
+        };
+
+        valid.then(|| {
+            (
+                pcrs[0..48].try_into().unwrap(),
+                pcrs[48..96].try_into().unwrap(),
+                pcrs[96..144].try_into().unwrap(),
+            )
+        })
+    }
+}
+
+struct Auther<'a> {
+    callbacks: ScallopAutherCallbacks,
+    // length returned by the callback if it exceeds the buffer
+    invalid_len: &'a Cell<Option<usize>>,
+}
+
+// only called on the thread running the handshake
+unsafe impl Send for Auther<'_> {}
+
+impl ScallopAuther for Auther<'_> {
+    async fn new_auth(&mut self) -> Box<[u8]> {
+        let mut buf = vec![0u8; SCALLOP_MAX_AUTH];
+        let len = unsafe {
+            self.callbacks.new_auth.unwrap()(self.callbacks.ctx, buf.as_mut_ptr(), buf.len())
+        };
+        if len > buf.len() {
+            // attestations past the limit fail the handshake, the status is fixed up by
+            // the caller
+            self.invalid_len.set(Some(len));
+            buf.push(0);
+            return buf.into();
+        }
+        buf.truncate(len);
+        buf.into()
+    }
+}
+
+enum Io {
+    // borrowed, closing is up to the caller
+    #[cfg(unix)]
+    Fd(std::mem::ManuallyDrop<std::fs::File>),
+    Callbacks(ScallopIoCallbacks),
+}
+
+impl Io {
+    fn read_blocking(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Io::Fd(file) => file.read(buf),
+            Io::Callbacks(callbacks) => {
+                let len =
+                    unsafe { callbacks.read.unwrap()(callbacks.ctx, buf.as_mut_ptr(), buf.len()) };
+                usize::try_from(len).map_err(|_| io::Error::other("read callback failed"))
+            }
+        }
+    }
+
+    fn write_blocking(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(unix)]
+            Io::Fd(file) => file.write(buf),
+            Io::Callbacks(callbacks) => {
+                let len =
+                    unsafe { callbacks.write.unwrap()(callbacks.ctx, buf.as_ptr(), buf.len()) };
+                usize::try_from(len).map_err(|_| io::Error::other("write callback failed"))
+            }
+        }
+    }
+}
+
+// blocking I/O, every poll is ready so the handshake timeout of the library never fires,
+// timeouts are up to the socket options or callbacks of the caller
+impl AsyncRead for Io {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let io = self.get_mut();
+        loop {
+            match io.read_blocking(buf.initialize_unfilled()) {
+                Ok(len) => {
+                    buf.advance(len);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Io {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let io = self.get_mut();
+        loop {
+            match io.write_blocking(buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // closing is up to the caller
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Scallop connection, created by the handshake functions and freed with `scallop_free`
+pub struct ScallopSession {
+    runtime: Runtime,
+    stream: ScallopStream<Io>,
+}
+
+unsafe fn handshake(
+    io: Io,
+    secret: *const u8,
+    auth_store: *const ScallopAuthStoreCallbacks,
+    auther: *const ScallopAutherCallbacks,
+    session: *mut *mut ScallopSession,
+    client: bool,
+) -> i32 {
+    if secret.is_null() || session.is_null() {
+        return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null secret or session");
+    }
+    let secret: [u8; 32] = std::ptr::read(secret as *const [u8; 32]);
+
+    let auth_store = match auth_store.as_ref() {
+        None => None,
+        Some(x) if x.contains.is_none() || x.verify.is_none() || x.set.is_none() => {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "missing auth store callback");
+        }
+        Some(x) => Some(AuthStore {
+            callbacks: *x,
+            pcrs: HashMap::new(),
+        }),
+    };
+    let invalid_len = Cell::new(None);
+    let auther = match auther.as_ref() {
+        None => None,
+        Some(x) if x.new_auth.is_none() => {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "missing auther callback");
+        }
+        Some(x) => Some(Auther {
+            callbacks: *x,
+            invalid_len: &invalid_len,
+        }),
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => return fail(SCALLOP_ERR_IO, e.to_string()),
+    };
+
+    let stream = if client {
+        runtime.block_on(new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+            io, &secret, auth_store, auther,
+        ))
+    } else {
+        runtime.block_on(new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+            io, &secret, auth_store, auther,
+        ))
+    };
+
+    match stream {
+        Ok(stream) => {
+            *session = Box::into_raw(Box::new(ScallopSession { runtime, stream }));
+            SCALLOP_OK
+        }
+        Err(e) => match invalid_len.get() {
+            Some(len) => fail(
+                SCALLOP_ERR_INVALID_ARGUMENT,
+                format!(
+                    "auther returned {len} bytes, more than the {SCALLOP_MAX_AUTH} byte buffer"
+                ),
+            ),
+            None => fail_with(e),
+        },
+    }
+}
+
+unsafe fn callbacks_io(io: *const ScallopIoCallbacks) -> Option<Io> {
+    io.as_ref()
+        .filter(|x| x.read.is_some() && x.write.is_some())
+        .map(|x| Io::Callbacks(*x))
+}
+
+#[cfg(unix)]
+fn fd_io(fd: i32) -> Option<Io> {
+    use std::os::fd::FromRawFd;
+
+    (fd >= 0).then(|| {
+        Io::Fd(std::mem::ManuallyDrop::new(unsafe {
+            std::fs::File::from_raw_fd(fd)
+        }))
+    })
+}
+
+/// Client handshake over the connected socket `fd`, which stays owned by the caller
+/// and has to outlive the session
+///
+/// `auth_store` and `auther` can be null, the server is not authenticated and
+/// attestation requests fail the handshake respectively. Reads and writes block until
+/// the timeouts set with `SO_RCVTIMEO` and `SO_SNDTIMEO`, if any.
+///
+/// # Safety
+///
+/// `secret` points to 32 bytes, `session` is writable, `auth_store` and `auther` are
+/// null or valid, their callbacks can be called with their `ctx` until this returns.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn scallop_client_fd(
+    fd: i32,
+    secret: *const u8,
+    auth_store: *const ScallopAuthStoreCallbacks,
+    auther: *const ScallopAutherCallbacks,
+    session: *mut *mut ScallopSession,
+) -> i32 {
+    guard(|| match fd_io(fd) {
+        Some(io) => handshake(io, secret, auth_store, auther, session, true),
+        None => fail(SCALLOP_ERR_INVALID_ARGUMENT, "invalid fd"),
+    })
+}
+
+/// Server handshake over the connected socket `fd`, see `scallop_client_fd`
+///
+/// # Safety
+///
+/// Same as `scallop_client_fd`.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn scallop_server_fd(
+    fd: i32,
+    secret: *const u8,
+    auth_store: *const ScallopAuthStoreCallbacks,
+    auther: *const ScallopAutherCallbacks,
+    session: *mut *mut ScallopSession,
+) -> i32 {
+    guard(|| match fd_io(fd) {
+        Some(io) => handshake(io, secret, auth_store, auther, session, false),
+        None => fail(SCALLOP_ERR_INVALID_ARGUMENT, "invalid fd"),
+    })
+}
+
+/// Client handshake over I/O callbacks, which are copied and used until the session is
+/// freed, see `scallop_client_fd`
+///
+/// # Safety
+///
+/// Same as `scallop_client_fd`, and the I/O callbacks can be called with their `ctx`
+/// until the session is freed.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_client_io(
+    io: *const ScallopIoCallbacks,
+    secret: *const u8,
+    auth_store: *const ScallopAuthStoreCallbacks,
+    auther: *const ScallopAutherCallbacks,
+    session: *mut *mut ScallopSession,
+) -> i32 {
+    guard(|| match callbacks_io(io) {
+        Some(io) => handshake(io, secret, auth_store, auther, session, true),
+        None => fail(SCALLOP_ERR_INVALID_ARGUMENT, "missing io callback"),
+    })
+}
+
+/// Server handshake over I/O callbacks, see `scallop_client_io`
+///
+/// # Safety
+///
+/// Same as `scallop_client_io`.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_server_io(
+    io: *const ScallopIoCallbacks,
+    secret: *const u8,
+    auth_store: *const ScallopAuthStoreCallbacks,
+    auther: *const ScallopAutherCallbacks,
+    session: *mut *mut ScallopSession,
+) -> i32 {
+    guard(|| match callbacks_io(io) {
+        Some(io) => handshake(io, secret, auth_store, auther, session, false),
+        None => fail(SCALLOP_ERR_INVALID_ARGUMENT, "missing io callback"),
+    })
+}
+
+/// Read up to `len` bytes into `buf`, returns the number of bytes read, 0 once the
+/// remote has closed the connection, or a negative status
+///
+/// # Safety
+///
+/// `session` is a live session and `buf` is writable for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_read(
+    session: *mut ScallopSession,
+    buf: *mut u8,
+    len: usize,
+) -> isize {
+    guard(|| {
+        let Some(session) = session.as_mut() else {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null session") as isize;
+        };
+        if buf.is_null() && len != 0 {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null buffer") as isize;
+        }
+        let buf = if len == 0 {
+            &mut []
+        } else {
+            std::slice::from_raw_parts_mut(buf, len)
+        };
+
+        match session.runtime.block_on(session.stream.read(buf)) {
+            Ok(len) => len as isize,
+            Err(e) => fail(SCALLOP_ERR_IO, e.to_string()) as isize,
+        }
+    })
+}
+
+/// Write and flush all `len` bytes of `buf`, returns `len` or a negative status
+///
+/// # Safety
+///
+/// `session` is a live session and `buf` is readable for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_write(
+    session: *mut ScallopSession,
+    buf: *const u8,
+    len: usize,
+) -> isize {
+    guard(|| {
+        let Some(session) = session.as_mut() else {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null session") as isize;
+        };
+        if buf.is_null() && len != 0 {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null buffer") as isize;
+        }
+        let buf = if len == 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(buf, len)
+        };
+
+        let stream = &mut session.stream;
+        match session.runtime.block_on(async {
+            stream.write_all(buf).await?;
+            stream.flush().await
+        }) {
+            Ok(()) => len as isize,
+            Err(e) => fail(SCALLOP_ERR_IO, e.to_string()) as isize,
+        }
+    })
+}
+
+/// Tell the remote nothing more will be written, reads keep working
+///
+/// # Safety
+///
+/// `session` is a live session.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_shutdown(session: *mut ScallopSession) -> i32 {
+    guard(|| {
+        let Some(session) = session.as_mut() else {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null session");
+        };
+
+        match session.runtime.block_on(session.stream.shutdown()) {
+            Ok(()) => SCALLOP_OK,
+            Err(e) => fail(SCALLOP_ERR_IO, e.to_string()),
+        }
+    })
+}
+
+/// Write the 32 byte static key of the remote to `key`
+///
+/// # Safety
+///
+/// `session` is a live session and `key` is writable for 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_remote_static(
+    session: *const ScallopSession,
+    key: *mut u8,
+) -> i32 {
+    guard(|| {
+        let (Some(session), false) = (session.as_ref(), key.is_null()) else {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null session or key");
+        };
+        // safe to unwrap since handshakes always know the remote key
+        let remote = session.stream.get_remote_static().unwrap();
+        std::ptr::copy_nonoverlapping(remote.as_ptr(), key, remote.len());
+
+        SCALLOP_OK
+    })
+}
+
+/// Copy up to `len` bytes of the channel binding of the session to `buf`, returns its
+/// full length or a negative status
+///
+/// # Safety
+///
+/// `session` is a live session and `buf` is writable for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_channel_binding(
+    session: *const ScallopSession,
+    buf: *mut u8,
+    len: usize,
+) -> isize {
+    guard(|| {
+        let Some(session) = session.as_ref() else {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null session") as isize;
+        };
+        if buf.is_null() && len != 0 {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null buffer") as isize;
+        }
+
+        let binding = session.stream.channel_binding();
+        std::ptr::copy_nonoverlapping(binding.as_ptr(), buf, binding.len().min(len));
+
+        binding.len() as isize
+    })
+}
+
+/// Fill `out` with `out_len` bytes of keying material exported from the session for
+/// `label` and `context`
+///
+/// # Safety
+///
+/// `session` is a live session, `label`, `context` and `out` are valid for their
+/// lengths.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_export_keying_material(
+    session: *const ScallopSession,
+    label: *const u8,
+    label_len: usize,
+    context: *const u8,
+    context_len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> i32 {
+    guard(|| {
+        let Some(session) = session.as_ref() else {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null session");
+        };
+        if (label.is_null() && label_len != 0)
+            || (context.is_null() && context_len != 0)
+            || (out.is_null() && out_len != 0)
+        {
+            return fail(SCALLOP_ERR_INVALID_ARGUMENT, "null buffer");
+        }
+
+        let slice = |ptr: *const u8, len: usize| {
+            if len == 0 {
+                &[][..]
+            } else {
+                std::slice::from_raw_parts(ptr, len)
+            }
+        };
+        let out = if out_len == 0 {
+            &mut []
+        } else {
+            std::slice::from_raw_parts_mut(out, out_len)
+        };
+
+        match session.stream.export_keying_material(
+            slice(label, label_len),
+            slice(context, context_len),
+            out,
+        ) {
+            Ok(()) => SCALLOP_OK,
+            Err(e) => fail_with(e),
+        }
+    })
+}
+
+/// Free the session without telling the remote, call `scallop_shutdown` before to close
+/// it cleanly, null is ignored
+///
+/// # Safety
+///
+/// `session` is null or a live session, which is no longer live afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_free(session: *mut ScallopSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Copy the description of the last failure on this thread to `buf` as a nul
+/// terminated string, truncated to `len` bytes, returns the length of the full
+/// description without the nul
+///
+/// # Safety
+///
+/// `buf` is null or writable for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn scallop_last_error(buf: *mut c_char, len: usize) -> usize {
+    LAST_ERROR.with(|x| {
+        let msg = x.borrow();
+        if !buf.is_null() && len != 0 {
+            let copied = msg.len().min(len - 1);
+            std::ptr::copy_nonoverlapping(msg.as_ptr() as *const c_char, buf, copied);
+            *buf.add(copied) = 0;
+        }
+        msg.len()
+    })
+}
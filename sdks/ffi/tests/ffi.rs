@@ -0,0 +1,379 @@
+// drives the C ABI the way C callers would, through raw pointers and callbacks
+
+use std::ffi::{c_char, c_void, CStr};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::ptr::{null, null_mut};
+use std::thread;
+use std::time::Duration;
+
+use oyster_scallop::*;
+
+fn keypair() -> ([u8; 32], [u8; 32]) {
+    let keys = snow::Builder::new("Noise_IX_25519_ChaChaPoly_BLAKE2b".parse().unwrap())
+        .generate_keypair()
+        .unwrap();
+    (
+        keys.private.try_into().unwrap(),
+        keys.public.try_into().unwrap(),
+    )
+}
+
+// attestations are "attestation" followed by the static key, with pcrs of all 7s
+const PREFIX: &[u8] = b"attestation";
+
+#[derive(Default)]
+struct Store {
+    trusted: Vec<[u8; 32]>,
+    // accept attestations at all
+    accept: bool,
+}
+
+unsafe extern "C" fn contains(ctx: *mut c_void, key: *const u8) -> bool {
+    let store = &*(ctx as *const Store);
+    store.trusted.contains(&*(key as *const [u8; 32]))
+}
+
+unsafe extern "C" fn verify(
+    ctx: *mut c_void,
+    attestation: *const u8,
+    attestation_len: usize,
+    key: *const u8,
+    pcrs: *mut u8,
+) -> bool {
+    let store = &*(ctx as *const Store);
+    let attestation = std::slice::from_raw_parts(attestation, attestation_len);
+    let expected = [PREFIX, &*(key as *const [u8; 32])].concat();
+    if !store.accept || attestation != expected {
+        return false;
+    }
+    std::ptr::write_bytes(pcrs, 7, SCALLOP_PCRS_LEN);
+    true
+}
+
+unsafe extern "C" fn set(ctx: *mut c_void, key: *const u8, pcrs: *const u8) {
+    let store = &mut *(ctx as *mut Store);
+    assert_eq!(*pcrs.add(SCALLOP_PCRS_LEN - 1), 7);
+    store.trusted.push(*(key as *const [u8; 32]));
+}
+
+unsafe extern "C" fn new_auth(ctx: *mut c_void, buf: *mut u8, len: usize) -> usize {
+    let attestation = [PREFIX, &*(ctx as *const [u8; 32])].concat();
+    assert!(attestation.len() <= len);
+    std::ptr::copy_nonoverlapping(attestation.as_ptr(), buf, attestation.len());
+    attestation.len()
+}
+
+unsafe extern "C" fn io_read(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize {
+    let stream = &mut *(ctx as *mut UnixStream);
+    match stream.read(std::slice::from_raw_parts_mut(buf, len)) {
+        Ok(len) => len as isize,
+        Err(_) => -1,
+    }
+}
+
+unsafe extern "C" fn io_write(ctx: *mut c_void, buf: *const u8, len: usize) -> isize {
+    let stream = &mut *(ctx as *mut UnixStream);
+    match stream.write(std::slice::from_raw_parts(buf, len)) {
+        Ok(len) => len as isize,
+        Err(_) => -1,
+    }
+}
+
+fn store_callbacks(store: &mut Store) -> ScallopAuthStoreCallbacks {
+    ScallopAuthStoreCallbacks {
+        ctx: store as *mut Store as *mut c_void,
+        contains: Some(contains),
+        verify: Some(verify),
+        set: Some(set),
+    }
+}
+
+fn auther_callbacks(public: &[u8; 32]) -> ScallopAutherCallbacks {
+    ScallopAutherCallbacks {
+        ctx: public as *const [u8; 32] as *mut c_void,
+        new_auth: Some(new_auth),
+    }
+}
+
+fn last_error() -> String {
+    let mut buf = [0 as c_char; 256];
+    unsafe { scallop_last_error(buf.as_mut_ptr(), buf.len()) };
+    unsafe { CStr::from_ptr(buf.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+type Session = Result<*mut ScallopSession, i32>;
+
+// client over the fd, server over callbacks, both authenticating the other
+fn connect(
+    client_store: &mut Store,
+    server_store: &mut Store,
+) -> (Session, Session, [u8; 32], [u8; 32]) {
+    let (client_secret, client_public) = keypair();
+    let (server_secret, server_public) = keypair();
+    let (client_stream, server_stream) = UnixStream::pair().unwrap();
+    // the callbacks refer to the stream for as long as the session lives
+    let server_stream = Box::leak(Box::new(server_stream));
+
+    let server_store = store_callbacks(server_store);
+
+    let (client, server) = thread::scope(|s| {
+        // raw pointers are not Send, so the client callbacks are built on its thread
+        let client = s.spawn(move || {
+            let client_store = store_callbacks(client_store);
+            let mut session = null_mut();
+            let status = unsafe {
+                scallop_client_fd(
+                    client_stream.as_raw_fd(),
+                    client_secret.as_ptr(),
+                    &client_store,
+                    &auther_callbacks(&client_public),
+                    &mut session,
+                )
+            };
+            // keep the fd open for the session, close it on failures to unblock the server
+            if status == SCALLOP_OK {
+                std::mem::forget(client_stream);
+            }
+            (status == SCALLOP_OK)
+                .then_some(session as usize)
+                .ok_or(status)
+        });
+
+        let io = ScallopIoCallbacks {
+            ctx: server_stream as *mut UnixStream as *mut c_void,
+            read: Some(io_read),
+            write: Some(io_write),
+        };
+        let mut session = null_mut();
+        let status = unsafe {
+            scallop_server_io(
+                &io,
+                server_secret.as_ptr(),
+                &server_store,
+                &auther_callbacks(&server_public),
+                &mut session,
+            )
+        };
+        let server = (status == SCALLOP_OK).then_some(session).ok_or(status);
+
+        (client.join().unwrap().map(|x| x as *mut _), server)
+    });
+
+    (client, server, client_public, server_public)
+}
+
+#[test]
+fn test_ffi() {
+    let mut client_store = Store {
+        accept: true,
+        ..Default::default()
+    };
+    let mut server_store = Store {
+        accept: true,
+        ..Default::default()
+    };
+    let (client, server, client_public, server_public) =
+        connect(&mut client_store, &mut server_store);
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // both ends verified and remembered each other
+    assert_eq!(client_store.trusted, [server_public]);
+    assert_eq!(server_store.trusted, [client_public]);
+    let mut key = [0u8; 32];
+    assert_eq!(
+        unsafe { scallop_remote_static(client, key.as_mut_ptr()) },
+        SCALLOP_OK
+    );
+    assert_eq!(key, server_public);
+    assert_eq!(
+        unsafe { scallop_remote_static(server, key.as_mut_ptr()) },
+        SCALLOP_OK
+    );
+    assert_eq!(key, client_public);
+
+    // same binding and exported keys on both ends
+    let (mut a, mut b) = ([0u8; 64], [0u8; 64]);
+    assert_eq!(
+        unsafe { scallop_channel_binding(client, a.as_mut_ptr(), a.len()) },
+        64
+    );
+    assert_eq!(
+        unsafe { scallop_channel_binding(server, b.as_mut_ptr(), b.len()) },
+        64
+    );
+    assert_eq!(a, b);
+    let export = |session, out: &mut [u8; 64]| unsafe {
+        scallop_export_keying_material(
+            session,
+            b"label".as_ptr(),
+            5,
+            null(),
+            0,
+            out.as_mut_ptr(),
+            64,
+        )
+    };
+    assert_eq!(export(client, &mut a), SCALLOP_OK);
+    assert_eq!(export(server, &mut b), SCALLOP_OK);
+    assert_eq!(a, b);
+
+    unsafe {
+        assert_eq!(scallop_write(client, b"hello".as_ptr(), 5), 5);
+        let mut buf = [0u8; 16];
+        assert_eq!(scallop_read(server, buf.as_mut_ptr(), buf.len()), 5);
+        assert_eq!(&buf[..5], b"hello");
+
+        // clean close
+        assert_eq!(scallop_shutdown(server), SCALLOP_OK);
+        assert_eq!(scallop_read(client, buf.as_mut_ptr(), buf.len()), 0);
+
+        scallop_free(client);
+        scallop_free(server);
+    }
+}
+
+#[test]
+fn test_ffi_errors() {
+    // attestations are rejected
+    let mut client_store = Store::default();
+    let mut server_store = Store {
+        accept: true,
+        ..Default::default()
+    };
+    let (client, server, _, _) = connect(&mut client_store, &mut server_store);
+    assert_eq!(client.unwrap_err(), SCALLOP_ERR_PROTOCOL);
+    // the server finished its side of the handshake and sees the client go away
+    let server = server.unwrap();
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        unsafe { scallop_read(server, buf.as_mut_ptr(), buf.len()) },
+        SCALLOP_ERR_IO as isize
+    );
+    assert!(!last_error().is_empty());
+    unsafe { scallop_free(server) };
+
+    // missing arguments
+    let mut session = null_mut();
+    let status = unsafe { scallop_client_fd(0, null(), null(), null(), &mut session) };
+    assert_eq!(status, SCALLOP_ERR_INVALID_ARGUMENT);
+    assert_eq!(last_error(), "null secret or session");
+
+    let io = ScallopIoCallbacks {
+        ctx: null_mut(),
+        read: None,
+        write: None,
+    };
+    let secret = [0u8; 32];
+    let status = unsafe { scallop_client_io(&io, secret.as_ptr(), null(), null(), &mut session) };
+    assert_eq!(status, SCALLOP_ERR_INVALID_ARGUMENT);
+    assert!(session.is_null());
+
+    // descriptions are truncated to the buffer
+    let mut buf = [1 as c_char; 5];
+    let len = unsafe { scallop_last_error(buf.as_mut_ptr(), buf.len()) };
+    assert_eq!(len, "missing io callback".len());
+    assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_bytes(), b"miss");
+}
+
+unsafe extern "C" fn new_auth_too_long(_ctx: *mut c_void, _buf: *mut u8, len: usize) -> usize {
+    len + 1
+}
+
+#[test]
+fn test_ffi_auth_length() {
+    // the server asks for an attestation, the client auther claims more than its buffer
+    let (client_stream, server_stream) = UnixStream::pair().unwrap();
+
+    let server = thread::spawn(move || {
+        let mut store = Store {
+            accept: true,
+            ..Default::default()
+        };
+        let store = store_callbacks(&mut store);
+        let mut session = null_mut();
+        unsafe {
+            scallop_server_fd(
+                server_stream.as_raw_fd(),
+                keypair().0.as_ptr(),
+                &store,
+                null(),
+                &mut session,
+            )
+        }
+    });
+
+    let auther = ScallopAutherCallbacks {
+        ctx: null_mut(),
+        new_auth: Some(new_auth_too_long),
+    };
+    let mut session = null_mut();
+    let status = unsafe {
+        scallop_client_fd(
+            client_stream.as_raw_fd(),
+            keypair().0.as_ptr(),
+            null(),
+            &auther,
+            &mut session,
+        )
+    };
+    assert_eq!(status, SCALLOP_ERR_INVALID_ARGUMENT);
+    assert_eq!(
+        last_error(),
+        format!(
+            "auther returned {} bytes, more than the {SCALLOP_MAX_AUTH} byte buffer",
+            SCALLOP_MAX_AUTH + 1
+        )
+    );
+    assert!(session.is_null());
+
+    // the server sees the client go away
+    drop(client_stream);
+    assert_eq!(server.join().unwrap(), SCALLOP_ERR_IO);
+}
+
+#[test]
+fn test_ffi_socket_timeout() {
+    // the remote never answers, only the socket timeout ends the handshake
+    let (client_stream, _silent) = UnixStream::pair().unwrap();
+    client_stream
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+
+    let mut session = null_mut();
+    let status = unsafe {
+        scallop_client_fd(
+            client_stream.as_raw_fd(),
+            keypair().0.as_ptr(),
+            null(),
+            null(),
+            &mut session,
+        )
+    };
+    assert_eq!(status, SCALLOP_ERR_IO);
+    assert!(session.is_null());
+}
+
+#[test]
+fn test_header() {
+    // every export of the library is declared in the header
+    let header = include_str!("../include/oyster_scallop.h");
+    let source = include_str!("../src/lib.rs");
+
+    for line in source.lines() {
+        let name = if let Some(rest) = line.strip_prefix("pub unsafe extern \"C\" fn ") {
+            rest.split('(').next().unwrap()
+        } else if let Some(rest) = line.strip_prefix("pub const ") {
+            rest.split(':').next().unwrap()
+        } else if let Some(rest) = line.strip_prefix("pub struct ") {
+            rest.split(' ').next().unwrap()
+        } else {
+            continue;
+        };
+
+        assert!(header.contains(name), "{name} missing from the header");
+    }
+}
@@ -0,0 +1,304 @@
+// Throughput of the Scallop record layer
+//
+// Pushes data from a client to a server over TCP loopback through ScallopStream and
+// through a copy of the earlier record layer, which allocated buffers for every record
+// and flushed the previous record on every write.
+//
+// cargo bench --bench scallop
+// cargo bench --bench scallop -- 1024     (MiB per run, defaults to 256)
+
+use std::time::{Duration, Instant};
+
+use oyster::scallop::{
+    new_client_async, new_server_async, AttestationAuther, HandshakeLimits, PolicyAuthStore,
+    ScallopProtocol,
+};
+use snow::{Builder, TransportState};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const PROTOCOL: ScallopProtocol = ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b;
+
+// write sizes of typical proxies, reads always use 16 KiB buffers like hyper
+const WRITE_SIZES: [usize; 4] = [1024, 16384, 65536, 1 << 20];
+const READ_SIZE: usize = 16384;
+
+fn secret() -> [u8; 32] {
+    Builder::new(PROTOCOL.name().parse().unwrap())
+        .generate_keypair()
+        .unwrap()
+        .private
+        .try_into()
+        .unwrap()
+}
+
+async fn tcp_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap());
+    let (client, server) = tokio::join!(client, listener.accept());
+    let (client, (server, _)) = (client.unwrap(), server.unwrap());
+    client.set_nodelay(true).unwrap();
+    server.set_nodelay(true).unwrap();
+
+    (client, server)
+}
+
+async fn scallop_pair() -> (
+    impl AsyncWrite + Unpin + Send,
+    impl AsyncRead + Unpin + Send,
+) {
+    let (client, server) = tcp_pair().await;
+    let (client_secret, server_secret) = (secret(), secret());
+    let (client, server) = tokio::join!(
+        new_client_async(
+            client,
+            &client_secret,
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            &[PROTOCOL],
+            HandshakeLimits::new(),
+        ),
+        new_server_async(
+            server,
+            &server_secret,
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            &[PROTOCOL],
+            HandshakeLimits::new(),
+        ),
+    );
+
+    (client.unwrap(), server.unwrap())
+}
+
+async fn legacy_pair() -> (
+    impl AsyncWrite + Unpin + Send,
+    impl AsyncRead + Unpin + Send,
+) {
+    let (client, server) = tcp_pair().await;
+
+    // plain IX handshake, only the transport keys matter here
+    let mut initiator = Builder::new(PROTOCOL.name().parse().unwrap())
+        .local_private_key(&secret())
+        .build_initiator()
+        .unwrap();
+    let mut responder = Builder::new(PROTOCOL.name().parse().unwrap())
+        .local_private_key(&secret())
+        .build_responder()
+        .unwrap();
+    let (mut buf, mut payload) = ([0u8; 1024], [0u8; 1024]);
+    let len = initiator.write_message(&[], &mut buf).unwrap();
+    responder.read_message(&buf[..len], &mut payload).unwrap();
+    let len = responder.write_message(&[], &mut buf).unwrap();
+    initiator.read_message(&buf[..len], &mut payload).unwrap();
+
+    (
+        legacy::Stream::new(initiator.into_transport_mode().unwrap(), client),
+        legacy::Stream::new(responder.into_transport_mode().unwrap(), server),
+    )
+}
+
+async fn transfer(
+    mut writer: impl AsyncWrite + Unpin + Send + 'static,
+    mut reader: impl AsyncRead + Unpin,
+    total: usize,
+    write_size: usize,
+) -> Duration {
+    let start = Instant::now();
+
+    let writer = tokio::spawn(async move {
+        let data = vec![0x5au8; write_size];
+        let mut left = total;
+        while left > 0 {
+            let len = left.min(write_size);
+            writer.write_all(&data[..len]).await.unwrap();
+            left -= len;
+        }
+        writer.flush().await.unwrap();
+        writer
+    });
+
+    let mut buf = vec![0u8; READ_SIZE];
+    let mut read = 0;
+    while read < total {
+        let len = reader.read(&mut buf).await.unwrap();
+        assert_ne!(len, 0, "unexpected eof");
+        read += len;
+    }
+
+    let elapsed = start.elapsed();
+    drop(writer.await.unwrap());
+
+    elapsed
+}
+
+fn throughput(total: usize, elapsed: Duration) -> f64 {
+    total as f64 / elapsed.as_secs_f64() / (1 << 20) as f64
+}
+
+#[tokio::main]
+async fn main() {
+    // libtest passes flags like --bench, ignore anything which is not a size
+    let mib = std::env::args()
+        .skip(1)
+        .find_map(|x| x.parse::<usize>().ok())
+        .unwrap_or(256);
+    let total = mib << 20;
+
+    println!("{mib} MiB per run, {READ_SIZE} byte reads");
+    println!(
+        "{:>12} {:>16} {:>16} {:>8}",
+        "write size", "scallop MiB/s", "legacy MiB/s", "speedup"
+    );
+
+    for write_size in WRITE_SIZES {
+        let (writer, reader) = scallop_pair().await;
+        let current = throughput(total, transfer(writer, reader, total, write_size).await);
+
+        let (writer, reader) = legacy_pair().await;
+        let legacy = throughput(total, transfer(writer, reader, total, write_size).await);
+
+        println!(
+            "{write_size:>12} {current:>16.1} {legacy:>16.1} {:>7.2}x",
+            current / legacy
+        );
+    }
+}
+
+// record layer as it was before buffers were reused, kept for comparison
+mod legacy {
+    use std::pin::Pin;
+    use std::task::{ready, Context, Poll};
+
+    use super::*;
+    use tokio::io::ReadBuf;
+
+    #[derive(Debug, PartialEq)]
+    enum ReadMode {
+        Length,
+        Body,
+        Read,
+    }
+
+    pub struct Stream<Base> {
+        noise: TransportState,
+        stream: Base,
+        rbuf: Box<[u8]>,
+        pending: usize,
+        mode: ReadMode,
+        read_start: usize,
+        read_end: usize,
+        wbuf: Box<[u8]>,
+        write_start: usize,
+        write_end: usize,
+    }
+
+    impl<Base> Stream<Base> {
+        pub fn new(noise: TransportState, stream: Base) -> Self {
+            Self {
+                noise,
+                stream,
+                rbuf: vec![0u8; 2].into_boxed_slice(),
+                pending: 2,
+                mode: ReadMode::Length,
+                read_start: 0,
+                read_end: 0,
+                wbuf: vec![].into_boxed_slice(),
+                write_start: 0,
+                write_end: 0,
+            }
+        }
+    }
+
+    impl<Base: AsyncRead + Unpin> AsyncRead for Stream<Base> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let stream = self.get_mut();
+            loop {
+                while stream.pending != 0 {
+                    let len = stream.rbuf.len();
+                    let mut buf = ReadBuf::new(&mut stream.rbuf[(len - stream.pending)..]);
+                    ready!(Pin::new(&mut stream.stream).poll_read(cx, &mut buf))?;
+                    if buf.filled().is_empty() {
+                        return Poll::Ready(Ok(()));
+                    }
+                    stream.pending -= buf.filled().len();
+                }
+
+                if stream.mode == ReadMode::Length {
+                    let record_length = u16::from_be_bytes(stream.rbuf[0..2].try_into().unwrap());
+                    stream.pending = record_length.into();
+                    stream.mode = ReadMode::Body;
+                    stream.rbuf = vec![0u8; stream.pending].into_boxed_slice();
+                } else if stream.mode == ReadMode::Body {
+                    let len = stream
+                        .noise
+                        .read_message(&stream.rbuf.clone(), &mut stream.rbuf)
+                        .map_err(std::io::Error::other)?;
+                    stream.read_start = 0;
+                    stream.read_end = len;
+                    stream.mode = ReadMode::Read;
+                } else {
+                    if buf.remaining() < stream.read_end - stream.read_start {
+                        let read_start = stream.read_start;
+                        stream.read_start += buf.remaining();
+                        let read_end = read_start + buf.remaining();
+                        buf.put_slice(&stream.rbuf[read_start..read_end]);
+                    } else {
+                        buf.put_slice(&stream.rbuf[stream.read_start..stream.read_end]);
+                        stream.rbuf = vec![0u8; 2].into_boxed_slice();
+                        stream.pending = 2;
+                        stream.mode = ReadMode::Length;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+
+    impl<Base: AsyncWrite + Unpin> AsyncWrite for Stream<Base> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            ready!(self.as_mut().poll_flush(cx))?;
+
+            let stream = self.get_mut();
+            let len = std::cmp::min(buf.len(), 64000);
+            let mut new_buf = vec![0u8; len + 1000].into_boxed_slice();
+            let noise_len = stream
+                .noise
+                .write_message(&buf[..len], &mut new_buf[2..])
+                .map_err(std::io::Error::other)?;
+            new_buf[0..2].copy_from_slice(&(noise_len as u16).to_be_bytes());
+
+            stream.wbuf = new_buf;
+            stream.write_start = 0;
+            stream.write_end = noise_len + 2;
+
+            Poll::Ready(Ok(len))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            let stream = self.get_mut();
+            while stream.write_start != stream.write_end {
+                let size = ready!(Pin::new(&mut stream.stream)
+                    .poll_write(cx, &stream.wbuf[stream.write_start..stream.write_end]))?;
+                stream.write_start += size;
+            }
+            Pin::new(&mut stream.stream).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            ready!(self.as_mut().poll_flush(cx))?;
+            Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+        }
+    }
+}
@@ -1,30 +1,32 @@
 // Axum based example for the scallop transport
-// Sadly, the only real axum usage here is for the router
-// We end up having to repeat the connection loop in order to wrap the underlying stream
+// The server uses the axum helper, the client uses the hyper connector
+// The remote identity is available to both through extensions
 
 use std::collections::HashMap;
 use std::error::Error;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use axum::{routing::get, Router};
-use http::{Request, StatusCode};
+use axum::{routing::get, Extension, Router};
 use http_body_util::BodyExt;
 use http_body_util::Empty;
 use hyper::body::Bytes;
-use hyper::body::Incoming;
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
 use libsodium_sys::{
     crypto_sign_ed25519_pk_to_curve25519, crypto_sign_ed25519_sk_to_curve25519, crypto_sign_keypair,
 };
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
 use tokio::time::sleep;
-use tower::Service;
 
-pub use oyster::scallop::*;
+use oyster::scallop::http::{serve, ScallopConfig, ScallopConnector, ScallopPeer, ServeLimits};
+use oyster::scallop::{ScallopAuthStore, ScallopAuther};
+
+type Pcrs = ([u8; 48], [u8; 48], [u8; 48]);
 
 #[derive(Default)]
 struct AuthStore {
-    store: HashMap<[u8; 32], ([u8; 48], [u8; 48], [u8; 48])>,
+    store: HashMap<[u8; 32], Pcrs>,
 }
 
 impl ScallopAuthStore for AuthStore {
@@ -53,6 +55,7 @@ impl ScallopAuthStore for AuthStore {
     }
 }
 
+#[derive(Clone)]
 struct Auther {}
 
 impl ScallopAuther for Auther {
@@ -64,88 +67,48 @@ impl ScallopAuther for Auther {
 async fn server_task(key: [u8; 32]) -> Result<(), Box<dyn Error + Send + Sync>> {
     let app = Router::new()
         .route("/hello", get(|| async { "Hello World!" }))
-        .route("/welcome", get(|| async { "Welcome!" }));
+        .route(
+            "/welcome",
+            get(|Extension(peer): Extension<ScallopPeer>| async move {
+                println!("Client key: {:?}", peer.static_key);
+                format!("Welcome {:?}!", peer.pcrs)
+            }),
+        );
 
     let server = TcpListener::bind("127.0.0.1:21000").await?;
 
-    let mut auth_store = AuthStore::default();
-    let mut auther = Auther {};
+    let config = ScallopConfig::new(key)
+        .auth_store(Arc::new(Mutex::new(AuthStore::default())))
+        .auther(|| async { Auther {}.new_auth().await });
 
-    loop {
-        let (stream, _) = server.accept().await?;
-
-        let stream = new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
-            stream,
-            &key,
-            Some(&mut auth_store),
-            Some(&mut auther),
-        )
-        .await?;
-
-        let app = app.clone();
-
-        println!("Client key: {:?}", stream.get_remote_static());
-
-        tokio::spawn(async move {
-            let stream = TokioIo::new(stream);
-
-            let app = hyper::service::service_fn(move |request: Request<Incoming>| {
-                app.clone().call(request)
-            });
-
-            if let Err(err) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
-                .serve_connection(stream, app)
-                .await
-            {
-                eprintln!("failed to serve connection: {err:#}");
-            }
-        });
-    }
+    serve(server, app, config, ServeLimits::default()).await?;
+
+    Ok(())
 }
 
 async fn client_task(key: [u8; 32]) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut auth_store = AuthStore::default();
-    let mut auther = Auther {};
+    let config = ScallopConfig::new(key)
+        .auth_store(Arc::new(Mutex::new(AuthStore::default())))
+        .auther(|| async { Auther {}.new_auth().await });
+    let client = Client::builder(TokioExecutor::new())
+        .build::<_, Empty<Bytes>>(ScallopConnector::new(config));
 
     loop {
-        let stream = TcpStream::connect("127.0.0.1:21000").await?;
-
-        let stream = new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
-            stream,
-            &key,
-            Some(&mut auth_store),
-            Some(&mut auther),
-        )
-        .await?;
-
-        println!("Server key: {:?}", stream.get_remote_static());
-
-        let stream = TokioIo::new(stream);
-
-        let (mut request_sender, connection) =
-            hyper::client::conn::http1::handshake(stream).await?;
-
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("Error in connection: {}", e);
-            }
-        });
-
-        let request = Request::builder()
-            .method("GET")
-            .uri("/hello")
-            .body(Empty::<Bytes>::new())?;
-        let response = request_sender.send_request(request).await?;
-        assert!(response.status() == StatusCode::OK);
+        let response = client.get("http://127.0.0.1:21000/hello".parse()?).await?;
+        assert!(response.status().is_success());
+        println!(
+            "Server key: {:?}",
+            response
+                .extensions()
+                .get::<ScallopPeer>()
+                .map(|x| x.static_key)
+        );
         println!("{:?}", response.collect().await?.to_bytes());
 
-        request_sender.ready().await?;
-        let request = Request::builder()
-            .method("GET")
-            .uri("/welcome")
-            .body(Empty::<Bytes>::new())?;
-        let response = request_sender.send_request(request).await?;
-        assert!(response.status() == StatusCode::OK);
+        let response = client
+            .get("http://127.0.0.1:21000/welcome".parse()?)
+            .await?;
+        assert!(response.status().is_success());
         println!("{:?}", response.collect().await?.to_bytes());
 
         sleep(Duration::from_secs(5)).await;
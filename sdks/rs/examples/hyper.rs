@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use http::{Request, Response, StatusCode};
@@ -9,19 +10,25 @@ use http_body_util::Full;
 use hyper::body::Bytes;
 use hyper::body::Incoming;
 use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
 use libsodium_sys::{
     crypto_sign_ed25519_pk_to_curve25519, crypto_sign_ed25519_sk_to_curve25519, crypto_sign_keypair,
 };
 use std::convert::Infallible;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
 use tokio::time::sleep;
 
-pub use oyster::scallop::*;
+use oyster::scallop::http::{ScallopConfig, ScallopConnector, ScallopPeer};
+use oyster::scallop::{
+    new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b, ScallopAuthStore, ScallopAuther,
+};
+
+type Pcrs = ([u8; 48], [u8; 48], [u8; 48]);
 
 #[derive(Default)]
 struct AuthStore {
-    store: HashMap<[u8; 32], ([u8; 48], [u8; 48], [u8; 48])>,
+    store: HashMap<[u8; 32], Pcrs>,
 }
 
 impl ScallopAuthStore for AuthStore {
@@ -50,6 +57,7 @@ impl ScallopAuthStore for AuthStore {
     }
 }
 
+#[derive(Clone)]
 struct Auther {}
 
 impl ScallopAuther for Auther {
@@ -96,47 +104,26 @@ async fn server_task(key: [u8; 32]) -> Result<(), Box<dyn Error + Send + Sync>>
 }
 
 async fn client_task(key: [u8; 32]) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let mut auth_store = AuthStore::default();
-    let mut auther = Auther {};
+    let config = ScallopConfig::new(key)
+        .auth_store(Arc::new(Mutex::new(AuthStore::default())))
+        .auther(|| async { Auther {}.new_auth().await });
+    let client = Client::builder(TokioExecutor::new())
+        .build::<_, Empty<Bytes>>(ScallopConnector::new(config));
 
     loop {
-        let stream = TcpStream::connect("127.0.0.1:21000").await?;
-
-        let stream = new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
-            stream,
-            &key,
-            Some(&mut auth_store),
-            Some(&mut auther),
-        )
-        .await?;
-
-        println!("Server key: {:?}", stream.get_remote_static());
-
-        let stream = TokioIo::new(stream);
-
-        let (mut request_sender, connection) =
-            hyper::client::conn::http1::handshake(stream).await?;
-
-        tokio::spawn(async move {
-            if let Err(e) = connection.await {
-                eprintln!("Error in connection: {}", e);
-            }
-        });
-
-        let request = Request::builder()
-            .method("GET")
-            .body(Empty::<Bytes>::new())?;
-        let response = request_sender.send_request(request).await?;
-        assert!(response.status() == StatusCode::OK);
-        println!("{:?}", response.collect().await?.to_bytes());
-
-        request_sender.ready().await?;
-        let request = Request::builder()
-            .method("GET")
-            .body(Empty::<Bytes>::new())?;
-        let response = request_sender.send_request(request).await?;
-        assert!(response.status() == StatusCode::OK);
-        println!("{:?}", response.collect().await?.to_bytes());
+        // connections are pooled, the second request reuses the first connection
+        for _ in 0..2 {
+            let response = client.get("http://127.0.0.1:21000/".parse()?).await?;
+            assert!(response.status() == StatusCode::OK);
+            println!(
+                "Server key: {:?}",
+                response
+                    .extensions()
+                    .get::<ScallopPeer>()
+                    .map(|x| x.static_key)
+            );
+            println!("{:?}", response.collect().await?.to_bytes());
+        }
 
         sleep(Duration::from_secs(5)).await;
     }
@@ -11,9 +11,11 @@ use tokio::time::sleep;
 
 pub use oyster::scallop::*;
 
+type Pcrs = ([u8; 48], [u8; 48], [u8; 48]);
+
 #[derive(Default)]
 struct AuthStore {
-    store: HashMap<[u8; 32], ([u8; 48], [u8; 48], [u8; 48])>,
+    store: HashMap<[u8; 32], Pcrs>,
 }
 
 impl ScallopAuthStore for AuthStore {
@@ -0,0 +1,94 @@
+// feeds arbitrary server responses to a client handshake, either as the raw stream or
+// as the attestation of a real server
+
+#![no_main]
+
+use std::time::Duration;
+
+use libfuzzer_sys::fuzz_target;
+use oyster::attestation::AttestationPolicy;
+use oyster::scallop::{
+    new_client_async, new_server_async, AttestationAuther, HandshakeLimits, PolicyAuthStore,
+    ScallopAuther, ScallopProtocol,
+};
+use tokio::io::{AsyncWriteExt, DuplexStream};
+
+// attests with the fuzzed input
+struct FuzzAuther<'a>(&'a [u8]);
+
+impl ScallopAuther for FuzzAuther<'_> {
+    async fn new_auth(&mut self) -> Box<[u8]> {
+        self.0.into()
+    }
+}
+
+fn limits() -> HandshakeLimits {
+    HandshakeLimits::new().timeout(Duration::from_millis(100))
+}
+
+async fn client(
+    stream: DuplexStream,
+    auth_store: Option<PolicyAuthStore>,
+    protocols: &[ScallopProtocol],
+) {
+    let _ = new_client_async(
+        stream,
+        &[1u8; 32],
+        auth_store,
+        None::<AttestationAuther>,
+        protocols,
+        limits(),
+    )
+    .await;
+}
+
+fuzz_target!(|data: &[u8]| {
+    // first byte picks the setup, the rest is what the server sends
+    let Some((&setup, data)) = data.split_first() else {
+        return;
+    };
+
+    let protocols: &[ScallopProtocol] = if setup & 1 == 0 {
+        &ScallopProtocol::ALL
+    } else {
+        &[ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b]
+    };
+    let auth_store = (setup & 2 != 0).then(|| PolicyAuthStore::new(AttestationPolicy::new()));
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        if setup & 4 != 0 {
+            // a real server sends the input as its attestation, which the client asks for
+            let (server_stream, client_stream) = tokio::io::duplex(1 << 16);
+            let auth_store = PolicyAuthStore::new(AttestationPolicy::new());
+            let server = async {
+                let _ = new_server_async(
+                    server_stream,
+                    &[2u8; 32],
+                    None::<PolicyAuthStore>,
+                    Some(FuzzAuther(data)),
+                    protocols,
+                    limits(),
+                )
+                .await;
+            };
+            tokio::join!(client(client_stream, Some(auth_store), protocols), server);
+            return;
+        }
+
+        let (mut server, client_stream) = tokio::io::duplex(data.len() + 1);
+        server.write_all(data).await.unwrap();
+        // the client sees the end of the stream after the input, never blocks for long
+        server.shutdown().await.unwrap();
+
+        // whatever the client sends is discarded, until the handshake drops its end
+        let drain = async {
+            let _ = tokio::io::copy(&mut server, &mut tokio::io::sink()).await;
+        };
+        tokio::join!(client(client_stream, auth_store, protocols), drain);
+    });
+});
@@ -0,0 +1,103 @@
+// feeds arbitrary client messages to a server handshake, resumption included, either as
+// the raw stream or as the attestation of a real client
+
+#![no_main]
+
+use std::time::Duration;
+
+use libfuzzer_sys::fuzz_target;
+use oyster::attestation::AttestationPolicy;
+use oyster::scallop::{
+    new_client_async, new_server_resumable_async, AttestationAuther, HandshakeLimits,
+    PolicyAuthStore, ScallopAuther, ScallopProtocol, ScallopTickets,
+};
+use tokio::io::{AsyncWriteExt, DuplexStream};
+
+// attests with the fuzzed input
+struct FuzzAuther<'a>(&'a [u8]);
+
+impl ScallopAuther for FuzzAuther<'_> {
+    async fn new_auth(&mut self) -> Box<[u8]> {
+        self.0.into()
+    }
+}
+
+fn limits() -> HandshakeLimits {
+    HandshakeLimits::new().timeout(Duration::from_millis(100))
+}
+
+async fn server(
+    stream: DuplexStream,
+    auth_store: Option<PolicyAuthStore>,
+    protocols: &[ScallopProtocol],
+    tickets: &ScallopTickets,
+) {
+    let _ = new_server_resumable_async(
+        stream,
+        &[2u8; 32],
+        auth_store,
+        None::<AttestationAuther>,
+        protocols,
+        tickets,
+        limits(),
+    )
+    .await;
+}
+
+fuzz_target!(|data: &[u8]| {
+    // first byte picks the setup, the rest is what the client sends
+    let Some((&setup, data)) = data.split_first() else {
+        return;
+    };
+
+    let protocols: &[ScallopProtocol] = if setup & 1 == 0 {
+        &ScallopProtocol::ALL
+    } else {
+        &[ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b]
+    };
+    let auth_store = (setup & 2 != 0).then(|| PolicyAuthStore::new(AttestationPolicy::new()));
+    let tickets = ScallopTickets::new();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        if setup & 4 != 0 {
+            // a real client sends the input as its attestation, which the server asks for
+            let (client_stream, server_stream) = tokio::io::duplex(1 << 16);
+            let auth_store = PolicyAuthStore::new(AttestationPolicy::new());
+            let client = async {
+                let _ = new_client_async(
+                    client_stream,
+                    &[1u8; 32],
+                    None::<PolicyAuthStore>,
+                    Some(FuzzAuther(data)),
+                    protocols,
+                    limits(),
+                )
+                .await;
+            };
+            tokio::join!(
+                server(server_stream, Some(auth_store), protocols, &tickets),
+                client
+            );
+            return;
+        }
+
+        let (mut client, server_stream) = tokio::io::duplex(data.len() + 1);
+        client.write_all(data).await.unwrap();
+        // the server sees the end of the stream after the input, never blocks for long
+        client.shutdown().await.unwrap();
+
+        // whatever the server sends is discarded, until the handshake drops its end
+        let drain = async {
+            let _ = tokio::io::copy(&mut client, &mut tokio::io::sink()).await;
+        };
+        tokio::join!(
+            server(server_stream, auth_store, protocols, &tickets),
+            drain
+        );
+    });
+});
@@ -1,16 +1,44 @@
+mod backend;
+#[cfg(any(test, feature = "builder"))]
+pub(crate) mod builder;
+mod clock;
+pub mod eip712;
+#[cfg(feature = "network")]
+mod fetch;
+pub mod json;
+mod policy;
+mod trust;
+
+pub use backend::PathViolation;
+#[cfg(any(test, feature = "builder"))]
+pub use builder::{AttestationBuilder, AttestationChain, SigningKey};
+pub use clock::{Clock, FixedClock, SystemClock};
+#[cfg(feature = "network")]
+pub use fetch::{
+    fetch_fresh_attestation, raw_attestation_uri, FreshAttestation, FreshAttestationClient,
+};
+pub use policy::{AttestationPolicy, PolicyFailure, PolicyReport, PublicKeyType, PCR_COUNT};
+pub use trust::TrustAnchors;
+
 use std::collections::BTreeMap;
 
-use aws_nitro_enclaves_cose::{crypto::Openssl, CoseSign1};
-use chrono::Utc;
+use aws_nitro_enclaves_cose::CoseSign1;
+#[cfg(feature = "network")]
 use http_body_util::{BodyExt, Full};
+#[cfg(feature = "network")]
 use hyper::body::Bytes;
+#[cfg(feature = "network")]
 use hyper::Uri;
+#[cfg(feature = "network")]
 use hyper_util::client::legacy::{Client, Error};
+#[cfg(feature = "network")]
 use hyper_util::rt::TokioExecutor;
-use openssl::asn1::Asn1Time;
-use openssl::x509::{X509VerifyResult, X509};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use serde_cbor::{self, value, value::Value};
 
+use backend::{Backend, DefaultBackend, Sha2};
+
 #[derive(Debug)]
 pub struct AttestationDecoded {
     pub pcrs: [[u8; 48]; 3],
@@ -18,78 +46,115 @@ pub struct AttestationDecoded {
     pub public_key: Vec<u8>,
 }
 
+/// Full attestation document as produced by the Nitro Secure Module
+///
+/// Fields follow the order and encoding used by the NSM so that
+/// decoding and re-encoding a document yields the original payload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationDocument {
+    /// issuing enclave module id
+    pub module_id: String,
+    /// digest function used for the pcrs, `SHA384` for Nitro enclaves
+    pub digest: String,
+    /// generation time in milliseconds since the unix epoch
+    pub timestamp: u64,
+    /// pcr index to pcr value
+    pub pcrs: BTreeMap<usize, ByteBuf>,
+    /// DER encoded enclave certificate which signs the document
+    pub certificate: ByteBuf,
+    /// DER encoded certificates, ordered from the root to the issuer of the enclave certificate
+    pub cabundle: Vec<ByteBuf>,
+    /// optional public key of the enclave application
+    pub public_key: Option<ByteBuf>,
+    /// optional user data
+    pub user_data: Option<ByteBuf>,
+    /// optional nonce
+    pub nonce: Option<ByteBuf>,
+}
+
+impl AttestationDocument {
+    /// Decode from a COSE_Sign1 attestation without verifying it
+    pub fn from_cose(attestation_doc: &[u8]) -> Result<Self, AttestationError> {
+        let cosesign1 = CoseSign1::from_bytes(attestation_doc)
+            .map_err(|e| AttestationError::ParseFailed(format!("cose: {e}")))?;
+        let payload = cosesign1
+            .get_payload::<Sha2>(None)
+            .map_err(|e| AttestationError::ParseFailed(format!("cose payload: {e}")))?;
+
+        Self::from_cbor(&payload)
+    }
+
+    /// Decode from the CBOR payload of an attestation
+    pub fn from_cbor(payload: &[u8]) -> Result<Self, AttestationError> {
+        serde_cbor::from_slice(payload)
+            .map_err(|e| AttestationError::ParseFailed(format!("doc: {e}")))
+    }
+
+    /// Encode into the CBOR payload of an attestation
+    pub fn to_cbor(&self) -> Result<Vec<u8>, AttestationError> {
+        serde_cbor::to_vec(self).map_err(|e| AttestationError::ParseFailed(format!("doc: {e}")))
+    }
+
+    /// Value of the pcr at the given index, if present
+    pub fn pcr(&self, index: usize) -> Option<&[u8]> {
+        self.pcrs.get(&index).map(|x| x.as_slice())
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum AttestationError {
     #[error("failed to parse: {0}")]
     ParseFailed(String),
     #[error("failed to verify attestation: {0}")]
     VerifyFailed(String),
+    #[error("invalid certificate path at depth {depth}: {violation}")]
+    InvalidPath {
+        /// position in the path, 0 for the enclave certificate up to the root
+        depth: usize,
+        violation: PathViolation,
+    },
+    #[error("attestation does not satisfy policy: {0}")]
+    PolicyFailed(PolicyReport),
+    #[error("attestation too old, age {age}ms exceeds {max_age}ms")]
+    TooOld { age: u64, max_age: u64 },
+    #[error("failed to build attestation: {0}")]
+    BuildFailed(String),
+    #[cfg(feature = "network")]
     #[error("http client error")]
     HttpClientError(#[from] Error),
+    #[cfg(feature = "network")]
     #[error("http body error")]
     HttpBodyError(#[from] hyper::Error),
+    #[cfg(feature = "network")]
+    #[error("http status {0}")]
+    HttpStatus(hyper::StatusCode),
+    #[cfg(feature = "network")]
+    #[error("attestation request timed out")]
+    Timeout,
 }
 
-fn get_all_certs(cert: X509, cabundle: Vec<Value>) -> Result<Vec<X509>, AttestationError> {
-    let mut all_certs = vec![cert];
-    for cert in cabundle {
-        let cert = (match cert {
-            Value::Bytes(b) => Ok(b),
-            _ => Err(AttestationError::ParseFailed("cert decode".into())),
-        })?;
-        let cert = X509::from_der(&cert)
-            .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
-        all_certs.push(cert);
-    }
-    Ok(all_certs)
-}
-
-fn verify_cert_chain(
-    cert: X509,
-    cabundle: Vec<Value>,
-    root_cert_pem: Vec<u8>,
-) -> Result<(), AttestationError> {
-    let certs = get_all_certs(cert, cabundle)?;
-
-    for i in 0..(certs.len() - 1) {
-        let pubkey = certs[i + 1]
-            .public_key()
-            .map_err(|e| AttestationError::ParseFailed(format!("pubkey: {e}")))?;
-        if !certs[i]
-            .verify(&pubkey)
-            .map_err(|e| AttestationError::ParseFailed(format!("signature: {e}")))?
-        {
-            return Err(AttestationError::VerifyFailed("signature".into()));
+#[cfg(feature = "network")]
+impl AttestationError {
+    /// Whether fetching the attestation again might succeed, true for transport
+    /// failures, timeouts and server errors
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AttestationError::HttpClientError(_)
+            | AttestationError::HttpBodyError(_)
+            | AttestationError::Timeout => true,
+            AttestationError::HttpStatus(status) => status.is_server_error(),
+            _ => false,
         }
-        if certs[i + 1].issued(&certs[i]) != X509VerifyResult::OK {
-            return Err(AttestationError::VerifyFailed("issuer or subject".into()));
-        }
-        let current_time =
-            Asn1Time::days_from_now(0).map_err(|e| AttestationError::ParseFailed(e.to_string()))?;
-        if certs[i].not_after() < current_time || certs[i].not_before() > current_time {
-            return Err(AttestationError::VerifyFailed("timestamp".into()));
-        }
-    }
-
-    let root_cert = X509::from_pem(&root_cert_pem)
-        .map_err(|e| AttestationError::ParseFailed(format!("pem: {e}")))?;
-    if &root_cert
-        != certs
-            .last()
-            .ok_or(AttestationError::ParseFailed("root".into()))?
-    {
-        return Err(AttestationError::VerifyFailed("root".into()));
     }
-    Ok(())
 }
 
 fn parse_attestation_doc(
     attestation_doc: &[u8],
 ) -> Result<(CoseSign1, BTreeMap<Value, Value>), AttestationError> {
-    let cosesign1 = CoseSign1::from_bytes(&attestation_doc)
+    let cosesign1 = CoseSign1::from_bytes(attestation_doc)
         .map_err(|e| AttestationError::ParseFailed(format!("cose: {e}")))?;
     let payload = cosesign1
-        .get_payload::<Openssl>(None)
+        .get_payload::<Sha2>(None)
         .map_err(|e| AttestationError::ParseFailed(format!("cose payload: {e}")))?;
     let cbor = serde_cbor::from_slice::<Value>(&payload)
         .map_err(|e| AttestationError::ParseFailed(format!("cbor: {e}")))?;
@@ -109,7 +174,7 @@ fn parse_pcrs(
         .map_err(|e| AttestationError::ParseFailed(format!("pcrs: {e}")))?;
 
     let mut result = [[0; 48]; 3];
-    for i in 0..3 {
+    for (i, result) in result.iter_mut().enumerate() {
         let pcr = pcrs_arr
             .remove(&(i as u32).into())
             .ok_or(AttestationError::ParseFailed(format!("pcr{i} not found")))?;
@@ -119,7 +184,7 @@ fn parse_pcrs(
                 "pcr{i} decode failure"
             ))),
         })?;
-        result[i] = pcr
+        *result = pcr
             .as_slice()
             .try_into()
             .map_err(|e| AttestationError::ParseFailed(format!("pcr{i} not 48 bytes: {e}")))?;
@@ -128,11 +193,9 @@ fn parse_pcrs(
     Ok(result)
 }
 
-fn verify_signature_and_cert_chain(
+fn parse_certificates(
     attestation_doc: &mut BTreeMap<Value, Value>,
-    cosesign1: &CoseSign1,
-) -> Result<(), AttestationError> {
-    // verify attestation doc signature
+) -> Result<(Vec<u8>, Vec<ByteBuf>), AttestationError> {
     let enclave_certificate = attestation_doc
         .remove(&"certificate".to_owned().into())
         .ok_or(AttestationError::ParseFailed(
@@ -144,37 +207,27 @@ fn verify_signature_and_cert_chain(
             "enclave certificate decode failure".to_owned(),
         )),
     })?;
-    let enclave_certificate = X509::from_der(&enclave_certificate)
-        .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
-    let pub_key = enclave_certificate
-        .public_key()
-        .map_err(|e| AttestationError::ParseFailed(format!("pubkey: {e}")))?;
-    let verify_result = cosesign1
-        .verify_signature::<Openssl>(&pub_key)
-        .map_err(|e| AttestationError::ParseFailed(format!("signature: {e}")))?;
-
-    if !verify_result {
-        return Err(AttestationError::VerifyFailed("signature".into()));
-    }
 
-    // verify certificate chain
     let cabundle = attestation_doc
         .remove(&"cabundle".to_owned().into())
         .ok_or(AttestationError::ParseFailed(
             "cabundle key not found in attestation doc".to_owned(),
         ))?;
-    let mut cabundle = (match cabundle {
+    let cabundle = (match cabundle {
         Value::Array(b) => Ok(b),
         _ => Err(AttestationError::ParseFailed(
             "cabundle decode failure".to_owned(),
         )),
     })?;
-    cabundle.reverse();
-
-    let root_cert_pem = include_bytes!("./aws.cert").to_vec();
-    verify_cert_chain(enclave_certificate, cabundle, root_cert_pem)?;
+    let cabundle = cabundle
+        .into_iter()
+        .map(|cert| match cert {
+            Value::Bytes(b) => Ok(ByteBuf::from(b)),
+            _ => Err(AttestationError::ParseFailed("cert decode".into())),
+        })
+        .collect::<Result<_, _>>()?;
 
-    Ok(())
+    Ok((enclave_certificate, cabundle))
 }
 
 fn parse_timestamp(
@@ -220,20 +273,33 @@ pub fn verify(
     attestation_doc_cbor: Vec<u8>,
     pcrs: [[u8; 48]; 3],
     max_age: usize,
+) -> Result<Vec<u8>, AttestationError> {
+    verify_with_clock(attestation_doc_cbor, pcrs, max_age, &SystemClock)
+}
+
+/// Same as [`verify`], with certificate validity and age checked against the given clock
+pub fn verify_with_clock(
+    attestation_doc_cbor: Vec<u8>,
+    pcrs: [[u8; 48]; 3],
+    max_age: usize,
+    clock: &dyn Clock,
 ) -> Result<Vec<u8>, AttestationError> {
     // verify attestation and decode fields
-    let decoded_data = verify_and_decode_attestation(attestation_doc_cbor)?;
+    let decoded_data = verify_and_decode_attestation_with_clock(attestation_doc_cbor, clock)?;
 
-    for i in 0..3 {
-        if decoded_data.pcrs[i] != pcrs[i] {
+    for (i, pcr) in pcrs.iter().enumerate() {
+        if &decoded_data.pcrs[i] != pcr {
             return Err(AttestationError::VerifyFailed(format!("pcr{i}")));
         }
     }
 
-    // verify age
-    let now = Utc::now().timestamp_millis();
-    if (now as usize) - max_age > decoded_data.timestamp {
-        return Err(AttestationError::VerifyFailed("too old".into()));
+    // verify age, timestamps in the future have an age of zero
+    let age = clock
+        .now_millis()
+        .saturating_sub(decoded_data.timestamp as u64);
+    let max_age = max_age as u64;
+    if age > max_age {
+        return Err(AttestationError::TooOld { age, max_age });
     }
 
     Ok(decoded_data.public_key)
@@ -247,8 +313,8 @@ pub fn verify_with_timestamp(
     // verify attestation and decode fields
     let decoded_data = verify_and_decode_attestation(attestation_doc_cbor)?;
 
-    for i in 0..3 {
-        if decoded_data.pcrs[i] != pcrs[i] {
+    for (i, pcr) in pcrs.iter().enumerate() {
+        if &decoded_data.pcrs[i] != pcr {
             return Err(AttestationError::VerifyFailed(format!("pcr{i}")));
         }
     }
@@ -263,6 +329,7 @@ pub fn verify_with_timestamp(
     Ok(decoded_data.public_key)
 }
 
+#[cfg(feature = "network")]
 pub async fn get_attestation_doc(endpoint: Uri) -> Result<Vec<u8>, AttestationError> {
     let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
     let res = client.get(endpoint).await?;
@@ -297,6 +364,13 @@ pub fn decode_attestation(
 
 pub fn verify_and_decode_attestation(
     attestation_doc: Vec<u8>,
+) -> Result<AttestationDecoded, AttestationError> {
+    verify_and_decode_attestation_with_clock(attestation_doc, &SystemClock)
+}
+
+fn verify_and_decode_attestation_with_clock(
+    attestation_doc: Vec<u8>,
+    clock: &dyn Clock,
 ) -> Result<AttestationDecoded, AttestationError> {
     let mut result = AttestationDecoded {
         pcrs: [[0; 48]; 3],
@@ -311,7 +385,14 @@ pub fn verify_and_decode_attestation(
     result.pcrs = parse_pcrs(&mut attestation_doc)?;
 
     // verify signature and cert chain
-    verify_signature_and_cert_chain(&mut attestation_doc, &cosesign1)?;
+    let (enclave_certificate, cabundle) = parse_certificates(&mut attestation_doc)?;
+    DefaultBackend::verify_signature_and_cert_chain(
+        &cosesign1,
+        &enclave_certificate,
+        &cabundle,
+        &TrustAnchors::aws_nitro(),
+        clock,
+    )?;
 
     // parse timestamp
     result.timestamp = parse_timestamp(&mut attestation_doc)?;
@@ -321,3 +402,145 @@ pub fn verify_and_decode_attestation(
 
     Ok(result)
 }
+
+/// Verify the signature and certificate chain of an attestation against the given
+/// trust anchors as of the given clock and decode all of its fields
+pub fn verify_and_decode_attestation_document(
+    attestation_doc: &[u8],
+    trust_anchors: &TrustAnchors,
+    clock: &dyn Clock,
+) -> Result<AttestationDocument, AttestationError> {
+    let cosesign1 = CoseSign1::from_bytes(attestation_doc)
+        .map_err(|e| AttestationError::ParseFailed(format!("cose: {e}")))?;
+    let payload = cosesign1
+        .get_payload::<Sha2>(None)
+        .map_err(|e| AttestationError::ParseFailed(format!("cose payload: {e}")))?;
+    let document = AttestationDocument::from_cbor(&payload)?;
+
+    DefaultBackend::verify_signature_and_cert_chain(
+        &cosesign1,
+        &document.certificate,
+        &document.cabundle,
+        trust_anchors,
+        clock,
+    )?;
+
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_decode() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+
+        let doc = AttestationDocument::from_cose(&attestation).unwrap();
+
+        assert_eq!(doc.digest, "SHA384");
+        assert_eq!(doc.timestamp, 1723012689640);
+        assert_eq!(doc.pcrs.len(), 16);
+        assert_eq!(hex::encode(doc.pcr(0).unwrap()), "5fec1b73727425848d725d68f4a062c634061a035067bd0b9a6dc73e25ed5013dfe7ccbf8a7e9857eceb0841c4cb6ae6");
+        assert_eq!(hex::encode(doc.pcr(1).unwrap()), "bcdf05fefccaa8e55bf2c8d6dee9e79bbff31e34bf28a99aa19e6b29c37ee80b214a414b7607236edf26fcb78654e63f");
+        assert_eq!(hex::encode(doc.pcr(2).unwrap()), "ae41ca22df64a32d729667160a7f218e59e31586809e121ff2c446a36dc5354ba4e0f74dce737be3298cf82c364692e7");
+        assert_eq!(hex::encode(doc.public_key.as_ref().unwrap()), "57febcf9e7f5081d3d24182817df526a1c9c3df7e46b64613acd13f9aa53b81de888a8562ba7b4a0e42c48d24d7e444ffcba311ceddb5068eca2ea899379ab50");
+        assert!(!doc.cabundle.is_empty());
+
+        // should agree with the legacy decoder
+        let decoded = decode_attestation(attestation).unwrap();
+        assert_eq!(decoded.timestamp as u64, doc.timestamp);
+        assert_eq!(decoded.pcrs[0], doc.pcr(0).unwrap());
+        assert_eq!(decoded.public_key, doc.public_key.unwrap().into_vec());
+    }
+
+    #[test]
+    fn test_verify_as_of_timestamp() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let timestamp = AttestationDocument::from_cose(&attestation)
+            .unwrap()
+            .timestamp;
+
+        // certificates of the recorded attestation have expired since
+        assert!(matches!(
+            verify_and_decode_attestation_document(
+                &attestation,
+                &TrustAnchors::aws_nitro(),
+                &SystemClock
+            ),
+            Err(AttestationError::VerifyFailed(_))
+        ));
+
+        let doc = verify_and_decode_attestation_document(
+            &attestation,
+            &TrustAnchors::aws_nitro(),
+            &FixedClock(timestamp),
+        )
+        .unwrap();
+        assert_eq!(doc.timestamp, timestamp);
+    }
+
+    #[test]
+    fn test_verify_age() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let decoded = decode_attestation(attestation.clone()).unwrap();
+        let timestamp = decoded.timestamp as u64;
+
+        // max age beyond the current time should not underflow
+        let public_key = verify_with_clock(
+            attestation.clone(),
+            decoded.pcrs,
+            usize::MAX,
+            &FixedClock(timestamp),
+        )
+        .unwrap();
+        assert_eq!(public_key, decoded.public_key);
+
+        assert!(verify_with_clock(
+            attestation.clone(),
+            decoded.pcrs,
+            1000,
+            &FixedClock(timestamp + 1000)
+        )
+        .is_ok());
+
+        assert!(matches!(
+            verify_with_clock(
+                attestation,
+                decoded.pcrs,
+                1000,
+                &FixedClock(timestamp + 1001)
+            ),
+            Err(AttestationError::TooOld {
+                age: 1001,
+                max_age: 1000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_document_roundtrip() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let payload = CoseSign1::from_bytes(&attestation)
+            .unwrap()
+            .get_payload::<Sha2>(None)
+            .unwrap();
+
+        let doc = AttestationDocument::from_cbor(&payload).unwrap();
+
+        assert_eq!(doc.to_cbor().unwrap(), payload);
+    }
+
+    #[test]
+    fn test_document_optional_fields() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let mut doc = AttestationDocument::from_cose(&attestation).unwrap();
+        doc.public_key = None;
+        doc.user_data = Some(ByteBuf::from(b"user data".to_vec()));
+        doc.nonce = Some(ByteBuf::from(vec![]));
+
+        let decoded = AttestationDocument::from_cbor(&doc.to_cbor().unwrap()).unwrap();
+
+        assert_eq!(decoded, doc);
+    }
+}
@@ -0,0 +1,269 @@
+// Cryptographic backends for attestation verification
+//
+// openssl is the default, the `rustcrypto` feature switches to a pure Rust
+// backend which takes precedence when both are enabled. Both backends are
+// expected to behave identically and are run over the same test vectors.
+
+#[cfg(not(any(feature = "openssl", feature = "rustcrypto")))]
+compile_error!("at least one of the `openssl` or `rustcrypto` features must be enabled");
+
+// only exercised by the shared tests when rustcrypto takes precedence
+#[cfg(feature = "openssl")]
+#[cfg_attr(feature = "rustcrypto", allow(dead_code))]
+mod openssl;
+mod path;
+#[cfg(feature = "rustcrypto")]
+mod rustcrypto;
+
+#[cfg(feature = "openssl")]
+#[cfg_attr(feature = "rustcrypto", allow(unused_imports))]
+pub(crate) use self::openssl::OpensslBackend;
+pub use self::path::PathViolation;
+#[cfg(feature = "rustcrypto")]
+pub(crate) use self::rustcrypto::RustCryptoBackend;
+
+#[cfg(not(feature = "rustcrypto"))]
+pub(crate) type DefaultBackend = OpensslBackend;
+#[cfg(feature = "rustcrypto")]
+pub(crate) type DefaultBackend = RustCryptoBackend;
+
+use aws_nitro_enclaves_cose::crypto::{Hash, MessageDigest};
+use aws_nitro_enclaves_cose::error::CoseError;
+use aws_nitro_enclaves_cose::CoseSign1;
+use serde_bytes::ByteBuf;
+use sha2::Digest;
+
+use super::{AttestationError, Clock, TrustAnchors};
+
+pub(crate) trait Backend {
+    /// Verify the COSE signature using the enclave certificate and the certificate
+    /// chain from the enclave certificate up to one of the trust anchors
+    fn verify_signature_and_cert_chain(
+        cosesign1: &CoseSign1,
+        enclave_certificate: &[u8],
+        cabundle: &[ByteBuf],
+        trust_anchors: &TrustAnchors,
+        clock: &dyn Clock,
+    ) -> Result<(), AttestationError>;
+
+    /// DER encodings of every certificate in a PEM bundle
+    fn pem_to_der(pem: &[u8]) -> Result<Vec<Vec<u8>>, AttestationError>;
+
+    /// Canonical DER encoding of a DER certificate, rejects anything which is not a certificate
+    fn canonical_der(der: &[u8]) -> Result<Vec<u8>, AttestationError>;
+}
+
+/// COSE hash implementation on top of the sha2 crate
+pub(crate) struct Sha2;
+
+impl Hash for Sha2 {
+    fn hash(digest: MessageDigest, data: &[u8]) -> Result<Vec<u8>, CoseError> {
+        Ok(match digest {
+            MessageDigest::Sha256 => sha2::Sha256::digest(data).to_vec(),
+            MessageDigest::Sha384 => sha2::Sha384::digest(data).to_vec(),
+            MessageDigest::Sha512 => sha2::Sha512::digest(data).to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p384::elliptic_curve::rand_core::OsRng;
+    use x509_cert::ext::pkix::{BasicConstraints, KeyUsage, KeyUsages};
+
+    use super::*;
+    use crate::attestation::builder::{test_certificate, test_extension};
+    use crate::attestation::{
+        AttestationBuilder, AttestationDocument, FixedClock, SigningKey, SystemClock,
+    };
+
+    // shared vectors, every backend has to produce the same outcome for each
+    fn verify<B: Backend>(
+        attestation: &[u8],
+        trust_anchors: &TrustAnchors,
+        clock: &dyn Clock,
+    ) -> Result<(), AttestationError> {
+        let cosesign1 = CoseSign1::from_bytes(attestation).unwrap();
+        let doc = AttestationDocument::from_cose(attestation).unwrap();
+        B::verify_signature_and_cert_chain(
+            &cosesign1,
+            &doc.certificate,
+            &doc.cabundle,
+            trust_anchors,
+            clock,
+        )
+    }
+
+    fn recorded() -> (Vec<u8>, u64) {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let timestamp = AttestationDocument::from_cose(&attestation)
+            .unwrap()
+            .timestamp;
+        (attestation, timestamp)
+    }
+
+    fn mock() -> Vec<u8> {
+        crate::attestation::builder::mock_attestation(
+            Some(&[1u8; 64]),
+            Some(b"user data"),
+            Some(b"nonce"),
+        )
+    }
+
+    fn suite<B: Backend>() {
+        let (attestation, timestamp) = recorded();
+        let aws = TrustAnchors::aws_nitro();
+        let oyster_mock = TrustAnchors::oyster_mock();
+
+        // recorded attestation as of its timestamp
+        verify::<B>(&attestation, &aws, &FixedClock(timestamp)).unwrap();
+
+        // recorded attestation with expired certificates
+        assert!(matches!(
+            verify::<B>(&attestation, &aws, &SystemClock),
+            Err(AttestationError::VerifyFailed(e)) if e == "timestamp"
+        ));
+
+        // recorded attestation before its certificates were issued
+        assert!(matches!(
+            verify::<B>(&attestation, &aws, &FixedClock(0)),
+            Err(AttestationError::VerifyFailed(e)) if e == "timestamp"
+        ));
+
+        // recorded attestation with an untrusted root
+        assert!(matches!(
+            verify::<B>(&attestation, &oyster_mock, &FixedClock(timestamp)),
+            Err(AttestationError::VerifyFailed(e)) if e == "root"
+        ));
+
+        // recorded attestation with a tampered payload, flips a bit in pcr0
+        let mut tampered = attestation.clone();
+        let offset = tampered
+            .windows(3)
+            .position(|x| x == [0x00, 0x58, 0x30])
+            .unwrap();
+        tampered[offset + 3] ^= 1;
+        assert!(matches!(
+            verify::<B>(&tampered, &aws, &FixedClock(timestamp)),
+            Err(AttestationError::VerifyFailed(e)) if e == "signature"
+        ));
+
+        // recorded attestation with a tampered signature
+        let mut tampered = attestation.clone();
+        let len = tampered.len();
+        tampered[len - 1] ^= 1;
+        assert!(matches!(
+            verify::<B>(&tampered, &aws, &FixedClock(timestamp)),
+            Err(AttestationError::VerifyFailed(e)) if e == "signature"
+        ));
+
+        // mock attestation
+        let attestation = mock();
+        verify::<B>(&attestation, &oyster_mock, &SystemClock).unwrap();
+        assert!(matches!(
+            verify::<B>(&attestation, &aws, &SystemClock),
+            Err(AttestationError::VerifyFailed(e)) if e == "root"
+        ));
+
+        // path validation, root -> intermediate -> enclave certificate
+        let ca = vec![
+            test_extension(
+                true,
+                &BasicConstraints {
+                    ca: true,
+                    path_len_constraint: None,
+                },
+            ),
+            test_extension(true, &KeyUsage(KeyUsages::KeyCertSign.into())),
+        ];
+        let vector = |intermediate, not_after| {
+            let keys: Vec<_> = (0..3).map(|_| SigningKey::random(&mut OsRng)).collect();
+            let root = test_certificate(
+                "CN=root",
+                &keys[2],
+                "CN=root",
+                &keys[2],
+                u32::MAX.into(),
+                ca.clone(),
+            );
+            let attestation = AttestationBuilder::new()
+                .certificate(test_certificate(
+                    "CN=leaf",
+                    &keys[0],
+                    "CN=intermediate",
+                    &keys[1],
+                    u32::MAX.into(),
+                    vec![],
+                ))
+                .cabundle([
+                    root.clone(),
+                    test_certificate(
+                        "CN=intermediate",
+                        &keys[1],
+                        "CN=root",
+                        &keys[2],
+                        not_after,
+                        intermediate,
+                    ),
+                ])
+                .sign(&keys[0])
+                .unwrap();
+            verify::<B>(
+                &attestation,
+                &TrustAnchors::from_der(&root).unwrap(),
+                &SystemClock,
+            )
+        };
+        vector(ca.clone(), u32::MAX.into()).unwrap();
+
+        // leaf used as a CA
+        let leaf = vec![
+            test_extension(
+                true,
+                &BasicConstraints {
+                    ca: false,
+                    path_len_constraint: None,
+                },
+            ),
+            test_extension(true, &KeyUsage(KeyUsages::DigitalSignature.into())),
+        ];
+        assert!(matches!(
+            vector(leaf, u32::MAX.into()),
+            Err(AttestationError::InvalidPath {
+                depth: 1,
+                violation: PathViolation::NotCa
+            })
+        ));
+
+        // expired intermediate
+        assert!(matches!(
+            vector(ca.clone(), 1),
+            Err(AttestationError::VerifyFailed(e)) if e == "timestamp"
+        ));
+
+        // pem and der parsing
+        let roots = B::pem_to_der(include_bytes!("../aws.cert")).unwrap();
+        assert_eq!(roots, aws.roots());
+        assert_eq!(B::canonical_der(&roots[0]).unwrap(), roots[0]);
+        assert!(matches!(
+            B::pem_to_der(b"not a pem"),
+            Err(AttestationError::ParseFailed(_))
+        ));
+        assert!(matches!(
+            B::canonical_der(b"not a der"),
+            Err(AttestationError::ParseFailed(_))
+        ));
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn test_openssl_backend() {
+        suite::<OpensslBackend>();
+    }
+
+    #[cfg(feature = "rustcrypto")]
+    #[test]
+    fn test_rustcrypto_backend() {
+        suite::<RustCryptoBackend>();
+    }
+}
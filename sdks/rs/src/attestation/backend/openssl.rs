@@ -0,0 +1,133 @@
+use aws_nitro_enclaves_cose::crypto::Openssl;
+use aws_nitro_enclaves_cose::CoseSign1;
+use openssl::asn1::Asn1Time;
+use openssl::x509::{X509VerifyResult, X509};
+use serde_bytes::ByteBuf;
+
+use super::path::{certificates, validate_path};
+use super::Backend;
+use crate::attestation::{AttestationError, Clock, TrustAnchors};
+
+/// Verification backed by openssl
+pub(crate) struct OpensslBackend;
+
+fn get_all_certs(cert: X509, cabundle: &[ByteBuf]) -> Result<Vec<X509>, AttestationError> {
+    let mut all_certs = vec![cert];
+    // cabundle is ordered from the root, walk it from the issuer of the leaf instead
+    for cert in cabundle.iter().rev() {
+        let cert =
+            X509::from_der(cert).map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
+        all_certs.push(cert);
+    }
+    Ok(all_certs)
+}
+
+fn verify_cert_chain(
+    cert: X509,
+    cabundle: &[ByteBuf],
+    trust_anchors: &TrustAnchors,
+    clock: &dyn Clock,
+) -> Result<(), AttestationError> {
+    let certs = get_all_certs(cert, cabundle)?;
+
+    let current_time = i64::try_from(clock.now_millis() / 1000)
+        .map_err(|e| AttestationError::ParseFailed(format!("current time: {e}")))?;
+    let current_time = Asn1Time::from_unix(current_time)
+        .map_err(|e| AttestationError::ParseFailed(e.to_string()))?;
+
+    for i in 0..(certs.len() - 1) {
+        let pubkey = certs[i + 1]
+            .public_key()
+            .map_err(|e| AttestationError::ParseFailed(format!("pubkey: {e}")))?;
+        if !certs[i]
+            .verify(&pubkey)
+            .map_err(|e| AttestationError::ParseFailed(format!("signature: {e}")))?
+        {
+            return Err(AttestationError::VerifyFailed("signature".into()));
+        }
+        if certs[i + 1].issued(&certs[i]) != X509VerifyResult::OK {
+            return Err(AttestationError::VerifyFailed("issuer or subject".into()));
+        }
+        if certs[i].not_after() < current_time || certs[i].not_before() > current_time {
+            return Err(AttestationError::VerifyFailed("timestamp".into()));
+        }
+    }
+
+    let root_cert = certs
+        .last()
+        .ok_or(AttestationError::ParseFailed("root".into()))?
+        .to_der()
+        .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
+    if !trust_anchors.contains(&root_cert) {
+        return Err(AttestationError::VerifyFailed("root".into()));
+    }
+    Ok(())
+}
+
+fn verify_signature_and_cert_chain(
+    cosesign1: &CoseSign1,
+    enclave_certificate: &[u8],
+    cabundle: &[ByteBuf],
+    trust_anchors: &TrustAnchors,
+    clock: &dyn Clock,
+) -> Result<(), AttestationError> {
+    // verify attestation doc signature
+    let enclave_der = enclave_certificate;
+    let enclave_certificate = X509::from_der(enclave_certificate)
+        .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
+    let pub_key = enclave_certificate
+        .public_key()
+        .map_err(|e| AttestationError::ParseFailed(format!("pubkey: {e}")))?;
+    let verify_result = cosesign1
+        .verify_signature::<Openssl>(&pub_key)
+        .map_err(|e| AttestationError::ParseFailed(format!("signature: {e}")))?;
+
+    if !verify_result {
+        return Err(AttestationError::VerifyFailed("signature".into()));
+    }
+
+    // verify certificate chain
+    validate_path(&certificates(enclave_der, cabundle)?)?;
+    verify_cert_chain(enclave_certificate, cabundle, trust_anchors, clock)?;
+
+    Ok(())
+}
+
+impl Backend for OpensslBackend {
+    fn verify_signature_and_cert_chain(
+        cosesign1: &CoseSign1,
+        enclave_certificate: &[u8],
+        cabundle: &[ByteBuf],
+        trust_anchors: &TrustAnchors,
+        clock: &dyn Clock,
+    ) -> Result<(), AttestationError> {
+        verify_signature_and_cert_chain(
+            cosesign1,
+            enclave_certificate,
+            cabundle,
+            trust_anchors,
+            clock,
+        )
+    }
+
+    fn pem_to_der(pem: &[u8]) -> Result<Vec<Vec<u8>>, AttestationError> {
+        let certs = X509::stack_from_pem(pem)
+            .map_err(|e| AttestationError::ParseFailed(format!("pem: {e}")))?;
+        if certs.is_empty() {
+            return Err(AttestationError::ParseFailed("pem: no certificates".into()));
+        }
+        certs
+            .iter()
+            .map(|cert| {
+                cert.to_der()
+                    .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))
+            })
+            .collect()
+    }
+
+    fn canonical_der(der: &[u8]) -> Result<Vec<u8>, AttestationError> {
+        X509::from_der(der)
+            .and_then(|cert| cert.to_der())
+            .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))
+    }
+}
@@ -0,0 +1,365 @@
+// Structural X.509 path validation, RFC 5280 section 6.1
+//
+// Signatures, issuer/subject linkage and validity periods are checked by the
+// backends, this covers the constraints on what each certificate in the path
+// is allowed to do. Name constraints and certificate policies are not
+// supported, certificates marking them critical are rejected.
+
+use std::collections::BTreeSet;
+
+use serde_bytes::ByteBuf;
+use x509_cert::der::oid::{AssociatedOid, ObjectIdentifier};
+use x509_cert::der::Decode;
+use x509_cert::ext::pkix::{
+    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAltName,
+    SubjectKeyIdentifier,
+};
+use x509_cert::{Certificate, Version};
+
+use crate::attestation::AttestationError;
+
+/// Violation of the X.509 path validation rules by a certificate in the chain
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PathViolation {
+    /// issues certificates without being a CA in its basic constraints
+    #[error("not a ca")]
+    NotCa,
+    /// issues certificates without keyCertSign in its key usage
+    #[error("key usage does not allow signing certificates")]
+    NoCertSign,
+    /// signs the attestation without digitalSignature in its key usage
+    #[error("key usage does not allow digital signatures")]
+    NoDigitalSignature,
+    /// more intermediates below it than its path length constraint allows
+    #[error("path length constraint of {max} exceeded by {len}")]
+    PathLength { max: u8, len: usize },
+    /// critical extension which is not processed
+    #[error("unsupported critical extension {0}")]
+    CriticalExtension(String),
+    /// extension which appears more than once
+    #[error("duplicate extension {0}")]
+    DuplicateExtension(String),
+}
+
+// extensions which are either enforced below or carry no constraints on the path
+const SUPPORTED: [ObjectIdentifier; 6] = [
+    BasicConstraints::OID,
+    KeyUsage::OID,
+    ExtendedKeyUsage::OID,
+    SubjectKeyIdentifier::OID,
+    AuthorityKeyIdentifier::OID,
+    SubjectAltName::OID,
+];
+
+/// Parse the enclave certificate and the cabundle into a path, ordered from the
+/// enclave certificate at depth 0 to the root
+pub(crate) fn certificates(
+    enclave_certificate: &[u8],
+    cabundle: &[ByteBuf],
+) -> Result<Vec<Certificate>, AttestationError> {
+    // cabundle is ordered from the root, walk it from the issuer of the leaf instead
+    std::iter::once(enclave_certificate)
+        .chain(cabundle.iter().rev().map(|cert| cert.as_slice()))
+        .map(|cert| {
+            Certificate::from_der(cert)
+                .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))
+        })
+        .collect()
+}
+
+/// Check basic constraints, key usage, path length and critical extensions of every
+/// certificate in a path ordered from the enclave certificate to the root
+///
+/// The root is held to the same constraints as the other CAs in the path.
+pub(crate) fn validate_path(certs: &[Certificate]) -> Result<(), AttestationError> {
+    for (depth, cert) in certs.iter().enumerate() {
+        let tbs = &cert.tbs_certificate;
+        let violation = |violation| AttestationError::InvalidPath { depth, violation };
+        let parse_err =
+            |e: x509_cert::der::Error| AttestationError::ParseFailed(format!("der: {e}"));
+
+        let mut seen = BTreeSet::new();
+        for extension in tbs.extensions.iter().flatten() {
+            if !seen.insert(extension.extn_id) {
+                return Err(violation(PathViolation::DuplicateExtension(
+                    extension.extn_id.to_string(),
+                )));
+            }
+            if extension.critical && !SUPPORTED.contains(&extension.extn_id) {
+                return Err(violation(PathViolation::CriticalExtension(
+                    extension.extn_id.to_string(),
+                )));
+            }
+        }
+
+        let key_usage = tbs.get::<KeyUsage>().map_err(parse_err)?.map(|x| x.1);
+        if depth == 0 {
+            // the enclave certificate signs the attestation
+            if key_usage.is_some_and(|x| !x.digital_signature()) {
+                return Err(violation(PathViolation::NoDigitalSignature));
+            }
+            continue;
+        }
+
+        // everything above the enclave certificate issues certificates,
+        // basic constraints are a v3 extension so earlier versions can never be CAs
+        let basic_constraints = tbs
+            .get::<BasicConstraints>()
+            .map_err(parse_err)?
+            .map(|x| x.1)
+            .filter(|x| x.ca && tbs.version == Version::V3)
+            .ok_or_else(|| violation(PathViolation::NotCa))?;
+        if key_usage.is_some_and(|x| !x.key_cert_sign()) {
+            return Err(violation(PathViolation::NoCertSign));
+        }
+
+        // intermediates between this certificate and the enclave certificate,
+        // self-issued ones do not count towards the limit
+        if let Some(max) = basic_constraints.path_len_constraint {
+            let len = certs[1..depth]
+                .iter()
+                .filter(|x| x.tbs_certificate.issuer != x.tbs_certificate.subject)
+                .count();
+            if len > max as usize {
+                return Err(violation(PathViolation::PathLength { max, len }));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use p384::elliptic_curve::rand_core::OsRng;
+    use x509_cert::der::asn1::OctetString;
+    use x509_cert::ext::pkix::KeyUsages;
+    use x509_cert::ext::Extension;
+
+    use super::*;
+    use crate::attestation::builder::{mock_attestation, test_certificate, test_extension};
+    use crate::attestation::{AttestationChain, AttestationDocument, SigningKey};
+
+    // far enough in the future for the vectors to never expire
+    const NOT_AFTER: u64 = 253402300799;
+
+    fn ca(path_len_constraint: Option<u8>) -> Vec<Extension> {
+        vec![
+            test_extension(
+                true,
+                &BasicConstraints {
+                    ca: true,
+                    path_len_constraint,
+                },
+            ),
+            test_extension(true, &KeyUsage(KeyUsages::KeyCertSign | KeyUsages::CRLSign)),
+        ]
+    }
+
+    fn leaf() -> Vec<Extension> {
+        vec![
+            test_extension(
+                true,
+                &BasicConstraints {
+                    ca: false,
+                    path_len_constraint: None,
+                },
+            ),
+            test_extension(true, &KeyUsage(KeyUsages::DigitalSignature.into())),
+        ]
+    }
+
+    // path from the enclave certificate up to a self-signed root, one extension set per certificate
+    fn path(extensions: Vec<Vec<Extension>>) -> Result<(), AttestationError> {
+        let keys: Vec<_> = extensions
+            .iter()
+            .map(|_| SigningKey::random(&mut OsRng))
+            .collect();
+        let names: Vec<_> = (0..extensions.len()).map(|i| format!("CN={i}")).collect();
+        let certs: Vec<_> = extensions
+            .into_iter()
+            .enumerate()
+            .map(|(i, extensions)| {
+                let issuer = (i + 1).min(keys.len() - 1);
+                let der = test_certificate(
+                    &names[i],
+                    &keys[i],
+                    &names[issuer],
+                    &keys[issuer],
+                    NOT_AFTER,
+                    extensions,
+                );
+                Certificate::from_der(&der).unwrap()
+            })
+            .collect();
+        validate_path(&certs)
+    }
+
+    fn violation(depth: usize, violation: PathViolation) -> Result<(), AttestationError> {
+        Err(AttestationError::InvalidPath { depth, violation })
+    }
+
+    fn assert_path(result: Result<(), AttestationError>, expected: Result<(), AttestationError>) {
+        assert_eq!(format!("{result:?}"), format!("{expected:?}"));
+    }
+
+    #[test]
+    fn test_valid_paths() {
+        // recorded aws path, with path length constraints on every intermediate
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let doc = AttestationDocument::from_cose(&attestation).unwrap();
+        let certs = certificates(&doc.certificate, &doc.cabundle).unwrap();
+        assert_eq!(certs.len(), 5);
+        validate_path(&certs).unwrap();
+
+        // mock path
+        let doc = AttestationDocument::from_cose(&mock_attestation(None, None, None)).unwrap();
+        validate_path(&certificates(&doc.certificate, &doc.cabundle).unwrap()).unwrap();
+
+        // generated path
+        let chain = AttestationChain::generate().unwrap();
+        let cabundle = [ByteBuf::from(chain.root.clone())];
+        validate_path(&certificates(&chain.leaf, &cabundle).unwrap()).unwrap();
+
+        assert_path(
+            path(vec![leaf(), ca(Some(1)), ca(Some(1)), ca(None)]),
+            Ok(()),
+        );
+        // extensions are optional on the enclave certificate
+        assert_path(path(vec![vec![], ca(Some(0))]), Ok(()));
+        // non-critical extensions are ignored
+        let mut extensions = leaf();
+        extensions.push(Extension {
+            extn_id: ObjectIdentifier::new_unwrap("1.3.6.1.4.1.4128.1"),
+            critical: false,
+            extn_value: OctetString::new([0x05, 0x00]).unwrap(),
+        });
+        assert_path(path(vec![extensions, ca(None)]), Ok(()));
+
+        assert!(matches!(
+            certificates(b"not a der", &[]),
+            Err(AttestationError::ParseFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_basic_constraints() {
+        // leaf used as a CA
+        assert_path(
+            path(vec![leaf(), leaf(), ca(None)]),
+            violation(1, PathViolation::NotCa),
+        );
+        // intermediate without basic constraints
+        assert_path(
+            path(vec![
+                leaf(),
+                vec![test_extension(
+                    true,
+                    &KeyUsage(KeyUsages::KeyCertSign.into()),
+                )],
+                ca(None),
+            ]),
+            violation(1, PathViolation::NotCa),
+        );
+        // the root is held to the same constraints
+        assert_path(
+            path(vec![leaf(), ca(None), vec![]]),
+            violation(2, PathViolation::NotCa),
+        );
+    }
+
+    #[test]
+    fn test_key_usage() {
+        // CA without keyCertSign
+        let mut extensions = ca(None);
+        extensions[1] = test_extension(true, &KeyUsage(KeyUsages::DigitalSignature.into()));
+        assert_path(
+            path(vec![leaf(), extensions, ca(None)]),
+            violation(1, PathViolation::NoCertSign),
+        );
+
+        // enclave certificate without digitalSignature
+        let mut extensions = leaf();
+        extensions[1] = test_extension(true, &KeyUsage(KeyUsages::KeyEncipherment.into()));
+        assert_path(
+            path(vec![extensions, ca(None)]),
+            violation(0, PathViolation::NoDigitalSignature),
+        );
+    }
+
+    #[test]
+    fn test_path_length() {
+        assert_path(
+            path(vec![leaf(), ca(None), ca(Some(0))]),
+            violation(2, PathViolation::PathLength { max: 0, len: 1 }),
+        );
+        assert_path(
+            path(vec![leaf(), ca(Some(0)), ca(Some(1)), ca(Some(1))]),
+            violation(3, PathViolation::PathLength { max: 1, len: 2 }),
+        );
+
+        // self-issued intermediates, e.g. from key rollover, do not count
+        let keys: Vec<_> = (0..3).map(|_| SigningKey::random(&mut OsRng)).collect();
+        let certs: Vec<_> = [
+            test_certificate("CN=leaf", &keys[0], "CN=root", &keys[1], NOT_AFTER, leaf()),
+            test_certificate(
+                "CN=root",
+                &keys[1],
+                "CN=root",
+                &keys[2],
+                NOT_AFTER,
+                ca(None),
+            ),
+            test_certificate(
+                "CN=root",
+                &keys[2],
+                "CN=root",
+                &keys[2],
+                NOT_AFTER,
+                ca(Some(0)),
+            ),
+        ]
+        .iter()
+        .map(|der| Certificate::from_der(der).unwrap())
+        .collect();
+        validate_path(&certs).unwrap();
+    }
+
+    #[test]
+    fn test_extensions() {
+        // name constraints are not supported
+        let mut extensions = ca(None);
+        extensions.push(Extension {
+            extn_id: ObjectIdentifier::new_unwrap("2.5.29.30"),
+            critical: true,
+            extn_value: OctetString::new([0x30, 0x00]).unwrap(),
+        });
+        assert_path(
+            path(vec![leaf(), extensions, ca(None)]),
+            violation(1, PathViolation::CriticalExtension("2.5.29.30".into())),
+        );
+
+        // unknown critical extensions fail even on the enclave certificate
+        let mut extensions = leaf();
+        extensions.push(Extension {
+            extn_id: ObjectIdentifier::new_unwrap("1.3.6.1.4.1.4128.1"),
+            critical: true,
+            extn_value: OctetString::new([0x05, 0x00]).unwrap(),
+        });
+        assert_path(
+            path(vec![extensions, ca(None)]),
+            violation(
+                0,
+                PathViolation::CriticalExtension("1.3.6.1.4.1.4128.1".into()),
+            ),
+        );
+
+        // duplicates, even if they agree
+        let mut extensions = ca(None);
+        extensions.push(extensions[0].clone());
+        assert_path(
+            path(vec![leaf(), extensions]),
+            violation(1, PathViolation::DuplicateExtension("2.5.29.19".into())),
+        );
+    }
+}
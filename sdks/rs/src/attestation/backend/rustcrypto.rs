@@ -0,0 +1,200 @@
+use aws_nitro_enclaves_cose::crypto::{MessageDigest, SignatureAlgorithm, SigningPublicKey};
+use aws_nitro_enclaves_cose::error::CoseError;
+use aws_nitro_enclaves_cose::CoseSign1;
+use p384::ecdsa::signature::hazmat::PrehashVerifier;
+use p384::ecdsa::signature::Verifier;
+use p384::ecdsa::{Signature, VerifyingKey};
+use serde_bytes::ByteBuf;
+use x509_cert::der::asn1::ObjectIdentifier;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::ext::pkix::{AuthorityKeyIdentifier, KeyUsage, KeyUsages, SubjectKeyIdentifier};
+use x509_cert::Certificate;
+
+use super::path::{certificates, validate_path};
+use super::{Backend, Sha2};
+use crate::attestation::{AttestationError, Clock, TrustAnchors};
+
+// ecdsa-with-SHA384, the only algorithm used by nitro attestation chains
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+
+/// Verification backed by pure Rust RustCrypto crates
+///
+/// Only supports P-384 keys and ecdsa-with-SHA384 signatures, which is all
+/// nitro attestations use.
+pub(crate) struct RustCryptoBackend;
+
+// COSE verification key wrapping a P-384 public key
+struct CoseKey(VerifyingKey);
+
+impl SigningPublicKey for CoseKey {
+    fn get_parameters(&self) -> Result<(SignatureAlgorithm, MessageDigest), CoseError> {
+        Ok((SignatureAlgorithm::ES384, MessageDigest::Sha384))
+    }
+
+    fn verify(&self, digest: &[u8], signature: &[u8]) -> Result<bool, CoseError> {
+        // COSE signatures are r || s, anything else is simply an invalid signature
+        let Ok(signature) = Signature::from_slice(signature) else {
+            return Ok(false);
+        };
+        Ok(self.0.verify_prehash(digest, &signature).is_ok())
+    }
+}
+
+fn parse_cert(der: &[u8]) -> Result<Certificate, AttestationError> {
+    Certificate::from_der(der).map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))
+}
+
+fn public_key(cert: &Certificate) -> Result<VerifyingKey, AttestationError> {
+    let key = cert
+        .tbs_certificate
+        .subject_public_key_info
+        .subject_public_key
+        .as_bytes()
+        .ok_or(AttestationError::ParseFailed("pubkey: unaligned".into()))?;
+    VerifyingKey::from_sec1_bytes(key)
+        .map_err(|e| AttestationError::ParseFailed(format!("pubkey: {e}")))
+}
+
+// signature of the child over its tbs certificate using the key of the parent
+fn verify_cert_signature(
+    cert: &Certificate,
+    parent: &Certificate,
+) -> Result<bool, AttestationError> {
+    if cert.signature_algorithm.oid != ECDSA_WITH_SHA384 {
+        return Err(AttestationError::ParseFailed(format!(
+            "signature: unsupported algorithm {}",
+            cert.signature_algorithm.oid
+        )));
+    }
+    let pubkey = public_key(parent)?;
+    let tbs = cert
+        .tbs_certificate
+        .to_der()
+        .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
+    let signature = cert
+        .signature
+        .as_bytes()
+        .ok_or(AttestationError::ParseFailed("signature: unaligned".into()))?;
+    // malformed signatures fail verification like openssl does
+    let Ok(signature) = Signature::from_der(signature) else {
+        return Ok(false);
+    };
+    Ok(pubkey.verify(&tbs, &signature).is_ok())
+}
+
+// mirrors openssl's X509_check_issued, subject/issuer names, key identifiers and key usage
+fn issued(parent: &Certificate, cert: &Certificate) -> Result<bool, AttestationError> {
+    let parse_err = |e: x509_cert::der::Error| AttestationError::ParseFailed(format!("der: {e}"));
+
+    if parent.tbs_certificate.subject != cert.tbs_certificate.issuer {
+        return Ok(false);
+    }
+
+    let akid = cert
+        .tbs_certificate
+        .get::<AuthorityKeyIdentifier>()
+        .map_err(parse_err)?;
+    let skid = parent
+        .tbs_certificate
+        .get::<SubjectKeyIdentifier>()
+        .map_err(parse_err)?;
+    if let (Some((_, akid)), Some((_, skid))) = (akid, skid) {
+        if akid.key_identifier.is_some_and(|x| x != skid.0) {
+            return Ok(false);
+        }
+    }
+
+    let key_usage = parent
+        .tbs_certificate
+        .get::<KeyUsage>()
+        .map_err(parse_err)?;
+    if let Some((_, key_usage)) = key_usage {
+        if !key_usage.0.contains(KeyUsages::KeyCertSign) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn verify_cert_chain(
+    certs: &[Certificate],
+    trust_anchors: &TrustAnchors,
+    clock: &dyn Clock,
+) -> Result<(), AttestationError> {
+    validate_path(certs)?;
+
+    let current_time = clock.now_millis() / 1000;
+
+    for i in 0..(certs.len() - 1) {
+        if !verify_cert_signature(&certs[i], &certs[i + 1])? {
+            return Err(AttestationError::VerifyFailed("signature".into()));
+        }
+        if !issued(&certs[i + 1], &certs[i])? {
+            return Err(AttestationError::VerifyFailed("issuer or subject".into()));
+        }
+        let validity = &certs[i].tbs_certificate.validity;
+        if validity.not_after.to_unix_duration().as_secs() < current_time
+            || validity.not_before.to_unix_duration().as_secs() > current_time
+        {
+            return Err(AttestationError::VerifyFailed("timestamp".into()));
+        }
+    }
+
+    let root_cert = certs
+        .last()
+        .ok_or(AttestationError::ParseFailed("root".into()))?
+        .to_der()
+        .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))?;
+    if !trust_anchors.contains(&root_cert) {
+        return Err(AttestationError::VerifyFailed("root".into()));
+    }
+    Ok(())
+}
+
+impl Backend for RustCryptoBackend {
+    fn verify_signature_and_cert_chain(
+        cosesign1: &CoseSign1,
+        enclave_certificate: &[u8],
+        cabundle: &[ByteBuf],
+        trust_anchors: &TrustAnchors,
+        clock: &dyn Clock,
+    ) -> Result<(), AttestationError> {
+        // verify attestation doc signature
+        let certs = certificates(enclave_certificate, cabundle)?;
+        let pub_key = CoseKey(public_key(&certs[0])?);
+        let verify_result = cosesign1
+            .verify_signature::<Sha2>(&pub_key)
+            .map_err(|e| AttestationError::ParseFailed(format!("signature: {e}")))?;
+
+        if !verify_result {
+            return Err(AttestationError::VerifyFailed("signature".into()));
+        }
+
+        // verify certificate chain
+        verify_cert_chain(&certs, trust_anchors, clock)?;
+
+        Ok(())
+    }
+
+    fn pem_to_der(pem: &[u8]) -> Result<Vec<Vec<u8>>, AttestationError> {
+        let certs = Certificate::load_pem_chain(pem)
+            .map_err(|e| AttestationError::ParseFailed(format!("pem: {e}")))?;
+        if certs.is_empty() {
+            return Err(AttestationError::ParseFailed("pem: no certificates".into()));
+        }
+        certs
+            .iter()
+            .map(|cert| {
+                cert.to_der()
+                    .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))
+            })
+            .collect()
+    }
+
+    fn canonical_der(der: &[u8]) -> Result<Vec<u8>, AttestationError> {
+        parse_cert(der)?
+            .to_der()
+            .map_err(|e| AttestationError::ParseFailed(format!("der: {e}")))
+    }
+}
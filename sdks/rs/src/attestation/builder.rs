@@ -0,0 +1,457 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use aws_nitro_enclaves_cose::crypto::{
+    MessageDigest, SignatureAlgorithm, SigningPrivateKey, SigningPublicKey,
+};
+use aws_nitro_enclaves_cose::error::CoseError;
+use aws_nitro_enclaves_cose::header_map::HeaderMap;
+use aws_nitro_enclaves_cose::CoseSign1;
+use p384::ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier};
+use p384::ecdsa::{DerSignature, Signature};
+use p384::elliptic_curve::rand_core::OsRng;
+use serde_bytes::ByteBuf;
+use x509_cert::builder::{Builder, CertificateBuilder, Profile};
+use x509_cert::der::asn1::GeneralizedTime;
+use x509_cert::der::Encode;
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
+use x509_cert::time::{Time, Validity};
+
+pub use p384::ecdsa::SigningKey;
+
+use super::backend::Sha2;
+use super::{AttestationDocument, AttestationError, Clock, SystemClock, TrustAnchors};
+
+// COSE signing key wrapping a P-384 private key
+struct CoseSigningKey<'a>(&'a SigningKey);
+
+impl SigningPublicKey for CoseSigningKey<'_> {
+    fn get_parameters(&self) -> Result<(SignatureAlgorithm, MessageDigest), CoseError> {
+        Ok((SignatureAlgorithm::ES384, MessageDigest::Sha384))
+    }
+
+    fn verify(&self, digest: &[u8], signature: &[u8]) -> Result<bool, CoseError> {
+        let Ok(signature) = Signature::from_slice(signature) else {
+            return Ok(false);
+        };
+        Ok(self
+            .0
+            .verifying_key()
+            .verify_prehash(digest, &signature)
+            .is_ok())
+    }
+}
+
+impl SigningPrivateKey for CoseSigningKey<'_> {
+    fn sign(&self, digest: &[u8]) -> Result<Vec<u8>, CoseError> {
+        let signature: Signature = self
+            .0
+            .sign_prehash(digest)
+            .map_err(|e| CoseError::SignatureError(Box::new(e)))?;
+        // COSE signatures are r || s
+        Ok(signature.to_bytes().to_vec())
+    }
+}
+
+/// Builds signed attestations, e.g. to mint realistic documents in tests and mock servers
+///
+/// Defaults to 16 zeroed pcrs, as in debug mode, and the current time as the timestamp.
+///
+/// ```ignore
+/// let chain = AttestationChain::generate()?;
+/// let attestation = chain
+///     .builder()
+///     .pcr(0, [1u8; 48])
+///     .public_key(key)
+///     .sign(&chain.leaf_key)?;
+/// let doc = AttestationPolicy::new()
+///     .trust_anchors(chain.trust_anchors())
+///     .verify(&attestation)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AttestationBuilder {
+    module_id: String,
+    timestamp: Option<u64>,
+    pcrs: BTreeMap<usize, Vec<u8>>,
+    certificate: Vec<u8>,
+    cabundle: Vec<Vec<u8>>,
+    public_key: Option<Vec<u8>>,
+    user_data: Option<Vec<u8>>,
+    nonce: Option<Vec<u8>>,
+}
+
+impl Default for AttestationBuilder {
+    fn default() -> Self {
+        Self {
+            module_id: "i-0000000000000000-enc0000000000000000".into(),
+            timestamp: None,
+            pcrs: (0..16).map(|i| (i, vec![0u8; 48])).collect(),
+            certificate: Vec::new(),
+            cabundle: Vec::new(),
+            public_key: None,
+            user_data: None,
+            nonce: None,
+        }
+    }
+}
+
+impl AttestationBuilder {
+    /// Builder with the default module id and pcrs, without any certificates
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Module id of the issuing enclave
+    pub fn module_id(mut self, module_id: impl Into<String>) -> Self {
+        self.module_id = module_id.into();
+        self
+    }
+
+    /// Timestamp in milliseconds since the unix epoch, defaults to the time of signing
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Set the value of a pcr, values need not be 48 bytes long
+    pub fn pcr(mut self, index: usize, value: impl Into<Vec<u8>>) -> Self {
+        self.pcrs.insert(index, value.into());
+        self
+    }
+
+    /// Remove a pcr from the document
+    pub fn without_pcr(mut self, index: usize) -> Self {
+        self.pcrs.remove(&index);
+        self
+    }
+
+    /// DER encoded enclave certificate, its key has to sign the attestation
+    pub fn certificate(mut self, certificate: impl Into<Vec<u8>>) -> Self {
+        self.certificate = certificate.into();
+        self
+    }
+
+    /// DER encoded certificates, ordered from the root to the issuer of the enclave certificate
+    pub fn cabundle<C: Into<Vec<u8>>>(mut self, cabundle: impl IntoIterator<Item = C>) -> Self {
+        self.cabundle = cabundle.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Public key to be included in the attestation
+    pub fn public_key(mut self, public_key: impl Into<Vec<u8>>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    /// User data to be included in the attestation
+    pub fn user_data(mut self, user_data: impl Into<Vec<u8>>) -> Self {
+        self.user_data = Some(user_data.into());
+        self
+    }
+
+    /// Nonce to be included in the attestation
+    pub fn nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Document as it would be signed, with a missing timestamp taken from the clock
+    pub fn document(&self, clock: &dyn Clock) -> AttestationDocument {
+        AttestationDocument {
+            module_id: self.module_id.clone(),
+            digest: "SHA384".into(),
+            timestamp: self.timestamp.unwrap_or_else(|| clock.now_millis()),
+            pcrs: self
+                .pcrs
+                .iter()
+                .map(|(index, value)| (*index, ByteBuf::from(value.clone())))
+                .collect(),
+            certificate: ByteBuf::from(self.certificate.clone()),
+            cabundle: self.cabundle.iter().cloned().map(ByteBuf::from).collect(),
+            public_key: self.public_key.clone().map(ByteBuf::from),
+            user_data: self.user_data.clone().map(ByteBuf::from),
+            nonce: self.nonce.clone().map(ByteBuf::from),
+        }
+    }
+
+    /// Sign the attestation as a COSE_Sign1 document
+    pub fn sign(&self, key: &SigningKey) -> Result<Vec<u8>, AttestationError> {
+        self.sign_at(key, &SystemClock)
+    }
+
+    /// Same as [`AttestationBuilder::sign`], with a missing timestamp taken from the clock
+    pub fn sign_at(
+        &self,
+        key: &SigningKey,
+        clock: &dyn Clock,
+    ) -> Result<Vec<u8>, AttestationError> {
+        let payload = self.document(clock).to_cbor()?;
+
+        CoseSign1::new::<Sha2>(&payload, &HeaderMap::new(), &CoseSigningKey(key))
+            .and_then(|cosesign1| cosesign1.as_bytes(false))
+            .map_err(|e| AttestationError::BuildFailed(format!("cose: {e}")))
+    }
+}
+
+/// Freshly generated self-signed root and a leaf issued by it, both P-384
+///
+/// IMPORTANT: Anyone holding the keys can mint attestations chaining up to the root,
+/// only trust it in tests and local development.
+#[derive(Debug, Clone)]
+pub struct AttestationChain {
+    /// DER encoded root certificate
+    pub root: Vec<u8>,
+    pub root_key: SigningKey,
+    /// DER encoded leaf certificate, signs attestations
+    pub leaf: Vec<u8>,
+    pub leaf_key: SigningKey,
+}
+
+impl AttestationChain {
+    /// Generate fresh keys and certificates, valid from the epoch with no well-defined expiry
+    pub fn generate() -> Result<Self, AttestationError> {
+        let root_key = SigningKey::random(&mut OsRng);
+        let leaf_key = SigningKey::random(&mut OsRng);
+
+        let build_err = |e: x509_cert::builder::Error| {
+            AttestationError::BuildFailed(format!("certificate: {e}"))
+        };
+        let der_err = |e: x509_cert::der::Error| AttestationError::BuildFailed(format!("der: {e}"));
+        let spki = |key: &SigningKey| {
+            SubjectPublicKeyInfoOwned::from_key(*key.verifying_key())
+                .map_err(|e| AttestationError::BuildFailed(format!("spki: {e}")))
+        };
+        // RFC 5280, 99991231235959Z for certificates without a well-defined expiration
+        let validity = Validity {
+            not_before: Time::GeneralTime(
+                GeneralizedTime::from_unix_duration(Duration::ZERO).map_err(der_err)?,
+            ),
+            not_after: Time::GeneralTime(
+                GeneralizedTime::from_unix_duration(Duration::from_secs(253402300799))
+                    .map_err(der_err)?,
+            ),
+        };
+        let root_name = Name::from_str("CN=root").map_err(der_err)?;
+        let leaf_name = Name::from_str("CN=leaf").map_err(der_err)?;
+
+        let root = CertificateBuilder::new(
+            Profile::Root,
+            SerialNumber::from(1u32),
+            validity,
+            root_name.clone(),
+            spki(&root_key)?,
+            &root_key,
+        )
+        .map_err(build_err)?
+        .build::<DerSignature>()
+        .map_err(build_err)?;
+
+        let leaf = CertificateBuilder::new(
+            Profile::Leaf {
+                issuer: root_name,
+                enable_key_agreement: false,
+                enable_key_encipherment: false,
+            },
+            SerialNumber::from(2u32),
+            validity,
+            leaf_name,
+            spki(&leaf_key)?,
+            &root_key,
+        )
+        .map_err(build_err)?
+        .build::<DerSignature>()
+        .map_err(build_err)?;
+
+        Ok(Self {
+            root: root.to_der().map_err(der_err)?,
+            root_key,
+            leaf: leaf.to_der().map_err(der_err)?,
+            leaf_key,
+        })
+    }
+
+    /// Trust anchors with only the root of this chain
+    pub fn trust_anchors(&self) -> TrustAnchors {
+        TrustAnchors::from_der(&self.root).expect("generated root should parse")
+    }
+
+    /// Attestation builder with the certificates of this chain, sign with [`Self::leaf_key`]
+    pub fn builder(&self) -> AttestationBuilder {
+        AttestationBuilder::new()
+            .certificate(self.leaf.clone())
+            .cabundle([self.root.clone()])
+    }
+}
+
+// attestation as generated by the mock backend of `attestation/server`, chains up to `TrustAnchors::oyster_mock`
+#[cfg(test)]
+pub(crate) fn mock_attestation(
+    public_key: Option<&[u8]>,
+    user_data: Option<&[u8]>,
+    nonce: Option<&[u8]>,
+) -> Vec<u8> {
+    let certs = "../../attestation/server/src/certs";
+    let leaf_key = std::fs::read(format!("{certs}/leaf.key")).unwrap();
+    let leaf_key = SigningKey::from(p384::SecretKey::from_sec1_der(&leaf_key).unwrap());
+
+    let mut builder = (0..16)
+        .fold(AttestationBuilder::new(), |builder, i| {
+            builder.pcr(i, [i as u8; 48])
+        })
+        .certificate(std::fs::read(format!("{certs}/leaf.crt")).unwrap())
+        .cabundle([std::fs::read(format!("{certs}/root.crt")).unwrap()]);
+    if let Some(public_key) = public_key {
+        builder = builder.public_key(public_key);
+    }
+    if let Some(user_data) = user_data {
+        builder = builder.user_data(user_data);
+    }
+    if let Some(nonce) = nonce {
+        builder = builder.nonce(nonce);
+    }
+
+    builder.sign(&leaf_key).unwrap()
+}
+
+// certificate with exactly the given extensions for path validation vectors,
+// valid from the epoch until `not_after` in seconds
+#[cfg(test)]
+pub(crate) fn test_certificate(
+    subject: &str,
+    key: &SigningKey,
+    issuer: &str,
+    issuer_key: &SigningKey,
+    not_after: u64,
+    extensions: Vec<x509_cert::ext::Extension>,
+) -> Vec<u8> {
+    use p384::ecdsa::signature::Signer;
+    use x509_cert::der::asn1::{BitString, ObjectIdentifier};
+    use x509_cert::spki::AlgorithmIdentifierOwned;
+    use x509_cert::{Certificate, TbsCertificate, Version};
+
+    let algorithm = AlgorithmIdentifierOwned {
+        // ecdsa-with-SHA384
+        oid: ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3"),
+        parameters: None,
+    };
+    let time = |secs| {
+        Time::GeneralTime(GeneralizedTime::from_unix_duration(Duration::from_secs(secs)).unwrap())
+    };
+    let tbs_certificate = TbsCertificate {
+        version: Version::V3,
+        serial_number: SerialNumber::from(1u32),
+        signature: algorithm.clone(),
+        issuer: Name::from_str(issuer).unwrap(),
+        validity: Validity {
+            not_before: time(0),
+            not_after: time(not_after),
+        },
+        subject: Name::from_str(subject).unwrap(),
+        subject_public_key_info: SubjectPublicKeyInfoOwned::from_key(*key.verifying_key()).unwrap(),
+        issuer_unique_id: None,
+        subject_unique_id: None,
+        extensions: Some(extensions),
+    };
+    let signature: DerSignature = issuer_key.sign(&tbs_certificate.to_der().unwrap());
+
+    Certificate {
+        tbs_certificate,
+        signature_algorithm: algorithm,
+        signature: BitString::from_bytes(signature.as_bytes()).unwrap(),
+    }
+    .to_der()
+    .unwrap()
+}
+
+#[cfg(test)]
+pub(crate) fn test_extension<T: x509_cert::der::oid::AssociatedOid + Encode>(
+    critical: bool,
+    value: &T,
+) -> x509_cert::ext::Extension {
+    x509_cert::ext::Extension {
+        extn_id: T::OID,
+        critical,
+        extn_value: x509_cert::der::asn1::OctetString::new(value.to_der().unwrap()).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attestation::{AttestationPolicy, FixedClock};
+
+    #[test]
+    fn test_build_and_verify() {
+        let chain = AttestationChain::generate().unwrap();
+        let attestation = chain
+            .builder()
+            .module_id("i-0123456789abcdef0-enc0123456789abcdef")
+            .timestamp(1723012689640)
+            .pcr(0, [1u8; 48])
+            .pcr(16, [2u8; 32])
+            .public_key([3u8; 64])
+            .user_data(*b"user data")
+            .nonce(*b"nonce")
+            .sign(&chain.leaf_key)
+            .unwrap();
+
+        let doc = AttestationPolicy::new()
+            .trust_anchors(chain.trust_anchors())
+            .pcr(0, [1u8; 48])
+            .nonce(*b"nonce")
+            .verify_at(&attestation, &FixedClock(1723012689640))
+            .unwrap();
+        assert_eq!(doc.module_id, "i-0123456789abcdef0-enc0123456789abcdef");
+        assert_eq!(doc.timestamp, 1723012689640);
+        assert_eq!(doc.pcrs.len(), 17);
+        assert_eq!(doc.pcr(1).unwrap(), [0u8; 48]);
+        assert_eq!(doc.pcr(16).unwrap(), [2u8; 32]);
+        assert_eq!(doc.public_key.unwrap().as_slice(), [3u8; 64]);
+        assert_eq!(doc.user_data.unwrap().as_slice(), b"user data");
+        assert_eq!(doc.certificate.as_slice(), chain.leaf);
+        assert_eq!(doc.cabundle, vec![ByteBuf::from(chain.root.clone())]);
+
+        // chains are fresh every time
+        let other = AttestationChain::generate().unwrap();
+        assert_ne!(other.root, chain.root);
+        assert!(matches!(
+            AttestationPolicy::new()
+                .trust_anchors(other.trust_anchors())
+                .verify(&attestation),
+            Err(AttestationError::VerifyFailed(e)) if e == "root"
+        ));
+
+        // signed by a key other than the one in the enclave certificate
+        let attestation = chain.builder().sign(&other.leaf_key).unwrap();
+        assert!(matches!(
+            AttestationPolicy::new()
+                .trust_anchors(chain.trust_anchors())
+                .verify(&attestation),
+            Err(AttestationError::VerifyFailed(e)) if e == "signature"
+        ));
+    }
+
+    #[test]
+    fn test_build_timestamp() {
+        let builder = AttestationBuilder::new().without_pcr(15);
+        let doc = builder.document(&FixedClock(1000));
+        assert_eq!(doc.timestamp, 1000);
+        assert_eq!(doc.pcrs.len(), 15);
+        assert!(doc.public_key.is_none());
+
+        let chain = AttestationChain::generate().unwrap();
+        let attestation = chain
+            .builder()
+            .sign_at(&chain.leaf_key, &FixedClock(2000))
+            .unwrap();
+        assert_eq!(
+            AttestationDocument::from_cose(&attestation)
+                .unwrap()
+                .timestamp,
+            2000
+        );
+    }
+}
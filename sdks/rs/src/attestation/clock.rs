@@ -0,0 +1,35 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time used during attestation verification
+///
+/// Both the certificate validity period and the age of the attestation are
+/// checked against it.
+pub trait Clock: Send + Sync {
+    /// milliseconds since the unix epoch
+    fn now_millis(&self) -> u64;
+}
+
+/// Wall clock time of the system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            // clocks before the epoch are treated as the epoch
+            .map_or(0, |x| x.as_millis().try_into().unwrap_or(u64::MAX))
+    }
+}
+
+/// Clock frozen at the given milliseconds since the unix epoch
+///
+/// Useful to verify recorded attestations as of their own timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now_millis(&self) -> u64 {
+        self.0
+    }
+}
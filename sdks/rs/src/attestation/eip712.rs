@@ -0,0 +1,343 @@
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use super::AttestationError;
+
+// keccak256(
+//     abi.encode(
+//         keccak256("EIP712Domain(string name,string version)"),
+//         keccak256("marlin.oyster.AttestationVerifier"),
+//         keccak256("1")
+//     )
+// )
+/// EIP-712 domain separator of the attestation verifier
+pub const DOMAIN_SEPARATOR: [u8; 32] =
+    hex_literal::hex!("0de834feb03c214f785e75b2828ffeceb322312d4487e2fb9640ca5fc32542c7");
+
+// keccak256("Attestation(bytes enclavePubKey,bytes PCR0,bytes PCR1,bytes PCR2,uint256 timestampInMilliseconds)")
+/// EIP-712 typehash of the attestation struct signed by the attestation verifier
+pub const ATTESTATION_TYPEHASH: [u8; 32] =
+    hex_literal::hex!("6889df476ca38f3f4b417c17eb496682eb401b4f41a2259741a78acc481ea805");
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// EIP-712 digest of an attestation as signed by the attestation verifier
+pub fn compute_digest(
+    enclave_pubkey: &[u8],
+    pcr0: &[u8],
+    pcr1: &[u8],
+    pcr2: &[u8],
+    timestamp: u64,
+) -> [u8; 32] {
+    let mut encoded_struct = Vec::with_capacity(32 * 6);
+    encoded_struct.extend_from_slice(&ATTESTATION_TYPEHASH);
+    encoded_struct.extend_from_slice(&keccak256(enclave_pubkey));
+    encoded_struct.extend_from_slice(&keccak256(pcr0));
+    encoded_struct.extend_from_slice(&keccak256(pcr1));
+    encoded_struct.extend_from_slice(&keccak256(pcr2));
+    // uint256, big endian
+    encoded_struct.extend_from_slice(&[0u8; 24]);
+    encoded_struct.extend_from_slice(&timestamp.to_be_bytes());
+
+    let hash_struct = keccak256(&encoded_struct);
+
+    let mut encoded_message = Vec::with_capacity(2 + 32 * 2);
+    encoded_message.extend_from_slice(&[0x19, 0x01]);
+    encoded_message.extend_from_slice(&DOMAIN_SEPARATOR);
+    encoded_message.extend_from_slice(&hash_struct);
+
+    keccak256(&encoded_message)
+}
+
+/// Recover the secp256k1 key which produced a 65 byte `r || s || v` signature over a digest
+///
+/// `v` is 27 or 28 as produced by the attestation verifier, 0 or 1 is accepted too. Signatures
+/// with a high `s` are rejected like on chain. Returns the uncompressed key without the 0x04
+/// prefix, as the verifier reports it.
+pub fn recover_signer(digest: &[u8; 32], signature: &[u8]) -> Result<[u8; 64], AttestationError> {
+    let [rs @ .., v]: &[u8; 65] = signature.try_into().map_err(|_| {
+        AttestationError::ParseFailed(format!(
+            "signature: expected 65 bytes, got {}",
+            signature.len()
+        ))
+    })?;
+    let recovery_id = RecoveryId::from_byte(if *v >= 27 { v - 27 } else { *v })
+        .filter(|x| !x.is_x_reduced())
+        .ok_or_else(|| AttestationError::ParseFailed(format!("signature: invalid v {v}")))?;
+    let signature = Signature::from_slice(rs)
+        .map_err(|e| AttestationError::ParseFailed(format!("signature: {e}")))?;
+    if signature.normalize_s().is_some() {
+        return Err(AttestationError::VerifyFailed("signature".into()));
+    }
+
+    let key = VerifyingKey::recover_from_prehash(digest, &signature, recovery_id)
+        .map_err(|_| AttestationError::VerifyFailed("signature".into()))?;
+    let mut signer = [0u8; 64];
+    signer.copy_from_slice(&key.to_encoded_point(false).as_bytes()[1..]);
+    Ok(signer)
+}
+
+/// Check the signature of an attestation verifier over the given attestation fields,
+/// returns the key of the verifier if it is one of the trusted ones
+pub fn verify_signature(
+    enclave_pubkey: &[u8],
+    pcr0: &[u8],
+    pcr1: &[u8],
+    pcr2: &[u8],
+    timestamp: u64,
+    signature: &[u8],
+    trusted_verifiers: &[[u8; 64]],
+) -> Result<[u8; 64], AttestationError> {
+    let digest = compute_digest(enclave_pubkey, pcr0, pcr1, pcr2, timestamp);
+    let signer = recover_signer(&digest, signature)?;
+    if !trusted_verifiers.contains(&signer) {
+        return Err(AttestationError::VerifyFailed("untrusted verifier".into()));
+    }
+
+    Ok(signer)
+}
+
+/// Response of the attestation verifier, hex encoded as on the wire
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifierResponse {
+    /// `r || s || v` signature over the EIP-712 digest of the fields below
+    pub signature: String,
+    /// public key of the attested enclave
+    pub secp256k1_public: String,
+    pub pcr0: String,
+    pub pcr1: String,
+    pub pcr2: String,
+    /// attestation timestamp in milliseconds
+    pub timestamp: u64,
+    /// key the verifier claims to have signed with, only meaningful once the signature checks out
+    pub verifier_secp256k1_public: String,
+}
+
+/// Attestation fields vouched for by a trusted attestation verifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedResponse {
+    pub enclave_public_key: Vec<u8>,
+    pub pcrs: [[u8; 48]; 3],
+    pub timestamp: u64,
+    /// uncompressed key of the verifier which signed the response, without the 0x04 prefix
+    pub verifier: [u8; 64],
+}
+
+impl VerifierResponse {
+    /// Check the signature against the trusted verifier keys and decode the attested fields
+    pub fn verify(
+        &self,
+        trusted_verifiers: &[[u8; 64]],
+    ) -> Result<VerifiedResponse, AttestationError> {
+        let decode = |name, value: &str| {
+            hex::decode(value).map_err(|e| AttestationError::ParseFailed(format!("{name}: {e}")))
+        };
+        let pcr = |name, value: &str| -> Result<[u8; 48], AttestationError> {
+            decode(name, value)?.try_into().map_err(|x: Vec<u8>| {
+                AttestationError::ParseFailed(format!("{name} not 48 bytes: {}", x.len()))
+            })
+        };
+
+        let enclave_public_key = decode("public key", &self.secp256k1_public)?;
+        let pcrs = [
+            pcr("pcr0", &self.pcr0)?,
+            pcr("pcr1", &self.pcr1)?,
+            pcr("pcr2", &self.pcr2)?,
+        ];
+        let signature = decode("signature", &self.signature)?;
+        let claimed = decode("verifier public key", &self.verifier_secp256k1_public)?;
+
+        let digest = compute_digest(
+            &enclave_public_key,
+            &pcrs[0],
+            &pcrs[1],
+            &pcrs[2],
+            self.timestamp,
+        );
+        let verifier = recover_signer(&digest, &signature)?;
+        if claimed != verifier {
+            return Err(AttestationError::VerifyFailed("verifier public key".into()));
+        }
+        if !trusted_verifiers.contains(&verifier) {
+            return Err(AttestationError::VerifyFailed("untrusted verifier".into()));
+        }
+
+        Ok(VerifiedResponse {
+            enclave_public_key,
+            pcrs,
+            timestamp: self.timestamp,
+            verifier,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+    use k256::elliptic_curve::rand_core::OsRng;
+
+    use super::*;
+
+    // response of `attestation/verifier` for the recorded attestation, see its handler tests
+    fn response() -> VerifierResponse {
+        serde_json::from_str(r#"{
+            "signature": "80836a2534fadf0b1adef2135434207eeecfd360819907e925d469a8179eddad4ef1de22cae8398f84bc8df640feef08a5854c77982639c3a242da1c210f535c1c",
+            "secp256k1_public": "57febcf9e7f5081d3d24182817df526a1c9c3df7e46b64613acd13f9aa53b81de888a8562ba7b4a0e42c48d24d7e444ffcba311ceddb5068eca2ea899379ab50",
+            "pcr0": "5fec1b73727425848d725d68f4a062c634061a035067bd0b9a6dc73e25ed5013dfe7ccbf8a7e9857eceb0841c4cb6ae6",
+            "pcr1": "bcdf05fefccaa8e55bf2c8d6dee9e79bbff31e34bf28a99aa19e6b29c37ee80b214a414b7607236edf26fcb78654e63f",
+            "pcr2": "ae41ca22df64a32d729667160a7f218e59e31586809e121ff2c446a36dc5354ba4e0f74dce737be3298cf82c364692e7",
+            "timestamp": 1723012689640,
+            "verifier_secp256k1_public": "89b14cb02441b6850534580800bd0a33e6ca483a9ea8f0f55de0a99fbf4a4f02a525d6bb48a7a7a80928af68e0d4ad859d699b49538a425cd35403cd1fbdf956"
+        }"#).unwrap()
+    }
+
+    const VERIFIER: [u8; 64] = hex_literal::hex!("89b14cb02441b6850534580800bd0a33e6ca483a9ea8f0f55de0a99fbf4a4f02a525d6bb48a7a7a80928af68e0d4ad859d699b49538a425cd35403cd1fbdf956");
+
+    #[test]
+    fn test_constants() {
+        let mut domain = Vec::new();
+        domain.extend_from_slice(&keccak256(b"EIP712Domain(string name,string version)"));
+        domain.extend_from_slice(&keccak256(b"marlin.oyster.AttestationVerifier"));
+        domain.extend_from_slice(&keccak256(b"1"));
+        assert_eq!(keccak256(&domain), DOMAIN_SEPARATOR);
+
+        assert_eq!(
+            keccak256(b"Attestation(bytes enclavePubKey,bytes PCR0,bytes PCR1,bytes PCR2,uint256 timestampInMilliseconds)"),
+            ATTESTATION_TYPEHASH
+        );
+    }
+
+    #[test]
+    fn test_compute_digest() {
+        let digest = compute_digest(&[1; 64], &[2; 48], &[3; 48], &[4; 48], 1723012689640);
+
+        // timestamp is encoded as a full 256 bit word
+        let mut encoded_struct = ATTESTATION_TYPEHASH.to_vec();
+        for x in [&[1u8; 64][..], &[2; 48], &[3; 48], &[4; 48]] {
+            encoded_struct.extend_from_slice(&keccak256(x));
+        }
+        let mut timestamp = [0u8; 32];
+        timestamp[26..].copy_from_slice(&[0x01, 0x91, 0x2b, 0x8f, 0x2e, 0xe8]);
+        encoded_struct.extend_from_slice(&timestamp);
+        let message = [
+            &[0x19, 0x01][..],
+            &DOMAIN_SEPARATOR,
+            &keccak256(&encoded_struct),
+        ]
+        .concat();
+        assert_eq!(digest, keccak256(&message));
+
+        assert_ne!(
+            digest,
+            compute_digest(&[1; 64], &[2; 48], &[3; 48], &[4; 48], 1723012689641)
+        );
+    }
+
+    #[test]
+    fn test_verifier_response() {
+        let response = response();
+        let verified = response.verify(&[[0; 64], VERIFIER]).unwrap();
+        assert_eq!(verified.verifier, VERIFIER);
+        assert_eq!(verified.timestamp, 1723012689640);
+        assert_eq!(hex::encode(verified.pcrs[0]), response.pcr0);
+        assert_eq!(
+            hex::encode(&verified.enclave_public_key),
+            response.secp256k1_public
+        );
+
+        // not in the trusted list
+        assert!(matches!(
+            response.verify(&[[0; 64]]),
+            Err(AttestationError::VerifyFailed(e)) if e == "untrusted verifier"
+        ));
+
+        // any change to the signed fields recovers some other key
+        let mut tampered = response.clone();
+        tampered.timestamp += 1;
+        assert!(matches!(
+            tampered.verify(&[VERIFIER]),
+            Err(AttestationError::VerifyFailed(e)) if e == "verifier public key"
+        ));
+        let mut tampered = response.clone();
+        tampered.pcr1 = tampered.pcr0.clone();
+        assert!(matches!(
+            tampered.verify(&[VERIFIER]),
+            Err(AttestationError::VerifyFailed(e)) if e == "verifier public key"
+        ));
+
+        let mut malformed = response.clone();
+        malformed.pcr2.truncate(94);
+        assert!(matches!(
+            malformed.verify(&[VERIFIER]),
+            Err(AttestationError::ParseFailed(_))
+        ));
+        let mut malformed = response;
+        malformed.signature.truncate(128);
+        assert!(matches!(
+            malformed.verify(&[VERIFIER]),
+            Err(AttestationError::ParseFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_recover_signer() {
+        let key = SigningKey::random(&mut OsRng);
+        let public: [u8; 64] = key.verifying_key().to_encoded_point(false).as_bytes()[1..]
+            .try_into()
+            .unwrap();
+        let digest = compute_digest(&[1; 64], &[2; 48], &[3; 48], &[4; 48], 1723012689640);
+        let (signature, recovery_id) = key.sign_prehash_recoverable(&digest).unwrap();
+
+        // both v conventions
+        let mut bytes = [
+            signature.to_bytes().as_slice(),
+            &[recovery_id.to_byte() + 27],
+        ]
+        .concat();
+        assert_eq!(recover_signer(&digest, &bytes).unwrap(), public);
+        bytes[64] -= 27;
+        assert_eq!(recover_signer(&digest, &bytes).unwrap(), public);
+        assert_eq!(
+            verify_signature(
+                &[1; 64],
+                &[2; 48],
+                &[3; 48],
+                &[4; 48],
+                1723012689640,
+                &bytes,
+                &[public]
+            )
+            .unwrap(),
+            public
+        );
+        assert!(matches!(
+            verify_signature(&[1; 64], &[2; 48], &[3; 48], &[4; 48], 1723012689640, &bytes, &[VERIFIER]),
+            Err(AttestationError::VerifyFailed(e)) if e == "untrusted verifier"
+        ));
+
+        // malleated signature with a high s
+        let (r, s) = signature.split_scalars();
+        let high = Signature::from_scalars(r, -*s).unwrap();
+        let bytes = [
+            high.to_bytes().as_slice(),
+            &[(recovery_id.to_byte() ^ 1) + 27],
+        ]
+        .concat();
+        assert!(matches!(
+            recover_signer(&digest, &bytes),
+            Err(AttestationError::VerifyFailed(e)) if e == "signature"
+        ));
+
+        assert!(matches!(
+            recover_signer(&digest, &[0; 64]),
+            Err(AttestationError::ParseFailed(_))
+        ));
+        assert!(matches!(
+            recover_signer(&digest, &[[1; 64].as_slice(), &[29]].concat()),
+            Err(AttestationError::ParseFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,471 @@
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connect, HttpConnector};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+
+use super::{AttestationDocument, AttestationError, AttestationPolicy};
+
+/// Attestation fetched by [`FreshAttestationClient`], bound to a fresh nonce
+#[derive(Debug, Clone)]
+pub struct FreshAttestation {
+    /// raw attestation as returned by the server
+    pub attestation: Vec<u8>,
+    /// verified and decoded attestation
+    pub document: AttestationDocument,
+    /// nonce the attestation was requested with
+    pub nonce: [u8; 32],
+}
+
+/// Client fetching attestations from the customizable routes of an `attestation/server`
+/// compatible attestation server
+///
+/// Every request carries a random nonce which the returned attestation has to echo,
+/// tying the attestation to the request instead of accepting any document within
+/// the max age. The attestation is verified against the configured policy.
+///
+/// ```ignore
+/// let fresh = FreshAttestationClient::new()
+///     .policy(AttestationPolicy::new().pcr(0, pcr0).max_age(60000))
+///     .public_key(key)
+///     .timeout(Duration::from_secs(5))
+///     .retries(3)
+///     .fetch(&"http://127.0.0.1:1350".parse()?)
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct FreshAttestationClient<C = HttpConnector> {
+    client: Client<C, Empty<Bytes>>,
+    timeout: Duration,
+    retries: usize,
+    retry_delay: Duration,
+    nonce: Option<[u8; 32]>,
+    public_key: Option<Vec<u8>>,
+    user_data: Option<Vec<u8>>,
+    policy: AttestationPolicy,
+}
+
+impl FreshAttestationClient<HttpConnector> {
+    /// Client over plain http
+    pub fn new() -> Self {
+        Self::with_connector(HttpConnector::new())
+    }
+}
+
+impl Default for FreshAttestationClient<HttpConnector> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> FreshAttestationClient<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Client over a custom connector, e.g. to reach the attestation server over vsock
+    pub fn with_connector(connector: C) -> Self {
+        Self {
+            client: Client::builder(TokioExecutor::new()).build(connector),
+            timeout: Duration::from_secs(10),
+            retries: 0,
+            retry_delay: Duration::from_millis(500),
+            nonce: None,
+            public_key: None,
+            user_data: None,
+            policy: AttestationPolicy::new(),
+        }
+    }
+
+    /// Timeout of each attempt, covering the request and the response body, defaults to 10s
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of retries after a failed attempt, defaults to none
+    ///
+    /// Only transport failures, timeouts and server errors are retried, attestations
+    /// which fail verification are not.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Delay between attempts, defaults to 500ms
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Request every attempt with the given nonce instead of a random one,
+    /// e.g. a challenge handed out by whoever consumes the attestation
+    pub fn nonce(mut self, nonce: [u8; 32]) -> Self {
+        self.nonce = Some(nonce);
+        self
+    }
+
+    /// Public key to be included in the attestation
+    pub fn public_key(mut self, public_key: impl Into<Vec<u8>>) -> Self {
+        self.public_key = Some(public_key.into());
+        self
+    }
+
+    /// User data to be included in the attestation
+    pub fn user_data(mut self, user_data: impl Into<Vec<u8>>) -> Self {
+        self.user_data = Some(user_data.into());
+        self
+    }
+
+    /// Policy the attestation is verified against, the nonce is always added to it
+    ///
+    /// Defaults to a policy with only the signature and certificate chain checked
+    /// against the AWS Nitro Enclaves root.
+    pub fn policy(mut self, policy: AttestationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Fetch and verify a fresh attestation from the server at `endpoint`,
+    /// e.g. `http://127.0.0.1:1350`
+    pub async fn fetch(&self, endpoint: &Uri) -> Result<FreshAttestation, AttestationError> {
+        let mut attempt = 0;
+        loop {
+            match self.fetch_once(endpoint).await {
+                Err(e) if attempt < self.retries && e.is_transient() => {
+                    attempt += 1;
+                    tokio::time::sleep(self.retry_delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn fetch_once(&self, endpoint: &Uri) -> Result<FreshAttestation, AttestationError> {
+        let nonce = self.nonce.unwrap_or_else(rand::random);
+        let uri = self.request_uri(endpoint, &nonce)?;
+
+        let attestation = tokio::time::timeout(self.timeout, async {
+            let res = self.client.get(uri).await?;
+            if !res.status().is_success() {
+                return Err(AttestationError::HttpStatus(res.status()));
+            }
+            Ok(res.collect().await?.to_bytes().to_vec())
+        })
+        .await
+        .map_err(|_| AttestationError::Timeout)??;
+
+        let document = self.policy.clone().nonce(nonce).verify(&attestation)?;
+
+        // the server echoes whatever it was asked to include
+        if document.public_key.as_deref().map(|x| x.as_slice()) != self.public_key.as_deref() {
+            return Err(AttestationError::VerifyFailed("public key".into()));
+        }
+        if document.user_data.as_deref().map(|x| x.as_slice()) != self.user_data.as_deref() {
+            return Err(AttestationError::VerifyFailed("user data".into()));
+        }
+
+        Ok(FreshAttestation {
+            attestation,
+            document,
+            nonce,
+        })
+    }
+
+    fn request_uri(&self, endpoint: &Uri, nonce: &[u8]) -> Result<Uri, AttestationError> {
+        let mut query = format!("nonce={}", hex::encode(nonce));
+        if let Some(public_key) = &self.public_key {
+            query += &format!("&public_key={}", hex::encode(public_key));
+        }
+        if let Some(user_data) = &self.user_data {
+            query += &format!("&user_data={}", hex::encode(user_data));
+        }
+
+        raw_attestation_uri(endpoint, &query)
+    }
+}
+
+/// Uri of the raw attestation route of the server at `endpoint` with the given query,
+/// appended to any path of the endpoint, e.g. `http://proxy/enclave-1` gives
+/// `http://proxy/enclave-1/attestation/raw`
+pub fn raw_attestation_uri(endpoint: &Uri, query: &str) -> Result<Uri, AttestationError> {
+    let prefix = endpoint.path().trim_end_matches('/');
+    let path = match query {
+        "" => format!("{prefix}/attestation/raw"),
+        query => format!("{prefix}/attestation/raw?{query}"),
+    };
+
+    let mut parts = endpoint.clone().into_parts();
+    parts.path_and_query = Some(
+        path.parse()
+            .map_err(|e| AttestationError::ParseFailed(format!("uri: {e}")))?,
+    );
+
+    Uri::from_parts(parts).map_err(|e| AttestationError::ParseFailed(format!("uri: {e}")))
+}
+
+/// Fetch and verify a fresh attestation over plain http with default timeouts and no retries,
+/// see [`FreshAttestationClient`] for more control
+pub async fn fetch_fresh_attestation(
+    endpoint: &Uri,
+    policy: AttestationPolicy,
+) -> Result<FreshAttestation, AttestationError> {
+    FreshAttestationClient::new()
+        .policy(policy)
+        .fetch(endpoint)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use axum::extract::{Query, State};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use hyper_util::rt::TokioIo;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::attestation::{PolicyFailure, TrustAnchors};
+
+    #[derive(Clone, Default)]
+    struct Server {
+        // requests seen so far
+        requests: Arc<AtomicUsize>,
+        // number of requests failing with a server error before succeeding
+        failures: usize,
+        // delay before responding
+        delay: Duration,
+        // ignore the query and respond with a fixed nonce
+        stale: bool,
+    }
+
+    fn extract(query: &HashMap<String, String>, key: &str) -> Option<Vec<u8>> {
+        query.get(key).map(|x| hex::decode(x).unwrap())
+    }
+
+    async fn handle_raw(
+        State(server): State<Server>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Result<Vec<u8>, StatusCode> {
+        let request = server.requests.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(server.delay).await;
+        if request < server.failures {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        let public_key = extract(&query, "public_key");
+        let user_data = extract(&query, "user_data");
+        let nonce = if server.stale {
+            Some(vec![0u8; 32])
+        } else {
+            extract(&query, "nonce")
+        };
+
+        Ok(crate::attestation::builder::mock_attestation(
+            public_key.as_deref(),
+            user_data.as_deref(),
+            nonce.as_deref(),
+        ))
+    }
+
+    async fn serve(server: Server) -> SocketAddr {
+        serve_at("/", server).await
+    }
+
+    // routes under a path prefix, like behind a reverse proxy
+    async fn serve_at(prefix: &str, server: Server) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes = Router::new()
+            .route("/attestation/raw", get(handle_raw))
+            .with_state(server);
+        let app = match prefix {
+            "/" => routes,
+            prefix => Router::new().nest(prefix, routes),
+        };
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        addr
+    }
+
+    fn client() -> FreshAttestationClient {
+        FreshAttestationClient::new()
+            .policy(AttestationPolicy::new().trust_anchors(TrustAnchors::oyster_mock()))
+            .retry_delay(Duration::from_millis(10))
+    }
+
+    #[tokio::test]
+    async fn test_fetch_fresh() {
+        let addr = serve(Server::default()).await;
+        let endpoint = format!("http://{addr}").parse().unwrap();
+
+        let fresh = client()
+            .public_key([1u8; 64])
+            .user_data(*b"user data")
+            .fetch(&endpoint)
+            .await
+            .unwrap();
+        assert_eq!(fresh.document.nonce.unwrap().as_slice(), fresh.nonce);
+        assert_eq!(fresh.document.public_key.unwrap().as_slice(), [1u8; 64]);
+        assert_eq!(fresh.document.user_data.unwrap().as_slice(), b"user data");
+        assert_eq!(
+            AttestationDocument::from_cose(&fresh.attestation)
+                .unwrap()
+                .nonce
+                .unwrap()
+                .as_slice(),
+            fresh.nonce
+        );
+
+        // nonces are never reused
+        let other = client().fetch(&endpoint).await.unwrap();
+        assert_ne!(other.nonce, fresh.nonce);
+        assert!(other.document.public_key.is_none());
+
+        // unless given
+        let fixed = client().nonce([2u8; 32]).fetch(&endpoint).await.unwrap();
+        assert_eq!(fixed.nonce, [2u8; 32]);
+        assert_eq!(fixed.document.nonce.unwrap().as_slice(), [2u8; 32]);
+
+        // default policy trusts the aws root only
+        let err = fetch_fresh_attestation(&endpoint, AttestationPolicy::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AttestationError::VerifyFailed(e) if e == "root"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prefixed() {
+        let addr = serve_at("/enclave-1", Server::default()).await;
+
+        for endpoint in [
+            format!("http://{addr}/enclave-1"),
+            format!("http://{addr}/enclave-1/"),
+        ] {
+            let fresh = client().fetch(&endpoint.parse().unwrap()).await.unwrap();
+            assert_eq!(fresh.document.nonce.unwrap().as_slice(), fresh.nonce);
+        }
+
+        let err = client()
+            .fetch(&format!("http://{addr}").parse().unwrap())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, AttestationError::HttpStatus(status) if status == StatusCode::NOT_FOUND)
+        );
+    }
+
+    #[test]
+    fn test_raw_attestation_uri() {
+        for (endpoint, query, uri) in [
+            (
+                "http://127.0.0.1:1350",
+                "",
+                "http://127.0.0.1:1350/attestation/raw",
+            ),
+            (
+                "http://127.0.0.1:1350/",
+                "nonce=00",
+                "http://127.0.0.1:1350/attestation/raw?nonce=00",
+            ),
+            (
+                "http://proxy/enclave-1",
+                "",
+                "http://proxy/enclave-1/attestation/raw",
+            ),
+            (
+                "http://proxy/enclave-1/?x=1",
+                "nonce=00",
+                "http://proxy/enclave-1/attestation/raw?nonce=00",
+            ),
+        ] {
+            let endpoint: Uri = endpoint.parse().unwrap();
+            assert_eq!(raw_attestation_uri(&endpoint, query).unwrap(), uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_stale() {
+        let server = Server {
+            stale: true,
+            ..Default::default()
+        };
+        let addr = serve(server.clone()).await;
+        let endpoint = format!("http://{addr}").parse().unwrap();
+
+        let err = client().retries(3).fetch(&endpoint).await.unwrap_err();
+        let AttestationError::PolicyFailed(report) = err else {
+            panic!("unexpected error: {err}");
+        };
+        assert_eq!(report.failures, vec![PolicyFailure::NonceMismatch]);
+        // verification failures are not retried
+        assert_eq!(server.requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_retries() {
+        let server = Server {
+            failures: 2,
+            ..Default::default()
+        };
+        let addr = serve(server.clone()).await;
+        let endpoint = format!("http://{addr}").parse().unwrap();
+
+        let err = client().retries(1).fetch(&endpoint).await.unwrap_err();
+        assert!(
+            matches!(err, AttestationError::HttpStatus(status) if status == StatusCode::INTERNAL_SERVER_ERROR)
+        );
+        assert_eq!(server.requests.load(Ordering::SeqCst), 2);
+
+        client().retries(1).fetch(&endpoint).await.unwrap();
+        assert_eq!(server.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_timeout() {
+        let server = Server {
+            delay: Duration::from_secs(5),
+            ..Default::default()
+        };
+        let addr = serve(server.clone()).await;
+        let endpoint = format!("http://{addr}").parse().unwrap();
+
+        let err = client()
+            .timeout(Duration::from_millis(100))
+            .retries(2)
+            .fetch(&endpoint)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AttestationError::Timeout));
+        assert_eq!(server.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_custom_connector() {
+        let addr = serve(Server::default()).await;
+
+        // routes every request to the server regardless of the uri, like a vsock connector would
+        let connector = tower::service_fn(move |_uri: Uri| {
+            Box::pin(async move {
+                Ok::<_, std::io::Error>(TokioIo::new(TcpStream::connect(addr).await?))
+            }) as Pin<Box<dyn Future<Output = _> + Send>>
+        });
+
+        let fresh = FreshAttestationClient::with_connector(connector)
+            .policy(AttestationPolicy::new().trust_anchors(TrustAnchors::oyster_mock()))
+            .fetch(&"http://enclave:1350".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(fresh.document.nonce.unwrap().as_slice(), fresh.nonce);
+    }
+}
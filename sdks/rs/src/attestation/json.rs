@@ -0,0 +1,247 @@
+//! JSON representation of attestation documents and policies
+//!
+//! Shared by the verifier CLI and the wasm bindings. Binary fields are hex encoded,
+//! optionally with a `0x` prefix when parsed.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{AttestationDocument, AttestationPolicy, PublicKeyType, TrustAnchors};
+
+/// Attestation document with binary fields hex encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Document {
+    pub module_id: String,
+    pub digest: String,
+    pub timestamp: u64,
+    pub pcrs: BTreeMap<usize, String>,
+    pub certificate: String,
+    pub cabundle: Vec<String>,
+    pub public_key: Option<String>,
+    pub user_data: Option<String>,
+    pub nonce: Option<String>,
+}
+
+impl From<AttestationDocument> for Document {
+    fn from(doc: AttestationDocument) -> Self {
+        Self {
+            module_id: doc.module_id,
+            digest: doc.digest,
+            timestamp: doc.timestamp,
+            pcrs: doc
+                .pcrs
+                .into_iter()
+                .map(|(index, value)| (index, hex::encode(value)))
+                .collect(),
+            certificate: hex::encode(doc.certificate),
+            cabundle: doc.cabundle.into_iter().map(hex::encode).collect(),
+            public_key: doc.public_key.map(hex::encode),
+            user_data: doc.user_data.map(hex::encode),
+            nonce: doc.nonce.map(hex::encode),
+        }
+    }
+}
+
+/// Expected value of a pcr, a list allows any of the values
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PcrValue {
+    One(String),
+    Any(Vec<String>),
+}
+
+/// Embedded trust anchors which can be referred to by name
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NamedAnchor {
+    AwsNitro,
+    /// IMPORTANT: NOT secure, see [`TrustAnchors::oyster_mock`]
+    OysterMock,
+}
+
+/// Trust anchor, either one of the embedded roots or a PEM encoded certificate
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Anchor {
+    Named(NamedAnchor),
+    Pem(String),
+}
+
+/// Public key encoding expected in the attestation
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyType {
+    Secp256k1,
+    P384,
+    X25519,
+    Ed25519,
+}
+
+impl From<KeyType> for PublicKeyType {
+    fn from(key_type: KeyType) -> Self {
+        match key_type {
+            KeyType::Secp256k1 => PublicKeyType::Secp256k1,
+            KeyType::P384 => PublicKeyType::P384,
+            KeyType::X25519 => PublicKeyType::X25519,
+            KeyType::Ed25519 => PublicKeyType::Ed25519,
+        }
+    }
+}
+
+/// Policy in JSON, see [`AttestationPolicy`] for the checks
+///
+/// Every field is optional, pcrs are keyed by index. Trust anchors default to
+/// the AWS Nitro Enclaves root.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    pub pcrs: BTreeMap<String, PcrValue>,
+    pub nonce: Option<String>,
+    pub user_data_prefix: Option<String>,
+    pub user_data_sha256: Option<String>,
+    pub public_key_len: Option<usize>,
+    pub public_key_type: Option<KeyType>,
+    /// milliseconds
+    pub max_age: Option<u64>,
+    /// milliseconds
+    pub max_future_skew: Option<u64>,
+    pub trust_anchors: Option<Vec<Anchor>>,
+}
+
+impl TryFrom<&Policy> for AttestationPolicy {
+    /// names the invalid field
+    type Error = String;
+
+    fn try_from(policy: &Policy) -> Result<Self, Self::Error> {
+        let mut result = AttestationPolicy::new();
+
+        for (index, value) in &policy.pcrs {
+            let index = index
+                .parse::<usize>()
+                .map_err(|e| format!("pcr index {index}: {e}"))?;
+            let values = match value {
+                PcrValue::One(value) => vec![from_hex(value, "pcr")?],
+                PcrValue::Any(values) => values
+                    .iter()
+                    .map(|value| from_hex(value, "pcr"))
+                    .collect::<Result<_, _>>()?,
+            };
+            result = result.pcr_any(index, values);
+        }
+        if let Some(nonce) = &policy.nonce {
+            result = result.nonce(from_hex(nonce, "nonce")?);
+        }
+        if let Some(prefix) = &policy.user_data_prefix {
+            result = result.user_data_prefix(from_hex(prefix, "userDataPrefix")?);
+        }
+        if let Some(hash) = &policy.user_data_sha256 {
+            let hash = from_hex(hash, "userDataSha256")?
+                .try_into()
+                .map_err(|_| "userDataSha256: expected 32 bytes".to_owned())?;
+            result = result.user_data_sha256(hash);
+        }
+        if let Some(len) = policy.public_key_len {
+            result = result.public_key_len(len);
+        }
+        if let Some(key_type) = policy.public_key_type {
+            result = result.public_key_type(key_type.into());
+        }
+        if let Some(max_age) = policy.max_age {
+            result = result.max_age(max_age);
+        }
+        if let Some(max_future_skew) = policy.max_future_skew {
+            result = result.max_future_skew(max_future_skew);
+        }
+        if let Some(anchors) = &policy.trust_anchors {
+            let mut trust_anchors = TrustAnchors::empty();
+            for anchor in anchors {
+                let roots = match anchor {
+                    Anchor::Named(NamedAnchor::AwsNitro) => TrustAnchors::aws_nitro(),
+                    Anchor::Named(NamedAnchor::OysterMock) => TrustAnchors::oyster_mock(),
+                    Anchor::Pem(pem) => TrustAnchors::from_pem(pem.as_bytes())
+                        .map_err(|e| format!("trustAnchors: {e}"))?,
+                };
+                for root in roots.roots() {
+                    trust_anchors = trust_anchors
+                        .with_der(root)
+                        .map_err(|e| format!("trustAnchors: {e}"))?;
+                }
+            }
+            result = result.trust_anchors(trust_anchors);
+        }
+
+        Ok(result)
+    }
+}
+
+fn from_hex(value: &str, name: &str) -> Result<Vec<u8>, String> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(value).map_err(|e| format!("{name}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::attestation::{AttestationError, FixedClock, PolicyFailure};
+
+    const PCR0: &str = "5fec1b73727425848d725d68f4a062c634061a035067bd0b9a6dc73e25ed5013dfe7ccbf8a7e9857eceb0841c4cb6ae6";
+
+    fn policy(value: serde_json::Value) -> Result<AttestationPolicy, String> {
+        let policy: Policy = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        AttestationPolicy::try_from(&policy)
+    }
+
+    #[test]
+    fn test_document() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let doc = AttestationDocument::from_cose(&attestation).unwrap();
+        let value = serde_json::to_value(Document::from(doc.clone())).unwrap();
+
+        assert_eq!(value["pcrs"]["0"], PCR0);
+        assert_eq!(value["moduleId"], doc.module_id);
+        assert_eq!(value["timestamp"], doc.timestamp);
+        assert_eq!(value["cabundle"].as_array().unwrap().len(), 4);
+        assert_eq!(value["publicKey"], hex::encode(doc.public_key.unwrap()));
+    }
+
+    #[test]
+    fn test_policy() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let doc = AttestationDocument::from_cose(&attestation).unwrap();
+        let clock = FixedClock(doc.timestamp);
+
+        let checked = policy(json!({
+            "pcrs": { "0": format!("0x{PCR0}"), "1": [PCR0, "00"] },
+            "maxAge": 300000,
+            "trustAnchors": ["aws_nitro"],
+        }))
+        .unwrap();
+        let Err(AttestationError::PolicyFailed(report)) = checked.verify_at(&attestation, &clock)
+        else {
+            panic!("pcr1 should not match");
+        };
+        assert!(matches!(
+            report.failures[..],
+            [PolicyFailure::PcrMismatch { index: 1, .. }]
+        ));
+
+        let checked = policy(json!({ "pcrs": { "0": PCR0 }, "publicKeyType": "secp256k1" }));
+        assert!(checked.unwrap().verify_at(&attestation, &clock).is_ok());
+
+        for invalid in [
+            json!({ "pcrs": { "0": "zz" } }),
+            json!({ "pcrs": { "zero": PCR0 } }),
+            json!({ "userDataSha256": "00" }),
+            json!({ "trustAnchors": ["not a pem"] }),
+            json!({ "maxAgee": 1 }),
+            json!({ "publicKeyType": "rsa" }),
+        ] {
+            assert!(policy(invalid).is_err());
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use super::{
+    verify_and_decode_attestation_document, AttestationDocument, AttestationError, Clock,
+    SystemClock, TrustAnchors,
+};
+
+/// Number of pcrs in a Nitro attestation document
+pub const PCR_COUNT: usize = 16;
+
+/// Expected encoding of the public key in an attestation
+///
+/// Only the shape of the encoding is checked, not whether the key is a valid point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublicKeyType {
+    /// secp256k1 key, either 64 raw bytes as used across Oyster or SEC1 encoded
+    Secp256k1,
+    /// SEC1 encoded P-384 key
+    P384,
+    /// 32 byte x25519 key, e.g. a Scallop static key
+    X25519,
+    /// 32 byte ed25519 key
+    Ed25519,
+}
+
+impl PublicKeyType {
+    fn matches(&self, key: &[u8]) -> bool {
+        match self {
+            PublicKeyType::Secp256k1 => {
+                key.len() == 64
+                    || (key.len() == 65 && key[0] == 0x04)
+                    || (key.len() == 33 && (key[0] == 0x02 || key[0] == 0x03))
+            }
+            PublicKeyType::P384 => {
+                (key.len() == 97 && key[0] == 0x04)
+                    || (key.len() == 49 && (key[0] == 0x02 || key[0] == 0x03))
+            }
+            PublicKeyType::X25519 | PublicKeyType::Ed25519 => key.len() == 32,
+        }
+    }
+}
+
+/// A single check of an [`AttestationPolicy`] that an attestation did not satisfy
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PolicyFailure {
+    #[error("pcr{index} not found")]
+    PcrMissing { index: usize },
+    #[error("pcr{index} does not match any allowed value, got {}", hex::encode(.actual))]
+    PcrMismatch { index: usize, actual: Vec<u8> },
+    #[error("nonce not found")]
+    NonceMissing,
+    #[error("nonce does not match")]
+    NonceMismatch,
+    #[error("user data not found")]
+    UserDataMissing,
+    #[error("user data prefix does not match")]
+    UserDataPrefixMismatch,
+    #[error("user data hash does not match")]
+    UserDataHashMismatch,
+    #[error("public key not found")]
+    PublicKeyMissing,
+    #[error("public key length is {actual}, expected {expected}")]
+    PublicKeyLengthMismatch { expected: usize, actual: usize },
+    #[error("public key is not a {expected:?} key")]
+    PublicKeyTypeMismatch { expected: PublicKeyType },
+    #[error("too old, age {age}ms exceeds {max_age}ms")]
+    TooOld { age: u64, max_age: u64 },
+    #[error("from the future, {skew}ms ahead exceeds {max_skew}ms")]
+    FromFuture { skew: u64, max_skew: u64 },
+}
+
+/// Outcome of evaluating an attestation against an [`AttestationPolicy`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PolicyReport {
+    pub failures: Vec<PolicyFailure>,
+}
+
+impl PolicyReport {
+    /// true if every check of the policy passed
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for PolicyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.failures.is_empty() {
+            return write!(f, "all checks passed");
+        }
+
+        for (i, failure) in self.failures.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{failure}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Declarative set of checks applied to an attestation after its signature and
+/// certificate chain have been verified
+///
+/// ```ignore
+/// let policy = AttestationPolicy::new()
+///     .pcr(0, pcr0)
+///     .pcr(0, pcr0_next)
+///     .pcr(1, pcr1)
+///     .public_key_len(64)
+///     .max_age(300000);
+/// let doc = policy.verify(&attestation)?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AttestationPolicy {
+    pcrs: BTreeMap<usize, Vec<Vec<u8>>>,
+    nonce: Option<Vec<u8>>,
+    user_data_prefix: Option<Vec<u8>>,
+    user_data_sha256: Option<[u8; 32]>,
+    public_key_len: Option<usize>,
+    public_key_type: Option<PublicKeyType>,
+    max_age: Option<u64>,
+    max_future_skew: Option<u64>,
+    trust_anchors: TrustAnchors,
+}
+
+impl AttestationPolicy {
+    /// Policy without any checks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow the given value for a pcr, can be called repeatedly to allow
+    /// multiple values for the same pcr during rolling upgrades
+    pub fn pcr(mut self, index: usize, value: impl Into<Vec<u8>>) -> Self {
+        self.pcrs.entry(index).or_default().push(value.into());
+        self
+    }
+
+    /// Allow any of the given values for a pcr
+    pub fn pcr_any<V: Into<Vec<u8>>>(
+        mut self,
+        index: usize,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        self.pcrs
+            .entry(index)
+            .or_default()
+            .extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    /// Require the given nonce
+    pub fn nonce(mut self, nonce: impl Into<Vec<u8>>) -> Self {
+        self.nonce = Some(nonce.into());
+        self
+    }
+
+    /// Require user data starting with the given prefix
+    pub fn user_data_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.user_data_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Require user data with the given sha256 hash
+    pub fn user_data_sha256(mut self, hash: [u8; 32]) -> Self {
+        self.user_data_sha256 = Some(hash);
+        self
+    }
+
+    /// Require a public key of the given length in bytes
+    pub fn public_key_len(mut self, len: usize) -> Self {
+        self.public_key_len = Some(len);
+        self
+    }
+
+    /// Require a public key of the given type
+    pub fn public_key_type(mut self, key_type: PublicKeyType) -> Self {
+        self.public_key_type = Some(key_type);
+        self
+    }
+
+    /// Maximum age of the attestation in milliseconds
+    pub fn max_age(mut self, max_age: u64) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Maximum amount in milliseconds the attestation timestamp is allowed to be ahead
+    /// of the local clock, future timestamps are not checked if unset
+    pub fn max_future_skew(mut self, max_future_skew: u64) -> Self {
+        self.max_future_skew = Some(max_future_skew);
+        self
+    }
+
+    /// Roots the certificate chain has to terminate at, defaults to the AWS Nitro root
+    pub fn trust_anchors(mut self, trust_anchors: TrustAnchors) -> Self {
+        self.trust_anchors = trust_anchors;
+        self
+    }
+
+    /// Evaluate every check against an already verified document, with `now`
+    /// in milliseconds since the unix epoch
+    pub fn evaluate(&self, doc: &AttestationDocument, now: u64) -> PolicyReport {
+        let mut failures = Vec::new();
+
+        for (&index, allowed) in &self.pcrs {
+            match doc.pcr(index) {
+                None => failures.push(PolicyFailure::PcrMissing { index }),
+                Some(actual) if !allowed.iter().any(|x| x == actual) => {
+                    failures.push(PolicyFailure::PcrMismatch {
+                        index,
+                        actual: actual.to_vec(),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(nonce) = &self.nonce {
+            match &doc.nonce {
+                None => failures.push(PolicyFailure::NonceMissing),
+                Some(actual) if actual.as_slice() != nonce.as_slice() => {
+                    failures.push(PolicyFailure::NonceMismatch)
+                }
+                _ => {}
+            }
+        }
+
+        if self.user_data_prefix.is_some() || self.user_data_sha256.is_some() {
+            match &doc.user_data {
+                None => failures.push(PolicyFailure::UserDataMissing),
+                Some(user_data) => {
+                    if let Some(prefix) = &self.user_data_prefix {
+                        if !user_data.starts_with(prefix) {
+                            failures.push(PolicyFailure::UserDataPrefixMismatch);
+                        }
+                    }
+                    if let Some(hash) = &self.user_data_sha256 {
+                        if Sha256::digest(user_data)[..] != hash[..] {
+                            failures.push(PolicyFailure::UserDataHashMismatch);
+                        }
+                    }
+                }
+            }
+        }
+
+        if self.public_key_len.is_some() || self.public_key_type.is_some() {
+            match &doc.public_key {
+                None => failures.push(PolicyFailure::PublicKeyMissing),
+                Some(public_key) => {
+                    if let Some(expected) = self.public_key_len {
+                        if public_key.len() != expected {
+                            failures.push(PolicyFailure::PublicKeyLengthMismatch {
+                                expected,
+                                actual: public_key.len(),
+                            });
+                        }
+                    }
+                    if let Some(expected) = self.public_key_type {
+                        if !expected.matches(public_key) {
+                            failures.push(PolicyFailure::PublicKeyTypeMismatch { expected });
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(max_age) = self.max_age {
+            let age = now.saturating_sub(doc.timestamp);
+            if age > max_age {
+                failures.push(PolicyFailure::TooOld { age, max_age });
+            }
+        }
+
+        if let Some(max_skew) = self.max_future_skew {
+            let skew = doc.timestamp.saturating_sub(now);
+            if skew > max_skew {
+                failures.push(PolicyFailure::FromFuture { skew, max_skew });
+            }
+        }
+
+        PolicyReport { failures }
+    }
+
+    /// Verify the signature and certificate chain of an attestation, then
+    /// evaluate the policy against it
+    pub fn verify(&self, attestation_doc: &[u8]) -> Result<AttestationDocument, AttestationError> {
+        self.verify_at(attestation_doc, &SystemClock)
+    }
+
+    /// Same as [`AttestationPolicy::verify`], as of the time given by the clock
+    pub fn verify_at(
+        &self,
+        attestation_doc: &[u8],
+        clock: &dyn Clock,
+    ) -> Result<AttestationDocument, AttestationError> {
+        let doc =
+            verify_and_decode_attestation_document(attestation_doc, &self.trust_anchors, clock)?;
+
+        let report = self.evaluate(&doc, clock.now_millis());
+        if !report.is_ok() {
+            return Err(AttestationError::PolicyFailed(report));
+        }
+
+        Ok(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attestation::FixedClock;
+
+    fn document() -> AttestationDocument {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        AttestationDocument::from_cose(&attestation).unwrap()
+    }
+
+    #[test]
+    fn test_policy_pass() {
+        let doc = document();
+        let policy = AttestationPolicy::new()
+            .pcr(0, [0u8; 48])
+            .pcr(0, doc.pcr(0).unwrap())
+            .pcr_any(1, [doc.pcr(1).unwrap()])
+            .pcr(15, doc.pcr(15).unwrap())
+            .public_key_len(64)
+            .public_key_type(PublicKeyType::Secp256k1)
+            .max_age(1000)
+            .max_future_skew(1000);
+
+        let report = policy.evaluate(&doc, doc.timestamp + 1000);
+
+        assert!(report.is_ok(), "{report}");
+    }
+
+    #[test]
+    fn test_policy_reports_every_failure() {
+        let mut doc = document();
+        doc.user_data = Some(b"hello world".to_vec().into());
+        let policy = AttestationPolicy::new()
+            .pcr(0, [0u8; 48])
+            .pcr(16, [0u8; 48])
+            .nonce(b"nonce")
+            .user_data_prefix(b"world")
+            .user_data_sha256([0u8; 32])
+            .public_key_len(32)
+            .public_key_type(PublicKeyType::P384)
+            .max_age(1000);
+
+        let report = policy.evaluate(&doc, doc.timestamp + 1001);
+
+        assert_eq!(
+            report.failures,
+            vec![
+                PolicyFailure::PcrMismatch {
+                    index: 0,
+                    actual: doc.pcr(0).unwrap().to_vec()
+                },
+                PolicyFailure::PcrMissing { index: 16 },
+                PolicyFailure::NonceMissing,
+                PolicyFailure::UserDataPrefixMismatch,
+                PolicyFailure::UserDataHashMismatch,
+                PolicyFailure::PublicKeyLengthMismatch {
+                    expected: 32,
+                    actual: 64
+                },
+                PolicyFailure::PublicKeyTypeMismatch {
+                    expected: PublicKeyType::P384
+                },
+                PolicyFailure::TooOld {
+                    age: 1001,
+                    max_age: 1000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_policy_user_data_and_nonce() {
+        let mut doc = document();
+        doc.user_data = Some(b"hello world".to_vec().into());
+        doc.nonce = Some(b"nonce".to_vec().into());
+        let policy = AttestationPolicy::new()
+            .nonce(b"nonce")
+            .user_data_prefix(b"hello")
+            .user_data_sha256(Sha256::digest(b"hello world").into());
+
+        assert!(policy.evaluate(&doc, doc.timestamp).is_ok());
+    }
+
+    #[test]
+    fn test_policy_verify_mock() {
+        let attestation = crate::attestation::builder::mock_attestation(
+            Some(&[1u8; 64]),
+            Some(b"user data"),
+            Some(b"nonce"),
+        );
+        let policy = AttestationPolicy::new()
+            .pcr(0, [0u8; 48])
+            .pcr(1, [1u8; 48])
+            .pcr(2, [2u8; 48])
+            .nonce(b"nonce")
+            .user_data_prefix(b"user")
+            .public_key_type(PublicKeyType::Secp256k1)
+            .max_age(60000);
+
+        // mock root is not trusted by default
+        assert!(matches!(
+            policy.verify(&attestation),
+            Err(AttestationError::VerifyFailed(_))
+        ));
+
+        let doc = policy
+            .trust_anchors(TrustAnchors::oyster_mock())
+            .verify(&attestation)
+            .unwrap();
+        assert_eq!(doc.public_key.unwrap().as_slice(), [1u8; 64]);
+        assert_eq!(doc.user_data.unwrap().as_slice(), b"user data");
+    }
+
+    #[test]
+    fn test_policy_verify_multiple_anchors() {
+        let attestation = crate::attestation::builder::mock_attestation(None, None, None);
+        let trust_anchors = TrustAnchors::aws_nitro()
+            .with_der(TrustAnchors::oyster_mock().roots()[0].as_slice())
+            .unwrap();
+
+        let doc = AttestationPolicy::new()
+            .trust_anchors(trust_anchors)
+            .verify(&attestation)
+            .unwrap();
+
+        assert!(doc.public_key.is_none());
+        assert!(doc.user_data.is_none());
+        assert!(doc.nonce.is_none());
+    }
+
+    #[test]
+    fn test_policy_verify_recorded() {
+        let attestation = std::fs::read("./src/test/attestation.bin").unwrap();
+        let doc = document();
+        let policy = AttestationPolicy::new()
+            .pcr(0, doc.pcr(0).unwrap())
+            .max_age(1000);
+
+        let clock = FixedClock(doc.timestamp + 1001);
+        let Err(AttestationError::PolicyFailed(report)) = policy.verify_at(&attestation, &clock)
+        else {
+            panic!("should fail age check");
+        };
+        assert_eq!(
+            report.failures,
+            vec![PolicyFailure::TooOld {
+                age: 1001,
+                max_age: 1000
+            }]
+        );
+
+        let clock = FixedClock(doc.timestamp);
+        assert_eq!(policy.verify_at(&attestation, &clock).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_policy_future_skew() {
+        let doc = document();
+        let policy = AttestationPolicy::new().max_age(0).max_future_skew(10);
+
+        assert!(policy.evaluate(&doc, doc.timestamp - 10).is_ok());
+        assert_eq!(
+            policy.evaluate(&doc, doc.timestamp - 11).failures,
+            vec![PolicyFailure::FromFuture {
+                skew: 11,
+                max_skew: 10
+            }]
+        );
+    }
+}
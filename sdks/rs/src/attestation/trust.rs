@@ -0,0 +1,74 @@
+use super::backend::{Backend, DefaultBackend};
+use super::AttestationError;
+
+/// Root certificates an attestation certificate chain is allowed to terminate at
+///
+/// Defaults to the AWS Nitro Enclaves root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustAnchors {
+    // DER encoded root certificates
+    roots: Vec<Vec<u8>>,
+}
+
+impl TrustAnchors {
+    /// Trust anchors without any roots, every chain is rejected until roots are added
+    pub fn empty() -> Self {
+        Self { roots: Vec::new() }
+    }
+
+    /// AWS Nitro Enclaves root
+    pub fn aws_nitro() -> Self {
+        Self::from_pem(include_bytes!("../aws.cert")).expect("embedded aws root should parse")
+    }
+
+    /// Root of the hardcoded chain used by the mock backend of `attestation/server`
+    ///
+    /// IMPORTANT: The private keys of this chain are public, attestations chaining
+    /// up to it are NOT secure. Meant for local development and tests only.
+    pub fn oyster_mock() -> Self {
+        // the mock root is stored in DER form
+        Self::from_der(include_bytes!("../mock.cert")).expect("embedded mock root should parse")
+    }
+
+    /// Trust anchors from one or more PEM encoded root certificates
+    pub fn from_pem(pem: &[u8]) -> Result<Self, AttestationError> {
+        Self::empty().with_pem(pem)
+    }
+
+    /// Trust anchors from a DER encoded root certificate
+    pub fn from_der(der: &[u8]) -> Result<Self, AttestationError> {
+        Self::empty().with_der(der)
+    }
+
+    /// Add one or more PEM encoded root certificates
+    pub fn with_pem(mut self, pem: &[u8]) -> Result<Self, AttestationError> {
+        self.roots.extend(DefaultBackend::pem_to_der(pem)?);
+
+        Ok(self)
+    }
+
+    /// Add a DER encoded root certificate
+    pub fn with_der(mut self, der: &[u8]) -> Result<Self, AttestationError> {
+        // parse to reject garbage early, store the canonical encoding
+        let der = DefaultBackend::canonical_der(der)?;
+        self.roots.push(der);
+
+        Ok(self)
+    }
+
+    /// DER encoded root certificates
+    pub fn roots(&self) -> &[Vec<u8>] {
+        &self.roots
+    }
+
+    /// Whether the DER encoded certificate is one of the roots
+    pub fn contains(&self, der: &[u8]) -> bool {
+        self.roots.iter().any(|root| root == der)
+    }
+}
+
+impl Default for TrustAnchors {
+    fn default() -> Self {
+        Self::aws_nitro()
+    }
+}
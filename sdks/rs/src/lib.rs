@@ -1,6 +1,13 @@
-mod attestation;
+pub mod attestation;
+#[cfg(feature = "network")]
 pub mod scallop;
 
 pub use attestation::{
-    decode_attestation, get_attestation_doc, verify, verify_with_timestamp, AttestationError,
+    decode_attestation, verify, verify_and_decode_attestation_document, verify_with_clock,
+    verify_with_timestamp, AttestationDocument, AttestationError, AttestationPolicy, Clock,
+    FixedClock, PolicyReport, SystemClock, TrustAnchors,
+};
+#[cfg(feature = "network")]
+pub use attestation::{
+    fetch_fresh_attestation, get_attestation_doc, FreshAttestation, FreshAttestationClient,
 };
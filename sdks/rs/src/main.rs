@@ -1,53 +1,849 @@
-use clap::Parser;
-use oyster::{get_attestation_doc, verify};
-use std::error::Error;
-use std::fs::File;
-use std::io::Write;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::Uri;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use oyster::attestation::json::{Document, Policy};
+use oyster::attestation::{eip712, raw_attestation_uri};
+use oyster::{
+    AttestationDocument, AttestationError, AttestationPolicy, FixedClock, FreshAttestationClient,
+    SystemClock,
+};
+use serde::Serialize;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  2  invalid usage
+  3  invalid input, e.g. unreadable files, bad hex or an invalid policy
+  4  malformed attestation
+  5  signature or certificate chain verification failed
+  6  attestation does not satisfy the policy
+  7  attestation server unreachable, failing or timed out
+  8  failed to write output";
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES)]
 struct Cli {
-    /// endpoint of the attestation server (http://<ip:port>)
-    #[clap(short, long, value_parser)]
-    endpoint: String,
+    /// print a single JSON object instead of human readable output
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode an attestation without verifying it
+    Decode(Input),
+    /// Verify an attestation offline against a policy
+    Verify {
+        #[command(flatten)]
+        input: Input,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
+
+        /// time to verify at in milliseconds since the unix epoch, defaults to now
+        #[arg(long)]
+        now: Option<u64>,
+    },
+    /// Fetch a fresh attestation from an attestation server and verify it against a policy
+    Fetch {
+        /// endpoint of the attestation server (http://<ip:port>)
+        #[arg(short, long)]
+        endpoint: String,
+
+        /// hex encoded 32 byte nonce, random if not given
+        #[arg(long)]
+        nonce: Option<String>,
+
+        /// hex encoded user data to include in the attestation
+        #[arg(long)]
+        user_data: Option<String>,
+
+        /// hex encoded public key to include in the attestation
+        #[arg(long)]
+        public_key: Option<String>,
+
+        /// fetch the attestation as served without a nonce, e.g. from servers with a fixed
+        /// key, only as fresh as the maximum age
+        #[arg(long, conflicts_with_all = ["nonce", "user_data", "public_key"], requires = "max_age")]
+        no_nonce: bool,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
+
+        /// timeout of each attempt in milliseconds
+        #[arg(long, default_value_t = 10000)]
+        timeout: u64,
 
-    /// path to public key file
+        /// number of retries after transport failures, timeouts and server errors
+        #[arg(long, default_value_t = 0)]
+        retries: usize,
+
+        /// path to write the public key in the attestation to
+        #[arg(long)]
+        public: Option<PathBuf>,
+
+        /// path to write the raw attestation to
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Compute the EIP-712 digest the attestation verifier signs for an attestation
+    Digest(DigestArgs),
+}
+
+/// Attestation input, raw or hex encoded
+#[derive(Args)]
+struct Input {
+    /// attestation file, raw or hex encoded, `-` for stdin
+    #[arg(default_value = "-")]
+    file: PathBuf,
+
+    /// hex encoded attestation instead of a file
+    #[arg(long, conflicts_with = "file")]
+    hex: Option<String>,
+}
+
+#[derive(Args)]
+struct PolicyArgs {
+    /// JSON policy file, same format as the policies of the wasm bindings
     #[arg(long)]
-    public: String,
+    policy: Option<PathBuf>,
 
-    /// expected pcr0
+    /// allowed pcr0, in addition to any in the policy file
     #[arg(long)]
-    pcr0: String,
+    pcr0: Option<String>,
 
-    /// expected pcr1
+    /// allowed pcr1, in addition to any in the policy file
     #[arg(long)]
-    pcr1: String,
+    pcr1: Option<String>,
 
-    /// expected pcr2
+    /// allowed pcr2, in addition to any in the policy file
     #[arg(long)]
-    pcr2: String,
+    pcr2: Option<String>,
 
-    /// maximum age of attestation (in milliseconds)
+    /// maximum age of the attestation in milliseconds, overrides the policy file
     #[arg(short, long)]
-    max_age: usize,
+    max_age: Option<u64>,
+}
+
+#[derive(Args)]
+struct DigestArgs {
+    /// take the fields from an attestation file, raw or hex encoded, `-` for stdin
+    #[arg(long, conflicts_with_all = ["public_key", "pcr0", "pcr1", "pcr2", "timestamp"])]
+    attestation: Option<PathBuf>,
+
+    /// hex encoded public key of the enclave
+    #[arg(long, required_unless_present = "attestation")]
+    public_key: Option<String>,
+
+    /// hex encoded pcr0
+    #[arg(long, required_unless_present = "attestation")]
+    pcr0: Option<String>,
+
+    /// hex encoded pcr1
+    #[arg(long, required_unless_present = "attestation")]
+    pcr1: Option<String>,
+
+    /// hex encoded pcr2
+    #[arg(long, required_unless_present = "attestation")]
+    pcr2: Option<String>,
+
+    /// attestation timestamp in milliseconds
+    #[arg(long, required_unless_present = "attestation")]
+    timestamp: Option<u64>,
+}
+
+/// Failure classes, each with its own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Failure {
+    Input = 3,
+    Parse = 4,
+    Verify = 5,
+    Policy = 6,
+    Network = 7,
+    Output = 8,
+}
+
+#[derive(Debug, Serialize)]
+struct CliError {
+    kind: Failure,
+    message: String,
+    /// failed policy checks
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failures: Vec<String>,
+}
+
+impl CliError {
+    fn input(message: impl Into<String>) -> Self {
+        Self {
+            kind: Failure::Input,
+            message: message.into(),
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl From<AttestationError> for CliError {
+    fn from(e: AttestationError) -> Self {
+        let kind = match &e {
+            AttestationError::ParseFailed(_) => Failure::Parse,
+            AttestationError::VerifyFailed(_)
+            | AttestationError::InvalidPath { .. }
+            | AttestationError::BuildFailed(_) => Failure::Verify,
+            AttestationError::PolicyFailed(_) | AttestationError::TooOld { .. } => Failure::Policy,
+            AttestationError::HttpClientError(_)
+            | AttestationError::HttpBodyError(_)
+            | AttestationError::HttpStatus(_)
+            | AttestationError::Timeout => Failure::Network,
+        };
+        let failures = match &e {
+            AttestationError::PolicyFailed(report) => {
+                report.failures.iter().map(ToString::to_string).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        Self {
+            kind,
+            message: e.to_string(),
+            failures,
+        }
+    }
+}
+
+impl PolicyArgs {
+    fn load(&self) -> Result<AttestationPolicy, CliError> {
+        let policy = match &self.policy {
+            Some(path) => serde_json::from_slice::<Policy>(&read(path)?)
+                .map_err(|e| CliError::input(format!("policy: {e}")))?,
+            None => Policy::default(),
+        };
+
+        let mut policy = AttestationPolicy::try_from(&policy)
+            .map_err(|e| CliError::input(format!("policy: {e}")))?;
+        for (index, pcr) in [&self.pcr0, &self.pcr1, &self.pcr2].into_iter().enumerate() {
+            if let Some(pcr) = pcr {
+                policy = policy.pcr(index, from_hex(pcr, &format!("pcr{index}"))?);
+            }
+        }
+        if let Some(max_age) = self.max_age {
+            policy = policy.max_age(max_age);
+        }
+
+        Ok(policy)
+    }
+}
+
+fn from_hex(value: &str, name: &str) -> Result<Vec<u8>, CliError> {
+    let value = value.trim();
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(value).map_err(|e| CliError::input(format!("{name}: {e}")))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, CliError> {
+    let mut data = Vec::new();
+    if path == Path::new("-") {
+        std::io::stdin().read_to_end(&mut data)
+    } else {
+        std::fs::File::open(path).and_then(|mut file| file.read_to_end(&mut data))
+    }
+    .map_err(|e| CliError::input(format!("{}: {e}", path.display())))?;
+
+    Ok(data)
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), CliError> {
+    std::fs::write(path, data).map_err(|e| CliError {
+        kind: Failure::Output,
+        message: format!("{}: {e}", path.display()),
+        failures: Vec::new(),
+    })
+}
+
+// raw COSE attestations start with 0x84, which is never valid utf-8, anything else is hex
+fn decode_attestation(data: Vec<u8>) -> Result<Vec<u8>, CliError> {
+    match std::str::from_utf8(&data) {
+        Ok(text) if !text.trim().is_empty() => from_hex(text, "attestation"),
+        _ => Ok(data),
+    }
+}
+
+fn read_attestation(path: &Path) -> Result<Vec<u8>, CliError> {
+    decode_attestation(read(path)?)
+}
+
+// attestation as served by `/attestation/raw` without a query, e.g. by the fixed key route
+async fn fetch_raw(
+    endpoint: &Uri,
+    timeout: Duration,
+    retries: usize,
+) -> Result<Vec<u8>, AttestationError> {
+    let uri = raw_attestation_uri(endpoint, "")?;
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    let mut attempt = 0;
+    loop {
+        let result = tokio::time::timeout(timeout, async {
+            let res = client.get(uri.clone()).await?;
+            if !res.status().is_success() {
+                return Err(AttestationError::HttpStatus(res.status()));
+            }
+            Ok(res.collect().await?.to_bytes().to_vec())
+        })
+        .await
+        .unwrap_or(Err(AttestationError::Timeout));
+
+        // same retries as FreshAttestationClient
+        match result {
+            Err(e) if attempt < retries && e.is_transient() => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl Input {
+    fn load(&self) -> Result<Vec<u8>, CliError> {
+        match &self.hex {
+            Some(hex) => from_hex(hex, "attestation"),
+            None => read_attestation(&self.file),
+        }
+    }
+}
+
+/// Result of a successful command
+#[derive(Debug, Default, Serialize)]
+struct Output {
+    /// printed in human readable mode ahead of everything else
+    #[serde(skip)]
+    message: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    document: Option<Document>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
+}
+
+impl Output {
+    fn to_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("output should serialize");
+        value["ok"] = true.into();
+        value
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            println!("{}", self.to_json());
+            return;
+        }
+
+        if let Some(message) = self.message {
+            println!("{message}");
+        }
+        if let Some(doc) = &self.document {
+            println!("module id: {}", doc.module_id);
+            println!("digest: {}", doc.digest);
+            println!("timestamp: {}", doc.timestamp);
+            for (index, pcr) in &doc.pcrs {
+                println!("pcr{index}: {pcr}");
+            }
+            println!("public key: {}", doc.public_key.as_deref().unwrap_or("-"));
+            println!("user data: {}", doc.user_data.as_deref().unwrap_or("-"));
+            println!("nonce: {}", doc.nonce.as_deref().unwrap_or("-"));
+            println!("cabundle: {} certificates", doc.cabundle.len());
+        }
+        if let Some(digest) = &self.digest {
+            println!("{digest}");
+        }
+    }
+}
+
+impl CliError {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "ok": false, "error": self })
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            println!("{}", self.to_json());
+            return;
+        }
+
+        eprintln!("error: {}", self.message);
+        for failure in &self.failures {
+            eprintln!("  {failure}");
+        }
+    }
+}
+
+async fn run(command: Command) -> Result<Output, CliError> {
+    match command {
+        Command::Decode(input) => {
+            let doc = AttestationDocument::from_cose(&input.load()?)?;
+            Ok(Output {
+                document: Some(doc.into()),
+                ..Default::default()
+            })
+        }
+        Command::Verify { input, policy, now } => {
+            let attestation = input.load()?;
+            let policy = policy.load()?;
+            let doc = match now {
+                Some(now) => policy.verify_at(&attestation, &FixedClock(now))?,
+                None => policy.verify_at(&attestation, &SystemClock)?,
+            };
+            Ok(Output {
+                message: Some("verification successful"),
+                document: Some(doc.into()),
+                ..Default::default()
+            })
+        }
+        Command::Fetch {
+            endpoint,
+            nonce,
+            user_data,
+            public_key,
+            policy,
+            no_nonce,
+            timeout,
+            retries,
+            public,
+            out,
+        } => {
+            let endpoint = endpoint
+                .parse()
+                .map_err(|e| CliError::input(format!("endpoint: {e}")))?;
+            let timeout = Duration::from_millis(timeout);
+
+            let (attestation, document, nonce) = if no_nonce {
+                let policy = policy.load()?;
+                let attestation = fetch_raw(&endpoint, timeout, retries).await?;
+                let document = policy.verify(&attestation)?;
+                (attestation, document, None)
+            } else {
+                let mut client = FreshAttestationClient::new()
+                    .policy(policy.load()?)
+                    .timeout(timeout)
+                    .retries(retries);
+                if let Some(nonce) = nonce {
+                    let nonce = from_hex(&nonce, "nonce")?
+                        .try_into()
+                        .map_err(|_| CliError::input("nonce: expected 32 bytes"))?;
+                    client = client.nonce(nonce);
+                }
+                if let Some(user_data) = user_data {
+                    client = client.user_data(from_hex(&user_data, "user data")?);
+                }
+                if let Some(public_key) = public_key {
+                    client = client.public_key(from_hex(&public_key, "public key")?);
+                }
+
+                let fresh = client.fetch(&endpoint).await?;
+                (
+                    fresh.attestation,
+                    fresh.document,
+                    Some(hex::encode(fresh.nonce)),
+                )
+            };
+
+            if let Some(path) = public {
+                let public_key = document.public_key.as_deref().ok_or(CliError {
+                    kind: Failure::Policy,
+                    message: "attestation does not contain a public key".into(),
+                    failures: Vec::new(),
+                })?;
+                write(&path, public_key)?;
+            }
+            if let Some(path) = out {
+                write(&path, &attestation)?;
+            }
+
+            Ok(Output {
+                message: Some("verification successful"),
+                document: Some(document.into()),
+                nonce,
+                ..Default::default()
+            })
+        }
+        Command::Digest(args) => {
+            let digest = match args.attestation {
+                Some(path) => {
+                    let doc = AttestationDocument::from_cose(&read_attestation(&path)?)?;
+                    let pcr = |index| {
+                        doc.pcr(index)
+                            .ok_or(CliError::from(AttestationError::ParseFailed(format!(
+                                "pcr{index} not found"
+                            ))))
+                    };
+                    let public_key = doc.public_key.as_deref().ok_or(CliError::from(
+                        AttestationError::ParseFailed("public key not found".into()),
+                    ))?;
+                    eip712::compute_digest(public_key, pcr(0)?, pcr(1)?, pcr(2)?, doc.timestamp)
+                }
+                None => {
+                    // clap ensures every field is present without an attestation
+                    let field =
+                        |value: Option<String>, name| from_hex(&value.unwrap_or_default(), name);
+                    eip712::compute_digest(
+                        &field(args.public_key, "public key")?,
+                        &field(args.pcr0, "pcr0")?,
+                        &field(args.pcr1, "pcr1")?,
+                        &field(args.pcr2, "pcr2")?,
+                        args.timestamp.unwrap_or_default(),
+                    )
+                }
+            };
+            Ok(Output {
+                digest: Some(hex::encode(digest)),
+                ..Default::default()
+            })
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
 
-    let pcrs: [[u8; 48]; 3] = [
-        hex::decode(cli.pcr0)?.as_slice().try_into()?,
-        hex::decode(cli.pcr1)?.as_slice().try_into()?,
-        hex::decode(cli.pcr2)?.as_slice().try_into()?,
-    ];
-    let attestation_doc = get_attestation_doc(cli.endpoint.parse()?).await?;
+    match run(cli.command).await {
+        Ok(output) => {
+            output.print(cli.json);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            e.print(cli.json);
+            ExitCode::from(e.kind as u8)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const ATTESTATION: &str = "./src/test/attestation.bin";
+    const TIMESTAMP: u64 = 1723012689640;
+    const PCR0: &str = "5fec1b73727425848d725d68f4a062c634061a035067bd0b9a6dc73e25ed5013dfe7ccbf8a7e9857eceb0841c4cb6ae6";
+
+    // exit code and --json output of a command line
+    async fn cli(args: &[&str]) -> (u8, Value) {
+        let args = ["verifier", "--json"].iter().chain(args);
+        let cli = match Cli::try_parse_from(args) {
+            Ok(cli) => cli,
+            Err(e) => return (e.exit_code() as u8, Value::Null),
+        };
+        match run(cli.command).await {
+            Ok(output) => (0, output.to_json()),
+            Err(e) => (e.kind as u8, e.to_json()),
+        }
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("verifier-{}-{name}", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_usage() {
+        for args in [
+            &["verify", "--unknown"][..],
+            &["digest", "--pcr0", "00"],
+            &["fetch", "-e", "http://127.0.0.1:1", "--no-nonce"],
+            &[
+                "fetch",
+                "-e",
+                "http://127.0.0.1:1",
+                "--no-nonce",
+                "--max-age",
+                "1",
+                "--nonce",
+                "00",
+            ],
+        ] {
+            assert_eq!(cli(args).await.0, 2, "{args:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode() {
+        let (code, output) = cli(&["decode", ATTESTATION]).await;
+        assert_eq!(code, 0);
+        assert_eq!(output["ok"], true);
+        assert_eq!(output["document"]["timestamp"], TIMESTAMP);
+        assert_eq!(output["document"]["pcrs"]["0"], PCR0);
+        assert_eq!(output["document"]["cabundle"].as_array().unwrap().len(), 4);
+        assert!(output["document"]["publicKey"].is_string());
+        assert!(output["document"]["nonce"].is_null());
+        assert_eq!(
+            output.as_object().unwrap().keys().collect::<Vec<_>>(),
+            ["document", "ok"]
+        );
+
+        // hex input, given directly or in a file
+        let hex = hex::encode(std::fs::read(ATTESTATION).unwrap());
+        assert_eq!(cli(&["decode", "--hex", &hex]).await, (0, output.clone()));
+        let file = temp_file("attestation.hex", format!("0x{hex}\n").as_bytes());
+        assert_eq!(cli(&["decode", &file]).await, (0, output));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_input() {
+        let policy = temp_file("invalid.json", br#"{ "maxAgee": 1 }"#);
+        for args in [
+            &["decode", "./src/test/missing.bin"][..],
+            &["decode", "--hex", "zz"],
+            &["verify", ATTESTATION, "--pcr0", "zz"],
+            &["verify", ATTESTATION, "--policy", &policy],
+            &["verify", ATTESTATION, "--policy", "./src/test/missing.json"],
+            &["fetch", "-e", "not a uri"],
+            &["fetch", "-e", "http://127.0.0.1:1", "--nonce", "00"],
+        ] {
+            let (code, output) = cli(args).await;
+            assert_eq!(code, 3, "{args:?}");
+            assert_eq!(output["ok"], false);
+            assert_eq!(output["error"]["kind"], "input");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed() {
+        // not utf-8, taken as raw
+        let file = temp_file("malformed.bin", &[0x84, 0xff]);
+        for args in [
+            &["decode", "--hex", "00"][..],
+            &["verify", "--hex", "84"],
+            &["digest", "--attestation", &file],
+        ] {
+            let (code, output) = cli(args).await;
+            assert_eq!(code, 4, "{args:?}");
+            assert_eq!(output["error"]["kind"], "parse");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify() {
+        let now = TIMESTAMP.to_string();
+        let (code, output) = cli(&["verify", ATTESTATION, "--now", &now, "--pcr0", PCR0]).await;
+        assert_eq!(code, 0);
+        assert_eq!(output["ok"], true);
+        assert_eq!(output["document"]["pcrs"]["0"], PCR0);
+
+        let policy = temp_file(
+            "policy.json",
+            json!({ "pcrs": { "0": PCR0, "1": ["00", "01"] } })
+                .to_string()
+                .as_bytes(),
+        );
+        let (code, output) =
+            cli(&["verify", ATTESTATION, "--now", &now, "--policy", &policy]).await;
+        assert_eq!(code, 6);
+        assert_eq!(output["ok"], false);
+        assert_eq!(output["error"]["kind"], "policy");
+        let failures = output["error"]["failures"].as_array().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].as_str().unwrap().starts_with("pcr1"));
+
+        // stale
+        let later = (TIMESTAMP + 60001).to_string();
+        let (code, output) =
+            cli(&["verify", ATTESTATION, "--now", &later, "--max-age", "60000"]).await;
+        assert_eq!(code, 6);
+        assert_eq!(output["error"]["kind"], "policy");
+        assert_eq!(
+            output["error"]["failures"],
+            json!(["too old, age 60001ms exceeds 60000ms"])
+        );
+        let (code, _) = cli(&["verify", ATTESTATION, "--now", &later, "--max-age", "60001"]).await;
+        assert_eq!(code, 0);
+
+        // certificates long expired
+        let (code, output) = cli(&["verify", ATTESTATION]).await;
+        assert_eq!(code, 5);
+        assert_eq!(output["error"]["kind"], "verify");
+
+        // untrusted root
+        let policy = temp_file("mock.json", br#"{ "trustAnchors": ["oyster_mock"] }"#);
+        let (code, _) = cli(&["verify", ATTESTATION, "--now", &now, "--policy", &policy]).await;
+        assert_eq!(code, 5);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_unreachable() {
+        for args in [
+            &["fetch", "-e", "http://127.0.0.1:1"][..],
+            &["fetch", "-e", "http://127.0.0.1:1", "--no-nonce", "-m", "1"],
+        ] {
+            let (code, output) = cli(args).await;
+            assert_eq!(code, 7, "{args:?}");
+            assert_eq!(output["error"]["kind"], "network");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_digest() {
+        // signature of `attestation/verifier` for the recorded attestation, see its handler tests
+        let signature = hex_literal::hex!("80836a2534fadf0b1adef2135434207eeecfd360819907e925d469a8179eddad4ef1de22cae8398f84bc8df640feef08a5854c77982639c3a242da1c210f535c1c");
+        let verifier = hex_literal::hex!("89b14cb02441b6850534580800bd0a33e6ca483a9ea8f0f55de0a99fbf4a4f02a525d6bb48a7a7a80928af68e0d4ad859d699b49538a425cd35403cd1fbdf956");
+
+        let (code, output) = cli(&["digest", "--attestation", ATTESTATION]).await;
+        assert_eq!(code, 0);
+        assert_eq!(
+            output.as_object().unwrap().keys().collect::<Vec<_>>(),
+            ["digest", "ok"]
+        );
+        let digest: [u8; 32] = hex::decode(output["digest"].as_str().unwrap())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            eip712::recover_signer(&digest, &signature).unwrap(),
+            verifier
+        );
+
+        // same digest from the fields
+        let doc = AttestationDocument::from_cose(&std::fs::read(ATTESTATION).unwrap()).unwrap();
+        let field = |value: &[u8]| format!("0x{}", hex::encode(value));
+        let (code, fields) = cli(&[
+            "digest",
+            "--public-key",
+            &field(doc.public_key.as_deref().unwrap()),
+            "--pcr0",
+            &field(doc.pcr(0).unwrap()),
+            "--pcr1",
+            &field(doc.pcr(1).unwrap()),
+            "--pcr2",
+            &field(doc.pcr(2).unwrap()),
+            "--timestamp",
+            &doc.timestamp.to_string(),
+        ])
+        .await;
+        assert_eq!((code, fields), (0, output));
+    }
+
+    #[cfg(feature = "builder")]
+    mod fetch {
+        use std::collections::HashMap;
+        use std::net::SocketAddr;
+
+        use axum::extract::Query;
+        use axum::routing::get;
+        use axum::Router;
+        use oyster::attestation::{AttestationBuilder, SigningKey};
+        use tokio::net::TcpListener;
+
+        use super::*;
+
+        // mock attestation server, a nonce in the query gets an attestation of the
+        // customizable route, anything else one with the fixed key
+        async fn serve() -> SocketAddr {
+            async fn handle_raw(Query(query): Query<HashMap<String, String>>) -> Vec<u8> {
+                let certs = "../../attestation/server/src/certs";
+                let leaf_key = std::fs::read(format!("{certs}/leaf.key")).unwrap();
+                let leaf_key = SigningKey::from(p384::SecretKey::from_sec1_der(&leaf_key).unwrap());
+                let field = |key: &str| query.get(key).map(|x| hex::decode(x).unwrap());
+
+                let mut builder = AttestationBuilder::new()
+                    .certificate(std::fs::read(format!("{certs}/leaf.crt")).unwrap())
+                    .cabundle([std::fs::read(format!("{certs}/root.crt")).unwrap()]);
+                match field("nonce") {
+                    Some(nonce) => {
+                        builder = builder.nonce(nonce);
+                        if let Some(public_key) = field("public_key") {
+                            builder = builder.public_key(public_key);
+                        }
+                    }
+                    None => builder = builder.public_key([1u8; 64]),
+                }
+
+                builder.sign(&leaf_key).unwrap()
+            }
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let app = Router::new().route("/attestation/raw", get(handle_raw));
+            tokio::spawn(async move { axum::serve(listener, app).await });
+
+            addr
+        }
+
+        #[tokio::test]
+        async fn test_fetch() {
+            let endpoint = format!("http://{}", serve().await);
+            let policy = temp_file("fetch.json", br#"{ "trustAnchors": ["oyster_mock"] }"#);
+            let public =
+                std::env::temp_dir().join(format!("verifier-{}-public", std::process::id()));
+            let public = public.to_str().unwrap();
+
+            // fresh, bound to a nonce
+            let (code, output) = cli(&[
+                "fetch",
+                "-e",
+                &endpoint,
+                "--policy",
+                &policy,
+                "--public-key",
+                "0202",
+                "--public",
+                public,
+            ])
+            .await;
+            assert_eq!(code, 0);
+            assert_eq!(output["document"]["nonce"], output["nonce"]);
+            assert_eq!(std::fs::read(public).unwrap(), [2, 2]);
 
-    let pub_key = verify(attestation_doc, pcrs, cli.max_age)?;
-    println!("verification successful with pubkey: {:?}", pub_key);
+            // as served, only checked against the max age
+            let (code, output) = cli(&[
+                "fetch",
+                "-e",
+                &endpoint,
+                "--policy",
+                &policy,
+                "--no-nonce",
+                "--max-age",
+                "60000",
+                "--public",
+                public,
+            ])
+            .await;
+            assert_eq!(code, 0);
+            assert!(output.get("nonce").is_none());
+            assert!(output["document"]["nonce"].is_null());
+            assert_eq!(std::fs::read(public).unwrap(), [1u8; 64]);
 
-    let mut file = File::create(cli.public)?;
-    file.write_all(pub_key.as_slice())?;
+            // trusting only the aws root
+            let (code, _) = cli(&["fetch", "-e", &endpoint, "--no-nonce", "-m", "60000"]).await;
+            assert_eq!(code, 5);
 
-    Ok(())
+            // output not writable
+            let (code, output) = cli(&[
+                "fetch",
+                "-e",
+                &endpoint,
+                "--policy",
+                &policy,
+                "--out",
+                "./src/test/missing/attestation.bin",
+            ])
+            .await;
+            assert_eq!(code, 8);
+            assert_eq!(output["error"]["kind"], "output");
+        }
+    }
 }
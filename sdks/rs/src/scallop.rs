@@ -144,11 +144,33 @@
 //
 // Pick NoiseSocket as the negotiation protocol
 //
-// TODOs:
-// - (desirable?) 0RTT
-//   - main concern is replay attacks
+// Records are a u16 length followed by a Noise transport message, empty payloads are
+// reserved for control records:
+// - an empty record rekeys the direction it is sent in
+// - an empty record right after a rekey is a close notify, the stream ends there
+// Control records are authenticated like everything else, so a stream which ends
+// without a close notify has been truncated.
+//
+// 0-RTT through resumption, see below
+
+mod auther;
+mod datagram;
+mod exporter;
+#[cfg(feature = "http")]
+pub mod http;
+mod mux;
+mod store;
+mod tickets;
+
+pub use auther::AttestationAuther;
+pub use datagram::{new_client_datagram, new_server_datagram, DatagramConfig, ScallopDatagram};
+pub use mux::{MuxConfig, MuxStream, ScallopMux};
+pub use store::PolicyAuthStore;
+pub use tickets::{ScallopTicket, ScallopTickets};
+
+use exporter::Exporter;
 
-// TODO: vectored reads/writes
+use std::time::Duration;
 
 use snow::{Builder, TransportState};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
@@ -161,15 +183,42 @@ pub enum ScallopError {
     TransportError(#[from] tokio::io::Error),
     #[error("noise error")]
     NoiseError(#[from] snow::Error),
-    #[error("protocol error")]
-    ProtocolError(String),
-}
-
-#[derive(Debug, PartialEq)]
-enum ReadMode {
-    Length,
-    Body,
-    Read,
+    #[error("handshake timed out")]
+    HandshakeTimeout,
+    #[error("invalid number of protocols")]
+    InvalidProtocolCount,
+    #[error("no common protocol")]
+    NoCommonProtocol,
+    #[error("unsupported negotiation version {0}")]
+    UnsupportedNegotiationVersion(u8),
+    #[error("invalid negotiation payload")]
+    InvalidNegotiation,
+    #[error("remote selected a protocol which was not offered")]
+    UnexpectedProtocol,
+    #[error("non empty handshake message in a retry request or rejection")]
+    UnexpectedHandshakeMessage,
+    #[error("handshake message of {len} bytes exceeds the limit of {max}")]
+    MessageTooLarge { len: usize, max: usize },
+    #[error("invalid handshake payload")]
+    InvalidPayload,
+    #[error("invalid auth request")]
+    InvalidAuthRequest,
+    #[error("auth requested but no auther available")]
+    NoAuther,
+    #[error("attestation of {len} bytes exceeds the limit of {max}")]
+    AttestationTooLarge { len: usize, max: usize },
+    #[error("attestation sent without being asked for")]
+    UnexpectedAttestation,
+    #[error("invalid attestation")]
+    InvalidAttestation,
+    #[error("invalid ticket")]
+    InvalidTicket,
+    #[error("resumption after a rejected resumption")]
+    ResumptionAfterRejection,
+    #[error("early data of {0} bytes exceeds the limit of 64000")]
+    EarlyDataTooLarge(usize),
+    #[error("invalid exporter parameters")]
+    InvalidExporterParameters,
 }
 
 pub trait ScallopAuthStore {
@@ -215,22 +264,234 @@ impl<T: ScallopAuther> ScallopAuther for &mut T {
     }
 }
 
-#[derive(Debug)]
+// largest payload written in a single record
+const MAX_PAYLOAD: usize = 64000;
+// authentication tag appended to every record
+const TAGLEN: usize = 16;
+// largest record accepted from the remote, including the length
+const MAX_RECORD: usize = 2 + u16::MAX as usize;
+// initial size of the read and write buffers, grown up to MAX_RECORD for larger records
+const INITIAL_BUFFER: usize = 16 * 1024;
+// largest attestation sent, leaves room for the auth request and its length in a record
+const MAX_ATTESTATION: usize = 60000;
+// ephemeral key, encrypted static key and payload tag of handshake messages
+const HANDSHAKE_OVERHEAD: usize = 2 * 32 + 2 * TAGLEN;
+
+/// Scallop session over a reliable, ordered stream
+///
+/// Read and write buffers start at 16 KiB each and grow to hold a full record (64 KiB)
+/// the first time a larger one is read or written. Payloads which do not fit the
+/// caller's buffer are kept in a third buffer, allocated on first use. A connection
+/// uses 32 KiB at first and up to about 192 KiB for buffers.
 pub struct ScallopStream<Stream: AsyncWrite + AsyncRead + Unpin> {
     noise: TransportState,
     stream: Stream,
-
-    // read buffer
-    rbuf: Box<[u8]>,
-    pending: usize,
-    mode: ReadMode,
-    read_end: usize,
+    protocol: ScallopProtocol,
+    // resumed sessions do not transmit static keys
+    remote_static: [u8; 32],
+    exporter: Exporter,
+    resumed: bool,
+    ticket: Option<ScallopTicket>,
+
+    // read buffer, records read from base but not yet decrypted
+    rbuf: Vec<u8>,
     read_start: usize,
+    read_end: usize,
+
+    // decrypted payloads which did not fit the caller's buffer
+    pbuf: Vec<u8>,
+    plain_start: usize,
+    plain_end: usize,
 
-    // write buffer
-    wbuf: Box<[u8]>,
+    // write buffer, records not yet written to base
+    wbuf: Vec<u8>,
     write_start: usize,
     write_end: usize,
+    // payloads of vectored writes spanning several buffers
+    gather: Vec<u8>,
+
+    // rekeying, counting records and bytes sent since the last rekey
+    limits: TransportLimits,
+    records_since_rekey: u64,
+    bytes_since_rekey: u64,
+
+    // close notify, a second empty record after a rekey
+    rekeyed: bool,
+    close_received: bool,
+    close_sent: bool,
+}
+
+impl<Base: AsyncWrite + AsyncRead + Unpin> std::fmt::Debug for ScallopStream<Base> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // buffers and the exporter secret are left out
+        f.debug_struct("ScallopStream")
+            .field("protocol", &self.protocol)
+            .field("remote_static", &hex::encode(self.remote_static))
+            .field("resumed", &self.resumed)
+            .field("limits", &self.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+// encodes payload as a record into buf, returns the encoded length
+fn encode_record(
+    noise: &mut TransportState,
+    payload: &[u8],
+    buf: &mut [u8],
+) -> std::io::Result<usize> {
+    let noise_len = noise
+        .write_message(payload, &mut buf[2..])
+        .map_err(std::io::Error::other)?;
+    buf[0..2].copy_from_slice(&(noise_len as u16).to_be_bytes());
+
+    Ok(noise_len + 2)
+}
+
+/// Limits on how long a [`ScallopStream`] keeps using the same transport keys
+///
+/// Rekeying replaces the outgoing key with one derived from it using the Noise `REKEY`
+/// function, after which earlier records can no longer be decrypted even if the current
+/// key leaks. It is signalled in-band with an empty record, the remote rekeys its
+/// incoming key on receipt. Every version since rekeying was introduced understands
+/// these records, older ones treat them as the end of the stream, so only enable
+/// rekeying when both ends are new enough.
+///
+/// Independent of rekeying, a connection fails once either direction has used
+/// `max_records` nonces, Noise does not allow nonces to be reused or wrap around.
+///
+/// ```ignore
+/// stream.set_limits(
+///     TransportLimits::new()
+///         .rekey_records(1 << 20)
+///         .rekey_bytes(1 << 30),
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransportLimits {
+    rekey_records: Option<u64>,
+    rekey_bytes: Option<u64>,
+    max_records: u64,
+}
+
+impl Default for TransportLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransportLimits {
+    /// Never rekey, fail only when nonces run out
+    pub fn new() -> Self {
+        Self {
+            rekey_records: None,
+            rekey_bytes: None,
+            // u64::MAX is reserved by Noise
+            max_records: u64::MAX,
+        }
+    }
+
+    /// Rekey after sending this many records
+    pub fn rekey_records(mut self, records: u64) -> Self {
+        self.rekey_records = Some(records.max(1));
+        self
+    }
+
+    /// Rekey after sending this many bytes of application data
+    pub fn rekey_bytes(mut self, bytes: u64) -> Self {
+        self.rekey_bytes = Some(bytes.max(1));
+        self
+    }
+
+    /// Fail the connection once either direction has used this many nonces, one per
+    /// record including rekey records and the final handshake messages
+    pub fn max_records(mut self, records: u64) -> Self {
+        self.max_records = records;
+        self
+    }
+
+    fn rekey_due(&self, records: u64, bytes: u64) -> bool {
+        self.rekey_records.is_some_and(|x| records >= x)
+            || self.rekey_bytes.is_some_and(|x| bytes >= x)
+    }
+}
+
+/// Limits on handshakes, so slow or malicious remotes can neither stall a handshake
+/// nor make it buffer large messages
+///
+/// Messages carrying attestations are limited by `max_attestation`, every other
+/// handshake message by `max_message`.
+///
+/// ```ignore
+/// let stream = new_server_async(
+///     stream,
+///     &secret,
+///     auth_store,
+///     auther,
+///     &protocols,
+///     HandshakeLimits::new()
+///         .timeout(Duration::from_secs(5))
+///         .max_attestation(16384),
+/// )
+/// .await?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeLimits {
+    timeout: Duration,
+    max_message: usize,
+    max_attestation: usize,
+}
+
+impl Default for HandshakeLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HandshakeLimits {
+    /// Give up after 10s, accept messages of up to 1024 bytes and attestations of up
+    /// to 60000 bytes
+    pub fn new() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_message: 1024,
+            max_attestation: MAX_ATTESTATION,
+        }
+    }
+
+    /// Time the whole handshake can take, including producing and verifying attestations
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Largest handshake message accepted from the remote, at most 65535 bytes
+    ///
+    /// Resumptions carry early data in their handshake message, servers accepting
+    /// early data need to raise it accordingly.
+    pub fn max_message(mut self, len: usize) -> Self {
+        self.max_message = len.min(u16::MAX as usize);
+        self
+    }
+
+    /// Largest attestation accepted from the remote, at most 60000 bytes
+    pub fn max_attestation(mut self, len: usize) -> Self {
+        self.max_attestation = len.min(MAX_ATTESTATION);
+        self
+    }
+
+    // largest message carrying an attestation, auth request and length included
+    fn max_auth_message(&self) -> usize {
+        self.max_attestation + 3 + TAGLEN
+    }
+
+    async fn run<T>(
+        &self,
+        handshake: impl std::future::Future<Output = Result<T, ScallopError>>,
+    ) -> Result<T, ScallopError> {
+        tokio::time::timeout(self.timeout, handshake)
+            .await
+            .map_err(|_| ScallopError::HandshakeTimeout)?
+    }
 }
 
 trait Noiser {
@@ -258,79 +519,357 @@ impl Noiser for snow::TransportState {
     }
 }
 
+// reads a length prefixed handshake message of at most max bytes, returns its payload
 async fn noise_read(
     noise: &mut impl Noiser,
     stream: &mut (impl AsyncRead + Unpin),
-    src: &mut [u8],
-    dst: &mut [u8],
-) -> Result<usize, ScallopError> {
+    max: usize,
+) -> Result<Vec<u8>, ScallopError> {
     // read noise message length
     let len = stream.read_u16().await? as usize;
+    if len > max {
+        return Err(ScallopError::MessageTooLarge { len, max });
+    }
 
     // read handshake message
-    stream.read_exact(&mut src[0..len]).await?;
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message).await?;
 
-    // handle handshake message
-    let len = noise.read_message(&src[0..len], dst)?;
+    // handle handshake message, payloads are never longer than messages
+    let mut payload = vec![0u8; len];
+    let len = noise.read_message(&message, &mut payload)?;
+    payload.truncate(len);
 
-    Ok(len)
+    Ok(payload)
 }
 
+// writes prefix, then payload as a length prefixed handshake message
 async fn noise_write(
     noise: &mut impl Noiser,
     stream: &mut (impl AsyncWrite + Unpin),
-    src: &[u8],
-    dst: &mut [u8],
-    // in case dst has data encoded already
-    dst_offset: usize,
+    // negotiation payload, if any
+    prefix: &[u8],
+    payload: &[u8],
 ) -> Result<(), ScallopError> {
+    let mut buf = vec![0u8; prefix.len() + 2 + payload.len() + HANDSHAKE_OVERHEAD];
+    buf[0..prefix.len()].copy_from_slice(prefix);
+
     // set noise message
     let len = noise
-        .write_message(src, &mut dst[dst_offset + 2..])
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        .write_message(payload, &mut buf[prefix.len() + 2..])
+        .map_err(std::io::Error::other)?;
 
     // set length
-    dst[dst_offset..dst_offset + 2].copy_from_slice(&(len as u16).to_be_bytes());
+    buf[prefix.len()..prefix.len() + 2].copy_from_slice(&(len as u16).to_be_bytes());
 
     // send
-    stream.write_all(&dst[0..dst_offset + len + 2]).await?;
+    stream.write_all(&buf[0..prefix.len() + 2 + len]).await?;
     stream.flush().await?;
 
     Ok(())
 }
 
+// length prefixed negotiation payload
+fn negotiation(payload: &[u8]) -> Vec<u8> {
+    // safe to cast since negotiation payloads are bounded well below u16::MAX
+    [&(payload.len() as u16).to_be_bytes()[..], payload].concat()
+}
+
+// Negotiation
+//
+// Follows the NoiseSocket framing, every handshake message is prefixed by a length
+// prefixed negotiation payload which is covered by the prologue.
+//
+// Client offers, in order of preference, encoded as
+// version (0x01) | number of protocols | protocol ids
+// and sends its first handshake message using the first protocol offered.
+//
+// Server responds with
+// version (0x01) | protocol id
+// - id of the first protocol offered, followed by its handshake message as usual
+// - id of another protocol offered, followed by an empty handshake message, the client
+//   retries with that protocol and "NoiseSocketInit2" | both negotiation payloads as the prologue
+// - 0x00 if there is no common protocol, followed by an empty handshake message
+//
+// An empty offer from the client stands for Noise_IX_25519_ChaChaPoly_BLAKE2b and is answered
+// with an empty response. Clients offering only that protocol send an empty offer, so
+// they interoperate with servers which predate negotiation.
+
+const NEGOTIATION_VERSION: u8 = 1;
+
+// Resumption
+//
+// Clients ask for a ticket by appending the tickets extension (0xff) to their offer,
+// servers which predate tickets skip it like any unknown protocol. Servers issuing tickets
+// append it to their response and append
+// lifetime (u32 seconds) | ticket length (u16) | ticket | psk
+// to the payload of their handshake message, which only the client can decrypt.
+//
+// Resuming clients offer
+// version (0x02) | protocol id | ticket
+// and send the first message of NNpsk0 with the same cipher and hash, keyed by the psk
+// of the ticket, with early data as its payload.
+//
+// Server responds with
+// version (0x02) | protocol id
+// - followed by the second message of NNpsk0 with a new ticket as its payload
+// - 0x00 instead of the protocol id if the ticket is not accepted, followed by an empty
+//   handshake message, the client continues with a full handshake and sends the early
+//   data afterwards
+//
+// Neither side sends attestations when resuming, the auth stores still have to trust
+// the remote for the ticket to be used. Servers accept every client ephemeral key once
+// while its ticket is valid, so early data cannot be replayed against the same server.
+
+const RESUMPTION_VERSION: u8 = 2;
+const TICKETS_EXTENSION: u8 = 0xff;
+
+/// Noise protocols supported by Scallop
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScallopProtocol {
+    Noise_IX_25519_ChaChaPoly_BLAKE2b,
+    Noise_IX_25519_ChaChaPoly_SHA256,
+    Noise_IX_25519_AESGCM_BLAKE2b,
+    Noise_IX_25519_AESGCM_SHA256,
+}
+
+impl ScallopProtocol {
+    /// Every supported protocol, in the default order of preference
+    pub const ALL: [ScallopProtocol; 4] = [
+        ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b,
+        ScallopProtocol::Noise_IX_25519_ChaChaPoly_SHA256,
+        ScallopProtocol::Noise_IX_25519_AESGCM_BLAKE2b,
+        ScallopProtocol::Noise_IX_25519_AESGCM_SHA256,
+    ];
+
+    /// Noise protocol name
+    pub fn name(self) -> &'static str {
+        match self {
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b => {
+                "Noise_IX_25519_ChaChaPoly_BLAKE2b"
+            }
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_SHA256 => "Noise_IX_25519_ChaChaPoly_SHA256",
+            ScallopProtocol::Noise_IX_25519_AESGCM_BLAKE2b => "Noise_IX_25519_AESGCM_BLAKE2b",
+            ScallopProtocol::Noise_IX_25519_AESGCM_SHA256 => "Noise_IX_25519_AESGCM_SHA256",
+        }
+    }
+
+    // wire id, 0x00 is reserved for rejections
+    fn id(self) -> u8 {
+        match self {
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b => 1,
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_SHA256 => 2,
+            ScallopProtocol::Noise_IX_25519_AESGCM_BLAKE2b => 3,
+            ScallopProtocol::Noise_IX_25519_AESGCM_SHA256 => 4,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.id() == id)
+    }
+
+    fn builder<'a>(
+        self,
+        secret: &'a [u8; 32],
+        prologue: &'a [u8],
+    ) -> Result<Builder<'a>, ScallopError> {
+        Ok(
+            Builder::new(self.name().parse().map_err(ScallopError::InitFailed)?)
+                .local_private_key(secret)
+                .prologue(prologue),
+        )
+    }
+
+    // NNpsk0 with the same cipher and hash, authenticated by the psk of a ticket
+    fn resumption_builder<'a>(
+        self,
+        psk: &'a [u8; 32],
+        prologue: &'a [u8],
+    ) -> Result<Builder<'a>, ScallopError> {
+        let name = self.name().replacen("_IX_", "_NNpsk0_", 1);
+        Ok(
+            Builder::new(name.parse().map_err(ScallopError::InitFailed)?)
+                .psk(0, psk)
+                .prologue(prologue),
+        )
+    }
+}
+
+impl std::fmt::Display for ScallopProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// "NoiseSocketInit1" or "NoiseSocketInit2" followed by the length prefixed negotiation payloads
+fn prologue(init: &[u8], negotiation: &[&[u8]]) -> Vec<u8> {
+    let mut prologue = init.to_vec();
+    for data in negotiation {
+        // safe to cast since negotiation payloads are bounded well below u16::MAX
+        prologue.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        prologue.extend_from_slice(data);
+    }
+    prologue
+}
+
 #[allow(non_snake_case)]
 pub async fn new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     Base: AsyncWrite + AsyncRead + Unpin,
 >(
+    stream: Base,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+) -> Result<ScallopStream<Base>, ScallopError> {
+    new_client_async(
+        stream,
+        secret,
+        auth_store,
+        auther,
+        &[ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b],
+        HandshakeLimits::new(),
+    )
+    .await
+}
+
+/// Client handshake offering the given protocols in order of preference
+pub async fn new_client_async<Base: AsyncWrite + AsyncRead + Unpin>(
+    stream: Base,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    limits: HandshakeLimits,
+) -> Result<ScallopStream<Base>, ScallopError> {
+    limits
+        .run(client_handshake(
+            stream, secret, auth_store, auther, protocols, false, &limits,
+        ))
+        .await
+}
+
+/// Client handshake resuming the session of `ticket` if possible, sending `early_data`
+/// along with the first handshake message
+///
+/// Falls back to a full handshake, with `early_data` sent right after it, if there is
+/// no ticket, it has expired, the auth store no longer trusts the server or the server
+/// rejects it. Full handshakes ask the server for a ticket, see
+/// [`ScallopStream::ticket`]. Servers which predate resumption fail the handshake
+/// when offered a ticket and do not issue tickets.
+///
+/// Early data is not forward secret and may be replayed against other servers sharing
+/// the ticket key, keep it to requests which are safe to repeat.
+#[allow(clippy::too_many_arguments)]
+pub async fn new_client_resumable_async<Base: AsyncWrite + AsyncRead + Unpin>(
     mut stream: Base,
     secret: &[u8; 32],
     // will not auth remote if None
     mut auth_store: Option<impl ScallopAuthStore>,
     // will not respond to auth requests if None
     auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    ticket: Option<&ScallopTicket>,
+    early_data: &[u8],
+    limits: HandshakeLimits,
 ) -> Result<ScallopStream<Base>, ScallopError> {
-    let mut buf = [0u8; 1024];
-    let mut noise_buf = [0u8; 1024];
+    if early_data.len() > MAX_PAYLOAD {
+        return Err(ScallopError::EarlyDataTooLarge(early_data.len()));
+    }
 
-    let prologue = b"NoiseSocketInit1\x00\x00";
+    // resume only while the server is still trusted
+    let ticket = ticket.filter(|x| {
+        !x.is_expired()
+            && protocols.contains(&x.protocol)
+            && auth_store
+                .as_mut()
+                .is_none_or(|store| store.contains(&x.server_static))
+    });
+
+    limits
+        .run(async {
+            if let Some(ticket) = ticket {
+                if let Some((noise, exporter, grant)) =
+                    resume_client(&mut stream, ticket, early_data, &limits).await?
+                {
+                    let mut stream = ScallopStream::new(
+                        noise,
+                        stream,
+                        ticket.protocol,
+                        ticket.server_static,
+                        exporter,
+                    );
+                    stream.resumed = true;
+                    stream.ticket = Some(grant);
+
+                    return Ok(stream);
+                }
+            }
 
-    let mut noise = Builder::new(
-        "Noise_IX_25519_ChaChaPoly_BLAKE2b"
-            .parse()
-            .map_err(ScallopError::InitFailed)?,
-    )
-    .local_private_key(secret)
-    .prologue(prologue)
-    .build_initiator()
-    .map_err(ScallopError::InitFailed)?;
+            let mut stream =
+                client_handshake(stream, secret, auth_store, auther, protocols, true, &limits)
+                    .await?;
 
-    //---- -> e, s start ----//
+            if !early_data.is_empty() {
+                stream.write_all(early_data).await?;
+                stream.flush().await?;
+            }
 
-    // first two bytes are already zero, skip writing negotiation payload
+            Ok(stream)
+        })
+        .await
+}
 
-    // encode and send handshake message
-    noise_write(&mut noise, &mut stream, &[], &mut buf, 2).await?;
+async fn client_handshake<Base: AsyncWrite + AsyncRead + Unpin>(
+    mut stream: Base,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    mut auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    request_ticket: bool,
+    limits: &HandshakeLimits,
+) -> Result<ScallopStream<Base>, ScallopError> {
+    if protocols.is_empty() || protocols.len() > 255 {
+        return Err(ScallopError::InvalidProtocolCount);
+    }
+
+    // empty offer if only the original protocol is offered
+    let offer =
+        if protocols == [ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b] && !request_ticket {
+            vec![]
+        } else if request_ticket {
+            if protocols.len() > 254 {
+                return Err(ScallopError::InvalidProtocolCount);
+            }
+
+            [NEGOTIATION_VERSION, protocols.len() as u8 + 1]
+                .into_iter()
+                .chain(protocols.iter().map(|x| x.id()))
+                .chain([TICKETS_EXTENSION])
+                .collect()
+        } else {
+            [NEGOTIATION_VERSION, protocols.len() as u8]
+                .into_iter()
+                .chain(protocols.iter().map(|x| x.id()))
+                .collect()
+        };
+
+    let mut protocol = protocols[0];
+    let mut noise = protocol
+        .builder(secret, &prologue(b"NoiseSocketInit1", &[&offer]))?
+        .build_initiator()
+        .map_err(ScallopError::InitFailed)?;
+
+    //---- -> e, s start ----//
+
+    // encode and send handshake message after the negotiation payload
+    noise_write(&mut noise, &mut stream, &negotiation(&offer), &[]).await?;
 
     //---- -> e, s end ----//
 
@@ -339,31 +878,85 @@ pub async fn new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     // read negotiation length
     let len = stream.read_u16().await?;
 
-    // length should be zero
+    if offer.is_empty() != (len == 0) {
+        return Err(ScallopError::InvalidNegotiation);
+    }
+
+    // whether the server grants a ticket in its handshake payload
+    let mut granted = false;
+
     if len != 0 {
-        return Err(ScallopError::ProtocolError(
-            "non zero second negotiation length".into(),
-        ));
+        // read negotiation response, the tickets extension is only acknowledged if offered
+        if len != 2 && !(len == 3 && request_ticket) {
+            return Err(ScallopError::InvalidNegotiation);
+        }
+        let mut response = vec![0u8; len.into()];
+        stream.read_exact(&mut response).await?;
+
+        if len == 3 {
+            if response[2] != TICKETS_EXTENSION {
+                return Err(ScallopError::InvalidNegotiation);
+            }
+            granted = true;
+        }
+
+        if response[0] != NEGOTIATION_VERSION {
+            return Err(ScallopError::UnsupportedNegotiationVersion(response[0]));
+        }
+
+        if response[1] == 0 {
+            return Err(ScallopError::NoCommonProtocol);
+        }
+
+        let Some(selected) =
+            ScallopProtocol::from_id(response[1]).filter(|x| protocols.contains(x))
+        else {
+            return Err(ScallopError::UnexpectedProtocol);
+        };
+
+        if selected != protocol {
+            // retry request, handshake message should be empty
+            if stream.read_u16().await? != 0 {
+                return Err(ScallopError::UnexpectedHandshakeMessage);
+            }
+
+            protocol = selected;
+            noise = protocol
+                .builder(secret, &prologue(b"NoiseSocketInit2", &[&offer, &response]))?
+                .build_initiator()
+                .map_err(ScallopError::InitFailed)?;
+
+            //---- -> e, s retry start ----//
+
+            // negotiation is done, empty negotiation payload
+            noise_write(&mut noise, &mut stream, &negotiation(&[]), &[]).await?;
+
+            //---- -> e, s retry end ----//
+
+            // read negotiation length
+            let len = stream.read_u16().await?;
+
+            // length should be zero
+            if len != 0 {
+                return Err(ScallopError::InvalidNegotiation);
+            }
+        }
     }
 
     // read and handle handshake message
-    let len = noise_read(&mut noise, &mut stream, &mut buf, &mut noise_buf).await?;
+    let payload = noise_read(&mut noise, &mut stream, limits.max_message).await?;
 
-    // handshake payload should contain auth request
-    if len != 3 || noise_buf[0] != 0 || noise_buf[1] != 1 {
-        return Err(ScallopError::ProtocolError(
-            "invalid second payload length".into(),
-        ));
+    // handshake payload should contain auth request, followed by the ticket if granted
+    if payload.len() < 3 || (payload.len() != 3 && !granted) || payload[0..2] != [0, 1] {
+        return Err(ScallopError::InvalidPayload);
     }
 
     // auth request should be 0 or 1
-    if noise_buf[2] > 1 {
-        return Err(ScallopError::ProtocolError(
-            "invalid auth request in second payload".into(),
-        ));
+    if payload[2] > 1 {
+        return Err(ScallopError::InvalidAuthRequest);
     }
 
-    let should_send_auth = noise_buf[2] == 1;
+    let should_send_auth = payload[2] == 1;
 
     //---- <- e, ee, se, s, es end ----//
 
@@ -371,18 +964,21 @@ pub async fn new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     if should_send_auth && auther.is_none() {
         // auth requested and no auther available
         // error out
-        return Err(ScallopError::ProtocolError(
-            "auth requested but no auther available".into(),
-        ));
+        return Err(ScallopError::NoAuther);
     }
 
     // safe to unwrap since IX should have key by now
     let remote_static: [u8; 32] = noise.get_remote_static().unwrap().try_into().unwrap();
 
+    let ticket = granted
+        .then(|| ScallopTicket::from_grant(&payload[3..], remote_static, protocol))
+        .transpose()?;
+
     let should_ask_auth =
         auth_store.is_some() && !auth_store.as_mut().unwrap().contains(&remote_static);
 
     // handshake is done, switch to transport mode
+    let exporter = Exporter::new(&mut noise, protocol);
     let mut noise = noise.into_transport_mode()?;
 
     //---- -> CLIENTFIN start ----//
@@ -394,59 +990,26 @@ pub async fn new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     // two bytes payload size
     // payload
 
-    async fn send_CLIENTFIN(
-        noise: &mut impl Noiser,
-        stream: &mut (impl AsyncWrite + Unpin),
-        buf: &mut [u8],
-        noise_buf: &mut [u8],
-        payload: &[u8],
-        should_ask_auth: bool,
-    ) -> Result<(), ScallopError> {
-        // assemble message for encryption
-        noise_buf[0] = if !should_ask_auth { 0 } else { 1 };
-        // safe to cast since range has been checked above
-        noise_buf[1..3].copy_from_slice(&(payload.len() as u16).to_be_bytes());
-        noise_buf[3..3 + payload.len()].copy_from_slice(payload);
-
-        // encode and send handshake message
-        noise_write(noise, stream, &noise_buf[0..payload.len() + 3], buf, 0).await?;
-
-        Ok(())
-    }
-
+    let mut fin = vec![should_ask_auth as u8, 0, 0];
     if should_send_auth {
         // safe to unwrap since it has been checked above
-        let payload = auther.unwrap().new_auth().await;
+        let auth = auther.unwrap().new_auth().await;
         // check if payload is not too big
-        if payload.len() > 60000 {
-            return Err(ScallopError::ProtocolError("auth payload too big".into()));
+        if auth.len() > MAX_ATTESTATION {
+            return Err(ScallopError::AttestationTooLarge {
+                len: auth.len(),
+                max: MAX_ATTESTATION,
+            });
         }
 
-        // new heap allocated buffers
-        let mut buf = vec![0u8; 65000].into_boxed_slice();
-        let mut noise_buf = vec![0u8; 65000].into_boxed_slice();
-
-        send_CLIENTFIN(
-            &mut noise,
-            &mut stream,
-            &mut buf,
-            &mut noise_buf,
-            &payload,
-            should_ask_auth,
-        )
-        .await?;
-    } else {
-        send_CLIENTFIN(
-            &mut noise,
-            &mut stream,
-            &mut buf,
-            &mut noise_buf,
-            &[],
-            should_ask_auth,
-        )
-        .await?;
+        // safe to cast since range has been checked above
+        fin[1..3].copy_from_slice(&(auth.len() as u16).to_be_bytes());
+        fin.extend_from_slice(&auth);
     }
 
+    // encode and send handshake message
+    noise_write(&mut noise, &mut stream, &[], &fin).await?;
+
     //---- -> CLIENTFIN end ----//
 
     //---- <- SERVERFIN start ----//
@@ -458,34 +1021,33 @@ pub async fn new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     // payload
 
     if should_ask_auth {
-        // new heap allocated buffers
-        let mut buf = vec![0u8; 65000].into_boxed_slice();
-        let mut noise_buf = vec![0u8; 65000].into_boxed_slice();
-
         // read and handle handshake message
-        let len = noise_read(&mut noise, &mut stream, &mut buf, &mut noise_buf).await?;
+        let payload = noise_read(&mut noise, &mut stream, limits.max_auth_message()).await?;
 
         // should have at least 2 size
-        if len < 2 {
-            return Err(ScallopError::ProtocolError(
-                "invalid SERVERFIN length".into(),
-            ));
+        if payload.len() < 2 {
+            return Err(ScallopError::InvalidPayload);
         }
 
         // payload size should match
-        if u16::from_be_bytes([noise_buf[0], noise_buf[1]]) as usize != len - 2 {
-            return Err(ScallopError::ProtocolError(
-                "invalid SERVERFIN payload length".into(),
-            ));
+        let attestation = &payload[2..];
+        if u16::from_be_bytes([payload[0], payload[1]]) as usize != attestation.len() {
+            return Err(ScallopError::InvalidPayload);
+        }
+        if attestation.len() > limits.max_attestation {
+            return Err(ScallopError::AttestationTooLarge {
+                len: attestation.len(),
+                max: limits.max_attestation,
+            });
         }
 
         // verify
         let Some(pcrs) = auth_store
             .as_mut()
             .unwrap()
-            .verify(&noise_buf[2..len], &remote_static)
+            .verify(attestation, &remote_static)
         else {
-            return Err(ScallopError::ProtocolError("invalid attestation".into()));
+            return Err(ScallopError::InvalidAttestation);
         };
 
         auth_store.unwrap().set(remote_static, pcrs);
@@ -493,76 +1055,302 @@ pub async fn new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
 
     //---- <- SERVERFIN end ----//
 
-    Ok(ScallopStream {
-        noise,
-        stream,
-        // initialize with 2 sized buffer to read length
-        rbuf: vec![0u8; 2].into_boxed_slice(),
-        pending: 2,
-        mode: ReadMode::Length,
-        read_start: 0,
-        read_end: 0,
-        wbuf: vec![].into_boxed_slice(),
-        write_start: 0,
-        write_end: 0,
-    })
+    let mut stream = ScallopStream::new(noise, stream, protocol, remote_static, exporter);
+    stream.ticket = ticket;
+
+    Ok(stream)
+}
+
+// Ok(None) if the server rejected the ticket and expects a full handshake next
+async fn resume_client(
+    stream: &mut (impl AsyncWrite + AsyncRead + Unpin),
+    ticket: &ScallopTicket,
+    early_data: &[u8],
+    limits: &HandshakeLimits,
+) -> Result<Option<(TransportState, Exporter, ScallopTicket)>, ScallopError> {
+    let offer: Vec<u8> = [RESUMPTION_VERSION, ticket.protocol.id()]
+        .into_iter()
+        .chain(ticket.ticket.iter().copied())
+        .collect();
+    let prologue = prologue(b"NoiseSocketInit1", &[&offer]);
+    let mut noise = ticket
+        .protocol
+        .resumption_builder(&ticket.psk, &prologue)?
+        .build_initiator()
+        .map_err(ScallopError::InitFailed)?;
+
+    //---- -> psk, e start ----//
+
+    // encode and send handshake message with the early data as payload
+    noise_write(&mut noise, stream, &negotiation(&offer), early_data).await?;
+
+    //---- -> psk, e end ----//
+
+    //---- <- e, ee start ----//
+
+    // read negotiation response
+    if stream.read_u16().await? != 2 {
+        return Err(ScallopError::InvalidNegotiation);
+    }
+    let mut response = [0u8; 2];
+    stream.read_exact(&mut response).await?;
+
+    if response[0] != RESUMPTION_VERSION {
+        return Err(ScallopError::UnsupportedNegotiationVersion(response[0]));
+    }
+
+    if response[1] == 0 {
+        // rejection, handshake message should be empty
+        if stream.read_u16().await? != 0 {
+            return Err(ScallopError::UnexpectedHandshakeMessage);
+        }
+
+        return Ok(None);
+    }
+
+    if response[1] != ticket.protocol.id() {
+        return Err(ScallopError::UnexpectedProtocol);
+    }
+
+    // read and handle handshake message, payload has the next ticket
+    let grant = noise_read(&mut noise, stream, limits.max_message).await?;
+    let grant = ScallopTicket::from_grant(&grant, ticket.server_static, ticket.protocol)?;
+
+    //---- <- e, ee end ----//
+
+    let exporter = Exporter::new(&mut noise, ticket.protocol);
+
+    Ok(Some((noise.into_transport_mode()?, exporter, grant)))
 }
 
 #[allow(non_snake_case)]
 pub async fn new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     Base: AsyncWrite + AsyncRead + Unpin,
 >(
+    stream: Base,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+) -> Result<ScallopStream<Base>, ScallopError> {
+    new_server_async(
+        stream,
+        secret,
+        auth_store,
+        auther,
+        &[ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b],
+        HandshakeLimits::new(),
+    )
+    .await
+}
+
+/// Server handshake accepting any of the given protocols, the protocol is picked
+/// according to the preference of the client
+pub async fn new_server_async<Base: AsyncWrite + AsyncRead + Unpin>(
+    stream: Base,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    limits: HandshakeLimits,
+) -> Result<ScallopStream<Base>, ScallopError> {
+    limits
+        .run(server_handshake(
+            stream, secret, auth_store, auther, protocols, None, &limits,
+        ))
+        .await
+}
+
+/// Server handshake which also issues tickets to clients asking for them and accepts
+/// resumptions with early data, see [`ScallopTickets`]
+///
+/// Early data of resumed sessions is read before anything sent after the handshake.
+pub async fn new_server_resumable_async<Base: AsyncWrite + AsyncRead + Unpin>(
+    stream: Base,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    tickets: &ScallopTickets,
+    limits: HandshakeLimits,
+) -> Result<ScallopStream<Base>, ScallopError> {
+    limits
+        .run(server_handshake(
+            stream,
+            secret,
+            auth_store,
+            auther,
+            protocols,
+            Some(tickets),
+            &limits,
+        ))
+        .await
+}
+
+async fn server_handshake<Base: AsyncWrite + AsyncRead + Unpin>(
     mut stream: Base,
     secret: &[u8; 32],
     // will not auth remote if None
     mut auth_store: Option<impl ScallopAuthStore>,
     // will not respond to auth requests if None
     auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    // will not issue or accept tickets if None
+    tickets: Option<&ScallopTickets>,
+    limits: &HandshakeLimits,
 ) -> Result<ScallopStream<Base>, ScallopError> {
-    let mut buf = [0u8; 1024];
-    let mut noise_buf = [0u8; 1024];
+    //---- -> e, s start ----//
 
-    let prologue = b"NoiseSocketInit1\x00\x00";
+    let mut rejected = false;
+    let (len, offer) = loop {
+        // read negotiation length
+        let len = stream.read_u16().await? as usize;
 
-    let mut noise = Builder::new(
-        "Noise_IX_25519_ChaChaPoly_BLAKE2b"
-            .parse()
-            .map_err(ScallopError::InitFailed)?,
-    )
-    .local_private_key(secret)
-    .prologue(prologue)
-    .build_responder()
-    .map_err(ScallopError::InitFailed)?;
+        // at most 255 protocols, tickets are shorter
+        if len > 257 {
+            return Err(ScallopError::InvalidNegotiation);
+        }
 
-    //---- -> e, s start ----//
+        // read negotiation payload
+        let mut offer = vec![0u8; len];
+        stream.read_exact(&mut offer).await?;
 
-    // read negotiation length
-    let len = stream.read_u16().await?;
+        if offer.first() != Some(&RESUMPTION_VERSION) {
+            break (len, offer);
+        }
 
-    // length should be zero
-    if len != 0 {
-        return Err(ScallopError::ProtocolError(
-            "non zero first negotiation length".into(),
-        ));
-    }
+        // clients resume at most once per connection
+        if rejected {
+            return Err(ScallopError::ResumptionAfterRejection);
+        }
+
+        if let Some((noise, exporter, protocol, remote_static, early_data)) = resume_server(
+            &mut stream,
+            &offer,
+            &mut auth_store,
+            protocols,
+            tickets,
+            limits,
+        )
+        .await?
+        {
+            let mut stream = ScallopStream::new(noise, stream, protocol, remote_static, exporter);
+            stream.resumed = true;
+            stream.set_early_data(&early_data);
+
+            return Ok(stream);
+        }
+
+        // full handshake follows
+        rejected = true;
+    };
+
+    let (protocol, response, mut noise) = if offer.is_empty() {
+        // empty offer stands for the original protocol
+        let protocol = ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b;
+        if !protocols.contains(&protocol) {
+            return Err(ScallopError::NoCommonProtocol);
+        }
+
+        let noise = protocol
+            .builder(secret, &prologue(b"NoiseSocketInit1", &[&offer]))?
+            .build_responder()
+            .map_err(ScallopError::InitFailed)?;
+
+        (protocol, vec![], noise)
+    } else {
+        if offer[0] != NEGOTIATION_VERSION {
+            return Err(ScallopError::UnsupportedNegotiationVersion(offer[0]));
+        }
+
+        if len < 3 || offer[1] as usize != len - 2 {
+            return Err(ScallopError::InvalidNegotiation);
+        }
+
+        // first protocol supported by both, in the order offered by the client
+        // unknown protocols are skipped, they might be from newer clients
+        let selected = offer[2..]
+            .iter()
+            .filter_map(|x| ScallopProtocol::from_id(*x))
+            .find(|x| protocols.contains(x));
+
+        let Some(protocol) = selected else {
+            // reject with an empty handshake message
+            stream
+                .write_all(&[0, 2, NEGOTIATION_VERSION, 0, 0, 0])
+                .await?;
+            stream.flush().await?;
+
+            return Err(ScallopError::NoCommonProtocol);
+        };
+
+        let mut response = vec![NEGOTIATION_VERSION, protocol.id()];
+
+        // acknowledge the tickets extension if we issue tickets
+        if tickets.is_some() && offer[2..].contains(&TICKETS_EXTENSION) {
+            response.push(TICKETS_EXTENSION);
+        }
+
+        if protocol.id() == offer[2] {
+            // handshake message uses the selected protocol
+            let noise = protocol
+                .builder(secret, &prologue(b"NoiseSocketInit1", &[&offer]))?
+                .build_responder()
+                .map_err(ScallopError::InitFailed)?;
+
+            (protocol, response, noise)
+        } else {
+            // handshake message uses some other protocol, skip it
+            let len = stream.read_u16().await? as usize;
+            if len > limits.max_message {
+                return Err(ScallopError::MessageTooLarge {
+                    len,
+                    max: limits.max_message,
+                });
+            }
+            tokio::io::copy(&mut (&mut stream).take(len as u64), &mut tokio::io::sink()).await?;
+
+            // request a retry with an empty handshake message
+            stream
+                .write_all(&[0, 2, response[0], response[1], 0, 0])
+                .await?;
+            stream.flush().await?;
+
+            let noise = protocol
+                .builder(secret, &prologue(b"NoiseSocketInit2", &[&offer, &response]))?
+                .build_responder()
+                .map_err(ScallopError::InitFailed)?;
+
+            // read negotiation length
+            let len = stream.read_u16().await?;
+
+            // length should be zero
+            if len != 0 {
+                return Err(ScallopError::InvalidNegotiation);
+            }
+
+            // both sides already know the protocol
+            (protocol, vec![], noise)
+        }
+    };
 
     // read and handle handshake message
-    let len = noise_read(&mut noise, &mut stream, &mut buf, &mut noise_buf).await?;
+    let payload = noise_read(&mut noise, &mut stream, limits.max_message).await?;
 
     // handshake payload should be empty
-    if len != 0 {
-        return Err(ScallopError::ProtocolError(
-            "non zero first handshake payload".into(),
-        ));
+    if !payload.is_empty() {
+        return Err(ScallopError::InvalidPayload);
     }
 
     //---- -> e, s end ----//
 
     //---- <- e, ee, se, s, es start ----//
 
-    // negotiation length
-    buf[0..2].copy_from_slice(&0u16.to_be_bytes());
-
     // request auth if auth_store is available
     // and static key is not found in the auth store
     let remote_static: [u8; 32] = noise
@@ -574,14 +1362,26 @@ pub async fn new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     let should_ask_auth =
         auth_store.is_some() && !auth_store.as_mut().unwrap().contains(&remote_static);
 
-    let payload = &[0u8, 1u8, if !should_ask_auth { 0u8 } else { 1u8 }];
+    let mut payload = vec![0u8, 1u8, if !should_ask_auth { 0u8 } else { 1u8 }];
 
-    // encode and send handshake message
-    noise_write(&mut noise, &mut stream, payload, &mut buf, 2).await?;
+    // grant a ticket if asked for, offer has been validated if not empty
+    if let Some(tickets) = tickets.filter(|_| {
+        offer
+            .get(2..)
+            .unwrap_or_default()
+            .contains(&TICKETS_EXTENSION)
+    }) {
+        payload.extend_from_slice(&tickets.grant(protocol, &remote_static));
+    }
+
+    // encode and send handshake message after the negotiation payload, empty unless the
+    // client offered protocols in its first message
+    noise_write(&mut noise, &mut stream, &negotiation(&response), &payload).await?;
 
     //---- <- e, ee, se, s, es end ----//
 
     // handshake is done, switch to transport mode
+    let exporter = Exporter::new(&mut noise, protocol);
     let mut noise = noise.into_transport_mode()?;
 
     //---- -> CLIENTFIN start ----//
@@ -593,45 +1393,54 @@ pub async fn new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     // two bytes payload size
     // payload
 
+    // attestations are only accepted if we asked for them
+    let max = if should_ask_auth {
+        limits.max_auth_message()
+    } else {
+        limits.max_message
+    };
+
     // read and handle handshake message
-    let len = noise_read(&mut noise, &mut stream, &mut buf, &mut noise_buf).await?;
+    let payload = noise_read(&mut noise, &mut stream, max).await?;
 
     // should have at least 3 size
-    if len < 3 {
-        return Err(ScallopError::ProtocolError(
-            "invalid CLIENTFIN length".into(),
-        ));
+    if payload.len() < 3 {
+        return Err(ScallopError::InvalidPayload);
     }
 
     // payload size should match
-    if u16::from_be_bytes([noise_buf[1], noise_buf[2]]) as usize != len - 3 {
-        return Err(ScallopError::ProtocolError(
-            "invalid CLIENTFIN payload length".into(),
-        ));
+    let attestation = &payload[3..];
+    if u16::from_be_bytes([payload[1], payload[2]]) as usize != attestation.len() {
+        return Err(ScallopError::InvalidPayload);
     }
 
     // verify auth if we asked for it
     if should_ask_auth {
+        if attestation.len() > limits.max_attestation {
+            return Err(ScallopError::AttestationTooLarge {
+                len: attestation.len(),
+                max: limits.max_attestation,
+            });
+        }
+
         // verify
         let Some(pcrs) = auth_store
             .as_mut()
             .unwrap()
-            .verify(&noise_buf[3..len], &remote_static)
+            .verify(attestation, &remote_static)
         else {
-            return Err(ScallopError::ProtocolError("invalid attestation".into()));
+            return Err(ScallopError::InvalidAttestation);
         };
 
         auth_store.unwrap().set(remote_static, pcrs);
     }
 
     // auth request should be 0 or 1
-    if noise_buf[0] > 1 {
-        return Err(ScallopError::ProtocolError(
-            "invalid auth request in third payload".into(),
-        ));
+    if payload[0] > 1 {
+        return Err(ScallopError::InvalidAuthRequest);
     }
 
-    let should_send_auth = noise_buf[0] == 1;
+    let should_send_auth = payload[0] == 1;
 
     //---- -> CLIENTFIN end ----//
 
@@ -639,9 +1448,7 @@ pub async fn new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
     if should_send_auth && auther.is_none() {
         // auth requested and no auther available
         // error out
-        return Err(ScallopError::ProtocolError(
-            "auth requested but no auther available".into(),
-        ));
+        return Err(ScallopError::NoAuther);
     }
 
     //---- <- SERVERFIN start ----//
@@ -654,53 +1461,241 @@ pub async fn new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b<
 
     if should_send_auth {
         // safe to unwrap since it has been checked above
-        let payload = auther.unwrap().new_auth().await;
+        let auth = auther.unwrap().new_auth().await;
         // check if payload is not too big
-        if payload.len() > 60000 {
-            return Err(ScallopError::ProtocolError("auth payload too big".into()));
+        if auth.len() > MAX_ATTESTATION {
+            return Err(ScallopError::AttestationTooLarge {
+                len: auth.len(),
+                max: MAX_ATTESTATION,
+            });
         }
 
-        // new heap allocated buffers
-        let mut buf = vec![0u8; 65000].into_boxed_slice();
-        let mut noise_buf = vec![0u8; 65000].into_boxed_slice();
-
         // safe to cast since range has been checked above
-        noise_buf[0..2].copy_from_slice(&(payload.len() as u16).to_be_bytes());
-        noise_buf[2..2 + payload.len()].copy_from_slice(&payload);
+        let fin = [&(auth.len() as u16).to_be_bytes()[..], &auth].concat();
 
         // encode and send handshake message
-        noise_write(
-            &mut noise,
-            &mut stream,
-            &noise_buf[0..payload.len() + 2],
-            &mut buf,
-            0,
-        )
-        .await?;
+        noise_write(&mut noise, &mut stream, &[], &fin).await?;
     }
 
     //---- <- SERVERFIN end ----//
 
-    Ok(ScallopStream {
+    Ok(ScallopStream::new(
         noise,
         stream,
-        // initialize with 2 sized buffer to read length
-        rbuf: vec![0u8; 2].into_boxed_slice(),
-        pending: 2,
-        mode: ReadMode::Length,
-        read_start: 0,
-        read_end: 0,
-        wbuf: vec![].into_boxed_slice(),
-        write_start: 0,
-        write_end: 0,
-    })
+        protocol,
+        remote_static,
+        exporter,
+    ))
+}
+
+type Resumed = (TransportState, Exporter, ScallopProtocol, [u8; 32], Vec<u8>);
+
+// Ok(None) if the ticket is rejected, the client continues with a full handshake
+async fn resume_server(
+    stream: &mut (impl AsyncWrite + AsyncRead + Unpin),
+    offer: &[u8],
+    auth_store: &mut Option<impl ScallopAuthStore>,
+    protocols: &[ScallopProtocol],
+    tickets: Option<&ScallopTickets>,
+    limits: &HandshakeLimits,
+) -> Result<Option<Resumed>, ScallopError> {
+    //---- -> psk, e start ----//
+
+    // read handshake message, needed even if rejecting to skip it
+    let len = stream.read_u16().await? as usize;
+    if len > limits.max_message {
+        return Err(ScallopError::MessageTooLarge {
+            len,
+            max: limits.max_message,
+        });
+    }
+    let mut message = vec![0u8; len];
+    stream.read_exact(&mut message).await?;
+
+    // ticket has to be ours, unexpired, for the offered protocol and a trusted client
+    let opened = tickets
+        .and_then(|x| x.open(&offer[2.min(offer.len())..]))
+        .filter(|x| {
+            offer.get(1) == Some(&x.protocol.id())
+                && protocols.contains(&x.protocol)
+                && auth_store
+                    .as_mut()
+                    .is_none_or(|store| store.contains(&x.client_static))
+        });
+
+    let mut resumed = None;
+    if let (Some(tickets), Some(opened)) = (tickets, opened) {
+        let prologue = prologue(b"NoiseSocketInit1", &[offer]);
+        let mut noise = opened
+            .protocol
+            .resumption_builder(&opened.psk, &prologue)?
+            .build_responder()
+            .map_err(ScallopError::InitFailed)?;
+
+        // handle handshake message, payload is early data
+        let mut early_data = vec![0u8; len];
+        let early_len = noise.read_message(&message, &mut early_data)?;
+        early_data.truncate(early_len);
+
+        // ephemeral key leads the message, accept every one once
+        let ephemeral = message[0..32].try_into().unwrap();
+        if tickets.accept(ephemeral, opened.expiry) {
+            resumed = Some((noise, opened, tickets, early_data));
+        }
+    }
+
+    //---- -> psk, e end ----//
+
+    //---- <- e, ee start ----//
+
+    let Some((mut noise, opened, tickets, early_data)) = resumed else {
+        // reject with an empty handshake message
+        stream
+            .write_all(&[0, 2, RESUMPTION_VERSION, 0, 0, 0])
+            .await?;
+        stream.flush().await?;
+
+        return Ok(None);
+    };
+
+    // encode and send handshake message with the next ticket as payload
+    let grant = tickets.grant(opened.protocol, &opened.client_static);
+    noise_write(
+        &mut noise,
+        stream,
+        &negotiation(&[RESUMPTION_VERSION, opened.protocol.id()]),
+        &grant,
+    )
+    .await?;
+
+    //---- <- e, ee end ----//
+
+    let exporter = Exporter::new(&mut noise, opened.protocol);
+
+    Ok(Some((
+        noise.into_transport_mode()?,
+        exporter,
+        opened.protocol,
+        opened.client_static,
+        early_data,
+    )))
 }
 
 impl<Base: AsyncWrite + AsyncRead + Unpin> ScallopStream<Base> {
+    // buffers only grow and are reused for every record
+    fn new(
+        noise: TransportState,
+        stream: Base,
+        protocol: ScallopProtocol,
+        remote_static: [u8; 32],
+        exporter: Exporter,
+    ) -> Self {
+        Self {
+            noise,
+            stream,
+            protocol,
+            remote_static,
+            exporter,
+            resumed: false,
+            ticket: None,
+            rbuf: vec![0u8; INITIAL_BUFFER],
+            read_start: 0,
+            read_end: 0,
+            pbuf: Vec::new(),
+            plain_start: 0,
+            plain_end: 0,
+            wbuf: vec![0u8; INITIAL_BUFFER],
+            write_start: 0,
+            write_end: 0,
+            gather: Vec::new(),
+            limits: TransportLimits::new(),
+            records_since_rekey: 0,
+            bytes_since_rekey: 0,
+            rekeyed: false,
+            close_received: false,
+            close_sent: false,
+        }
+    }
+
     pub fn get_remote_static(&self) -> Option<[u8; 32]> {
-        self.noise
-            .get_remote_static()
-            .map(|x| x.try_into().expect("expected 32 byte key"))
+        Some(self.remote_static)
+    }
+
+    /// Protocol negotiated during the handshake
+    pub fn protocol(&self) -> ScallopProtocol {
+        self.protocol
+    }
+
+    /// Whether the session was resumed from a ticket, early data was accepted if so
+    pub fn resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Ticket issued by the server during the handshake, for resuming the session later
+    pub fn ticket(&self) -> Option<&ScallopTicket> {
+        self.ticket.as_ref()
+    }
+
+    /// Noise handshake hash, the same on both ends and unique to the session, but not
+    /// secret
+    pub fn channel_binding(&self) -> &[u8] {
+        self.exporter.channel_binding()
+    }
+
+    /// Fill `out` with a secret derived from the session, `label` and `context`, which
+    /// only the two ends of the session can compute, like the TLS exporter
+    ///
+    /// Outputs are limited to 255 times the hash length of the protocol.
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), ScallopError> {
+        self.exporter.export(label, context, out)
+    }
+
+    // early data is read before anything sent after the handshake
+    fn set_early_data(&mut self, early_data: &[u8]) {
+        self.pbuf.clear();
+        self.pbuf.extend_from_slice(early_data);
+        self.plain_start = 0;
+        self.plain_end = early_data.len();
+    }
+
+    /// Rekeying and nonce limits of the stream
+    pub fn limits(&self) -> TransportLimits {
+        self.limits
+    }
+
+    /// Set the rekeying and nonce limits, applied from the next record on
+    pub fn set_limits(&mut self, limits: TransportLimits) {
+        self.limits = limits;
+    }
+
+    // IMPORTANT: Return Pending only as a direct result of base returning Pending
+    // Ensures wakers are set up correctly
+    //
+    // writes buffered records to base without flushing it
+    fn poll_write_buffered(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        while self.write_start != self.write_end {
+            let base = std::pin::pin!(&mut self.stream);
+            let size = std::task::ready!(
+                base.poll_write(cx, &self.wbuf[self.write_start..self.write_end])
+            )?;
+            if size == 0 {
+                return std::task::Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+            self.write_start += size;
+        }
+
+        self.write_start = 0;
+        self.write_end = 0;
+
+        std::task::Poll::Ready(Ok(()))
     }
 }
 
@@ -714,113 +1709,212 @@ impl<Base: AsyncWrite + AsyncRead + Unpin> AsyncRead for ScallopStream<Base> {
     ) -> std::task::Poll<std::io::Result<()>> {
         let stream = self.get_mut();
         loop {
-            while stream.pending != 0 {
-                let base = std::pin::pin!(&mut stream.stream);
-
-                // do not have enough data, try to read more
-                let len = stream.rbuf.len();
-                let mut buf = ReadBuf::new(&mut stream.rbuf[(len - stream.pending)..]);
-                std::task::ready!(base.poll_read(cx, &mut buf))?;
-
-                // check eof
-                if buf.filled().is_empty() {
-                    return std::task::Poll::Ready(Ok(()));
-                }
-                stream.pending -= buf.filled().len();
+            // nothing is read past a close notify
+            if stream.close_received {
+                return std::task::Poll::Ready(Ok(()));
             }
 
-            // pending should always be 0 after this point
-
-            if stream.mode == ReadMode::Length {
-                // we have read the length
+            // payload left over from an earlier record
+            if stream.plain_start != stream.plain_end {
+                let len = std::cmp::min(buf.remaining(), stream.plain_end - stream.plain_start);
+                buf.put_slice(&stream.pbuf[stream.plain_start..stream.plain_start + len]);
+                stream.plain_start += len;
+                return std::task::Poll::Ready(Ok(()));
+            }
 
-                // parse length
-                let record_length = u16::from_be_bytes(stream.rbuf[0..2].try_into().unwrap());
+            // length of the next record, if known
+            let buffered = stream.read_end - stream.read_start;
+            let record_length = (buffered >= 2).then(|| {
+                u16::from_be_bytes([
+                    stream.rbuf[stream.read_start],
+                    stream.rbuf[stream.read_start + 1],
+                ]) as usize
+            });
+
+            if let Some(record_length) = record_length.filter(|x| buffered >= x + 2) {
+                // we have the full record
+                let record = stream.read_start + 2..stream.read_start + 2 + record_length;
+                stream.read_start = record.end;
+
+                if stream.noise.receiving_nonce() >= stream.limits.max_records {
+                    return std::task::Poll::Ready(Err(std::io::Error::other(
+                        "receiving nonces exhausted",
+                    )));
+                }
 
-                // set up to read record
-                stream.pending = record_length.into();
-                stream.mode = ReadMode::Body;
-                stream.rbuf = vec![0u8; stream.pending].into_boxed_slice();
-            } else if stream.mode == ReadMode::Body {
-                // we have the data
+                let payload_length = record_length.saturating_sub(TAGLEN);
+                if payload_length != 0 && buf.remaining() >= payload_length {
+                    // decrypt straight into the caller's buffer
+                    let len = stream
+                        .noise
+                        .read_message(
+                            &stream.rbuf[record],
+                            buf.initialize_unfilled_to(payload_length),
+                        )
+                        .map_err(std::io::Error::other)?;
+                    buf.advance(len);
+                    stream.rekeyed = false;
+                    return std::task::Poll::Ready(Ok(()));
+                }
 
                 // process as noise message
+                if stream.pbuf.len() < payload_length {
+                    stream.pbuf.resize(payload_length, 0);
+                }
                 let len = stream
                     .noise
-                    .read_message(&stream.rbuf.clone(), &mut stream.rbuf)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+                    .read_message(&stream.rbuf[record], &mut stream.pbuf)
+                    .map_err(std::io::Error::other)?;
 
-                // set up to send body upstream
-                stream.read_start = 0;
-                stream.read_end = len;
-                stream.mode = ReadMode::Read;
-            } else {
-                if buf.remaining() < stream.read_end - stream.read_start {
-                    // can transmit only partial
-                    let read_start = stream.read_start;
-                    stream.read_start += buf.remaining();
-                    let read_end = read_start + buf.remaining();
-                    buf.put_slice(&stream.rbuf[read_start..read_end]);
-                } else {
-                    // can transmit full
-                    buf.put_slice(&stream.rbuf[stream.read_start..stream.read_end]);
-
-                    stream.rbuf = vec![0u8; 2].into_boxed_slice();
-                    stream.pending = 2;
-                    stream.mode = ReadMode::Length;
+                if len == 0 && stream.rekeyed {
+                    // close notify
+                    stream.close_received = true;
+                    return std::task::Poll::Ready(Ok(()));
                 }
-                return std::task::Poll::Ready(Ok(()));
+
+                stream.rekeyed = len == 0;
+                if len == 0 {
+                    // empty records signal a rekey, never surface them as eof
+                    stream.noise.rekey_incoming();
+                }
+
+                stream.plain_start = 0;
+                stream.plain_end = len;
+                continue;
+            }
+
+            // do not have enough data, make sure the record fits and try to read more
+            let needed = record_length.unwrap_or(0) + 2;
+            if stream.read_start == stream.read_end {
+                stream.read_start = 0;
+                stream.read_end = 0;
+            } else if stream.read_start + needed > stream.rbuf.len() {
+                stream
+                    .rbuf
+                    .copy_within(stream.read_start..stream.read_end, 0);
+                stream.read_end -= stream.read_start;
+                stream.read_start = 0;
             }
+            // grow for records larger than the buffer
+            if needed > stream.rbuf.len() {
+                stream.rbuf.resize(needed.min(MAX_RECORD), 0);
+            }
+
+            let base = std::pin::pin!(&mut stream.stream);
+            let mut read_buf = ReadBuf::new(&mut stream.rbuf[stream.read_end..]);
+            std::task::ready!(base.poll_read(cx, &mut read_buf))?;
+
+            // check eof, only a close notify ends the stream cleanly
+            if read_buf.filled().is_empty() {
+                return std::task::Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "stream truncated without close notify",
+                )));
+            }
+            stream.read_end += read_buf.filled().len();
         }
     }
 }
 
 impl<Base: AsyncWrite + AsyncRead + Unpin> AsyncWrite for ScallopStream<Base> {
-    // IMPORTANT: Return Pending only as a direct result of base returning Pending
-    // Ensures wakers are set up correctly
     fn poll_write(
-        mut self: std::pin::Pin<&mut Self>,
+        self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
         buf: &[u8],
     ) -> std::task::Poll<Result<usize, std::io::Error>> {
-        // flush existing data first
-        std::task::ready!(self.as_mut().poll_flush(cx))?;
+        self.poll_write_vectored(cx, &[std::io::IoSlice::new(buf)])
+    }
 
-        let mut stream = self.as_mut();
+    // IMPORTANT: Return Pending only as a direct result of base returning Pending
+    // Ensures wakers are set up correctly
+    //
+    // Records are buffered until the write buffer runs out of room or the stream is
+    // flushed, so small writes share syscalls.
+    fn poll_write_vectored(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> std::task::Poll<Result<usize, std::io::Error>> {
+        let stream = self.get_mut();
+
+        // up to MAX_PAYLOAD bytes at once
+        let len = bufs
+            .iter()
+            .fold(0usize, |len, x| len.saturating_add(x.len()))
+            .min(MAX_PAYLOAD);
 
-        // construct new buf
-        // up to 64000 bytes at once
-        let len = std::cmp::min(buf.len(), 64000) as u16;
-        let mut new_buf = vec![0u8; len as usize + 1000].into_boxed_slice();
+        // empty records are reserved for control records
+        if len == 0 {
+            return std::task::Poll::Ready(Ok(0));
+        }
 
-        // set noise message
-        let noise_len = stream
+        if stream.close_sent {
+            return std::task::Poll::Ready(Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "write after close notify",
+            )));
+        }
+
+        let rekey = stream
+            .limits
+            .rekey_due(stream.records_since_rekey, stream.bytes_since_rekey);
+
+        // hard guard against running out of nonces, leaving room for the rekey record
+        if stream
             .noise
-            .write_message(&buf[0..len as usize], &mut new_buf[2..])
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .sending_nonce()
+            .saturating_add(rekey as u64 + 1)
+            > stream.limits.max_records
+        {
+            return std::task::Poll::Ready(Err(std::io::Error::other("sending nonces exhausted")));
+        }
 
-        // set length
-        new_buf[0..2].copy_from_slice(&(noise_len as u16).to_be_bytes());
+        // make room for the record and a rekey record
+        let needed = len + 2 * (2 + TAGLEN);
+        if stream.wbuf.len() - stream.write_end < needed {
+            std::task::ready!(stream.poll_write_buffered(cx))?;
+            // grow for records larger than the buffer
+            if needed > stream.wbuf.len() {
+                stream.wbuf.resize(needed.min(MAX_RECORD), 0);
+            }
+        }
+
+        if rekey {
+            // empty record under the old key, everything after uses the new key
+            stream.write_end +=
+                encode_record(&mut stream.noise, &[], &mut stream.wbuf[stream.write_end..])?;
+            stream.noise.rekey_outgoing();
+            stream.records_since_rekey = 0;
+            stream.bytes_since_rekey = 0;
+        }
+
+        // encrypt from the first buffer if possible, gather otherwise
+        let first = bufs.iter().find(|x| !x.is_empty()).unwrap();
+        let payload = if first.len() >= len {
+            &first[..len]
+        } else {
+            stream.gather.clear();
+            for buf in bufs {
+                let take = std::cmp::min(buf.len(), len - stream.gather.len());
+                stream.gather.extend_from_slice(&buf[..take]);
+            }
+            &stream.gather[..]
+        };
+
+        stream.write_end += encode_record(
+            &mut stream.noise,
+            payload,
+            &mut stream.wbuf[stream.write_end..],
+        )?;
 
-        // queue up new buf
-        stream.wbuf = new_buf;
-        stream.write_start = 0;
-        stream.write_end = noise_len + 2;
+        stream.records_since_rekey += 1;
+        stream.bytes_since_rekey += len as u64;
 
-        // TODO: Should we flush here so it does not need to be called in the common case?
-        // How do we implement this?
-        //
-        // Not sure how the semantics will play out though.
-        //
-        // Happy path looks great.
-        // We make a call to poll_flush, it returns Ready and we return Ready with length.
-        //
-        // But what if it returns Pending?
-        // If we return Pending, the caller will assume nothing was sent.
-        // If we return Ready, polL_flush has potentially set up wakers.
-        // What happens on repeated calls? Unsure if it is supposed to be idempotent.
+        std::task::Poll::Ready(Ok(len))
+    }
 
-        std::task::Poll::Ready(Ok(len as usize))
+    fn is_write_vectored(&self) -> bool {
+        true
     }
 
     // IMPORTANT: Return Pending only as a direct result of base returning Pending
@@ -831,15 +1925,8 @@ impl<Base: AsyncWrite + AsyncRead + Unpin> AsyncWrite for ScallopStream<Base> {
     ) -> std::task::Poll<Result<(), std::io::Error>> {
         let stream = self.get_mut();
 
-        while stream.write_start != stream.write_end {
-            let base = std::pin::pin!(&mut stream.stream);
-
-            // try to send existing messages first
-            let size = std::task::ready!(
-                base.poll_write(cx, &stream.wbuf[stream.write_start..stream.write_end])
-            )?;
-            stream.write_start += size;
-        }
+        // try to send existing messages first
+        std::task::ready!(stream.poll_write_buffered(cx))?;
 
         // flush data after write since base could be buffered
         let base = std::pin::pin!(&mut stream.stream);
@@ -865,8 +1952,834 @@ impl<Base: AsyncWrite + AsyncRead + Unpin> AsyncWrite for ScallopStream<Base> {
         std::task::ready!(self.as_mut().poll_flush(cx))?;
 
         let stream = self.get_mut();
-        let base = std::pin::pin!(&mut stream.stream);
 
+        if !stream.close_sent {
+            if stream.noise.sending_nonce().saturating_add(2) > stream.limits.max_records {
+                return std::task::Poll::Ready(Err(std::io::Error::other(
+                    "sending nonces exhausted",
+                )));
+            }
+
+            // close notify, an empty record after a rekey
+            // write buffer is empty after the flush
+            stream.write_end = encode_record(&mut stream.noise, &[], &mut stream.wbuf)?;
+            stream.noise.rekey_outgoing();
+            stream.write_end +=
+                encode_record(&mut stream.noise, &[], &mut stream.wbuf[stream.write_end..])?;
+            stream.close_sent = true;
+
+            std::task::ready!(std::pin::Pin::new(&mut *stream).poll_flush(cx))?;
+        }
+
+        let base = std::pin::pin!(&mut stream.stream);
         base.poll_shutdown(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::io::DuplexStream;
+
+    use super::*;
+
+    type Handshakes = (
+        Result<ScallopStream<DuplexStream>, ScallopError>,
+        Result<ScallopStream<DuplexStream>, ScallopError>,
+    );
+
+    fn secret() -> [u8; 32] {
+        Builder::new("Noise_IX_25519_ChaChaPoly_BLAKE2b".parse().unwrap())
+            .generate_keypair()
+            .unwrap()
+            .private
+            .try_into()
+            .unwrap()
+    }
+
+    // None stands for the handshakes which predate negotiation
+    async fn handshake(
+        client: Option<&'static [ScallopProtocol]>,
+        server: Option<&'static [ScallopProtocol]>,
+    ) -> Handshakes {
+        let (client_stream, server_stream) = tokio::io::duplex(10000);
+
+        let client = tokio::spawn(async move {
+            match client {
+                Some(protocols) => {
+                    new_client_async(
+                        client_stream,
+                        &secret(),
+                        None::<PolicyAuthStore>,
+                        None::<AttestationAuther>,
+                        protocols,
+                        HandshakeLimits::new(),
+                    )
+                    .await
+                }
+                None => {
+                    new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+                        client_stream,
+                        &secret(),
+                        None::<PolicyAuthStore>,
+                        None::<AttestationAuther>,
+                    )
+                    .await
+                }
+            }
+        });
+        let server = match server {
+            Some(protocols) => {
+                new_server_async(
+                    server_stream,
+                    &secret(),
+                    None::<PolicyAuthStore>,
+                    None::<AttestationAuther>,
+                    protocols,
+                    HandshakeLimits::new(),
+                )
+                .await
+            }
+            None => {
+                new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+                    server_stream,
+                    &secret(),
+                    None::<PolicyAuthStore>,
+                    None::<AttestationAuther>,
+                )
+                .await
+            }
+        };
+
+        (client.await.unwrap(), server)
+    }
+
+    async fn negotiated(handshakes: Handshakes) -> ScallopProtocol {
+        let (mut client, mut server) = (handshakes.0.unwrap(), handshakes.1.unwrap());
+        assert_eq!(client.protocol(), server.protocol());
+
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        client.protocol()
+    }
+
+    fn rejected(handshakes: Handshakes) {
+        assert!(matches!(handshakes.0, Err(ScallopError::NoCommonProtocol)));
+        assert!(matches!(handshakes.1, Err(ScallopError::NoCommonProtocol)));
+    }
+
+    #[tokio::test]
+    async fn test_negotiation() {
+        use ScallopProtocol::*;
+
+        // handshakes which predate negotiation
+        assert_eq!(
+            negotiated(handshake(None, None).await).await,
+            Noise_IX_25519_ChaChaPoly_BLAKE2b
+        );
+        assert_eq!(
+            negotiated(handshake(None, Some(&ScallopProtocol::ALL)).await).await,
+            Noise_IX_25519_ChaChaPoly_BLAKE2b
+        );
+        assert_eq!(
+            negotiated(handshake(Some(&[Noise_IX_25519_ChaChaPoly_BLAKE2b]), None).await).await,
+            Noise_IX_25519_ChaChaPoly_BLAKE2b
+        );
+
+        // first offer accepted
+        assert_eq!(
+            negotiated(
+                handshake(
+                    Some(&[Noise_IX_25519_AESGCM_SHA256]),
+                    Some(&ScallopProtocol::ALL)
+                )
+                .await
+            )
+            .await,
+            Noise_IX_25519_AESGCM_SHA256
+        );
+
+        // retry with a later offer
+        assert_eq!(
+            negotiated(
+                handshake(
+                    Some(&[
+                        Noise_IX_25519_AESGCM_SHA256,
+                        Noise_IX_25519_ChaChaPoly_BLAKE2b
+                    ]),
+                    None
+                )
+                .await
+            )
+            .await,
+            Noise_IX_25519_ChaChaPoly_BLAKE2b
+        );
+        assert_eq!(
+            negotiated(
+                handshake(
+                    Some(&[
+                        Noise_IX_25519_ChaChaPoly_SHA256,
+                        Noise_IX_25519_AESGCM_BLAKE2b,
+                        Noise_IX_25519_AESGCM_SHA256
+                    ]),
+                    Some(&[Noise_IX_25519_AESGCM_SHA256, Noise_IX_25519_AESGCM_BLAKE2b])
+                )
+                .await
+            )
+            .await,
+            Noise_IX_25519_AESGCM_BLAKE2b
+        );
+
+        // no common protocol
+        rejected(
+            handshake(
+                Some(&[Noise_IX_25519_AESGCM_SHA256]),
+                Some(&[Noise_IX_25519_ChaChaPoly_BLAKE2b]),
+            )
+            .await,
+        );
+        let (_, server) = handshake(None, Some(&[Noise_IX_25519_AESGCM_SHA256])).await;
+        assert!(matches!(server, Err(ScallopError::NoCommonProtocol)));
+    }
+
+    #[tokio::test]
+    async fn test_negotiation_unknown_protocols() {
+        let (mut client, server) = tokio::io::duplex(10000);
+        let server = tokio::spawn(async move {
+            new_server_async(
+                server,
+                &secret(),
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            )
+            .await
+        });
+
+        // offer from a newer client, unknown protocols are skipped
+        client
+            .write_all(&[0, 5, 1, 3, 0xff, 4, 2, 0, 0])
+            .await
+            .unwrap();
+
+        // retry request for the first known protocol
+        let mut response = [0u8; 6];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, [0, 2, 1, 4, 0, 0]);
+
+        drop(client);
+        assert!(server.await.unwrap().is_err());
+    }
+
+    async fn connected() -> (ScallopStream<DuplexStream>, ScallopStream<DuplexStream>) {
+        let (client, server) = handshake(None, None).await;
+        (client.unwrap(), server.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_rekey() {
+        let (mut client, mut server) = connected().await;
+        client.set_limits(TransportLimits::new().rekey_records(3).rekey_bytes(100));
+
+        // the final handshake messages may already have used nonces
+        let nonce = client.noise.sending_nonce();
+        assert_eq!(server.noise.receiving_nonce(), nonce);
+        let server_nonce = server.noise.sending_nonce();
+
+        // empty writes do not produce records
+        assert_eq!(client.write(&[]).await.unwrap(), 0);
+        assert_eq!(client.noise.sending_nonce(), nonce);
+
+        // rekeys before the 4th, 7th and 10th records, and after 100 bytes
+        for i in 0..10u8 {
+            client.write_all(&[i; 5]).await.unwrap();
+            client.flush().await.unwrap();
+            let mut buf = [0u8; 5];
+            server.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, [i; 5]);
+        }
+        assert_eq!(client.noise.sending_nonce(), nonce + 13);
+        assert_eq!(server.noise.receiving_nonce(), nonce + 13);
+
+        client.write_all(&[0xaa; 200]).await.unwrap();
+        client.write_all(&[0xbb; 5]).await.unwrap();
+        client.flush().await.unwrap();
+        let mut buf = [0u8; 205];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf[..200], [0xaa; 200]);
+        assert_eq!(buf[200..], [0xbb; 5]);
+        assert_eq!(client.noise.sending_nonce(), nonce + 16);
+
+        // the other direction is unaffected
+        server.write_all(b"hello").await.unwrap();
+        server.flush().await.unwrap();
+        let mut buf = [0u8; 5];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+        assert_eq!(server.noise.sending_nonce(), server_nonce + 1);
+    }
+
+    #[tokio::test]
+    async fn test_nonce_exhaustion() {
+        let (mut client, mut server) = connected().await;
+        let nonce = client.noise.sending_nonce();
+        client.set_limits(TransportLimits::new().max_records(nonce + 3));
+        server.set_limits(TransportLimits::new().max_records(nonce + 2));
+
+        for _ in 0..3 {
+            client.write_all(b"hello").await.unwrap();
+        }
+        client.flush().await.unwrap();
+        let err = client.write_all(b"hello").await.unwrap_err();
+        assert_eq!(err.to_string(), "sending nonces exhausted");
+
+        let mut buf = [0u8; 10];
+        server.read_exact(&mut buf).await.unwrap();
+        let err = server.read_exact(&mut buf[..5]).await.unwrap_err();
+        assert_eq!(err.to_string(), "receiving nonces exhausted");
+
+        // rekey records count towards the limit
+        let (mut client, _server) = connected().await;
+        let nonce = client.noise.sending_nonce();
+        client.set_limits(
+            TransportLimits::new()
+                .rekey_records(1)
+                .max_records(nonce + 2),
+        );
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        assert!(client.write_all(b"hello").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_close_notify() {
+        let (mut client, mut server) = connected().await;
+
+        client.write_all(b"hello").await.unwrap();
+        client.shutdown().await.unwrap();
+        let err = client.write_all(b"hello").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+
+        let mut buf = vec![];
+        server.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hello");
+        assert_eq!(server.read(&mut [0u8; 5]).await.unwrap(), 0);
+
+        // still rekeys with a close notify pending
+        let (mut client, mut server) = connected().await;
+        client.set_limits(TransportLimits::new().rekey_records(1));
+        for _ in 0..3 {
+            client.write_all(b"hello").await.unwrap();
+        }
+        client.shutdown().await.unwrap();
+
+        let mut buf = vec![];
+        server.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, b"hellohellohello");
+    }
+
+    #[tokio::test]
+    async fn test_truncation() {
+        // at a record boundary
+        let (mut client, mut server) = connected().await;
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        client.stream.shutdown().await.unwrap();
+
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        let err = server.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // in the middle of a record
+        let (mut client, mut server) = connected().await;
+        client.write_all(b"hello").await.unwrap();
+        let record = client.wbuf[..client.write_end].to_vec();
+        client.stream.write_all(&record[..10]).await.unwrap();
+        client.stream.shutdown().await.unwrap();
+
+        let err = server.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        // right after a rekey record, which looks like the start of a close notify
+        let (mut client, mut server) = connected().await;
+        client.set_limits(TransportLimits::new().rekey_records(1));
+        client.write_all(b"hello").await.unwrap();
+        client.write_all(b"hello").await.unwrap();
+        // both records are buffered, only the first one and the rekey get through
+        let records = client.wbuf[..client.write_end].to_vec();
+        client.stream.write_all(&records[..23 + 18]).await.unwrap();
+        client.stream.shutdown().await.unwrap();
+
+        let mut buf = [0u8; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        let err = server.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    async fn test_channel_binding() {
+        fn export(stream: &ScallopStream<DuplexStream>, label: &[u8], context: &[u8]) -> Vec<u8> {
+            let mut out = vec![0u8; 100];
+            stream
+                .export_keying_material(label, context, &mut out)
+                .unwrap();
+            out
+        }
+
+        let (client, server) = connected().await;
+        assert_eq!(client.channel_binding(), server.channel_binding());
+        assert_eq!(client.channel_binding().len(), 64);
+        assert_eq!(export(&client, b"a", b"b"), export(&server, b"a", b"b"));
+
+        // labels, contexts and lengths give unrelated outputs
+        assert_ne!(export(&client, b"a", b"b"), export(&client, b"ab", b""));
+        assert_ne!(export(&client, b"a", b"b"), export(&client, b"a", b"c"));
+        let mut short = [0u8; 32];
+        client
+            .export_keying_material(b"a", b"b", &mut short)
+            .unwrap();
+        assert_ne!(short, export(&client, b"a", b"b")[..32]);
+
+        // other sessions have other values
+        let (other, _) = connected().await;
+        assert_ne!(client.channel_binding(), other.channel_binding());
+        assert_ne!(export(&client, b"a", b"b"), export(&other, b"a", b"b"));
+
+        let (client, server) = handshake(
+            Some(&[ScallopProtocol::Noise_IX_25519_AESGCM_SHA256]),
+            Some(&ScallopProtocol::ALL),
+        )
+        .await;
+        let (client, server) = (client.unwrap(), server.unwrap());
+        assert_eq!(client.channel_binding(), server.channel_binding());
+        assert_eq!(client.channel_binding().len(), 32);
+        assert_eq!(export(&client, b"a", b""), export(&server, b"a", b""));
+        let mut long = vec![0u8; 255 * 32 + 1];
+        assert!(client.export_keying_material(b"a", b"", &mut long).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_debug() {
+        let (client, _server) = connected().await;
+        let debug = format!("{client:?}");
+        assert!(debug.contains(&format!("{:?}", client.protocol())));
+        assert!(debug.contains(&hex::encode(client.get_remote_static().unwrap())));
+        assert!(debug.contains("resumed: false"));
+        assert!(debug.contains("limits: TransportLimits"));
+        assert!(!debug.contains("exporter"));
+        assert!(!debug.contains("buf"));
+    }
+
+    #[tokio::test]
+    async fn test_record_io() {
+        let (mut client, mut server) = connected().await;
+        let nonce = client.noise.sending_nonce();
+
+        // vectored writes gather into a single record
+        let bufs = [
+            std::io::IoSlice::new(b"he"),
+            std::io::IoSlice::new(b""),
+            std::io::IoSlice::new(b"llo"),
+        ];
+        assert!(client.is_write_vectored());
+        assert_eq!(client.write_vectored(&bufs).await.unwrap(), 5);
+        client.write_all(b" world").await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(client.noise.sending_nonce(), nonce + 2);
+
+        // small reads are served from the decrypted payload
+        let mut buf = [0u8; 11];
+        for chunk in buf.chunks_mut(2) {
+            server.read_exact(chunk).await.unwrap();
+        }
+        assert_eq!(&buf, b"hello world");
+
+        // buffers only grow as far as the records need
+        assert_eq!(client.wbuf.len(), INITIAL_BUFFER);
+        assert_eq!(server.rbuf.len(), INITIAL_BUFFER);
+        assert_eq!(server.pbuf.len(), 6);
+
+        // large writes are split into records
+        let data: Vec<u8> = (0..200000u32).map(|x| x as u8).collect();
+        let expected = data.clone();
+        let writer = tokio::spawn(async move {
+            client.write_all(&data).await.unwrap();
+            client.shutdown().await.unwrap();
+            client
+        });
+
+        let mut buf = vec![];
+        server.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf, expected);
+        assert_eq!(server.rbuf.len(), 2 + MAX_PAYLOAD + TAGLEN);
+
+        // 4 data records and the close notify
+        let client = writer.await.unwrap();
+        assert_eq!(client.noise.sending_nonce(), nonce + 2 + 4 + 2);
+        assert_eq!(client.wbuf.len(), MAX_PAYLOAD + 2 * (2 + TAGLEN));
+    }
+
+    async fn resumable(
+        tickets: Arc<ScallopTickets>,
+        ticket: Option<ScallopTicket>,
+        early_data: &'static [u8],
+    ) -> (ScallopStream<DuplexStream>, ScallopStream<DuplexStream>) {
+        let (client_stream, server_stream) = tokio::io::duplex(100000);
+        let client = tokio::spawn(async move {
+            new_client_resumable_async(
+                client_stream,
+                &secret(),
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                ticket.as_ref(),
+                early_data,
+                HandshakeLimits::new(),
+            )
+            .await
+        });
+        let server = new_server_resumable_async(
+            server_stream,
+            &secret(),
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            &ScallopProtocol::ALL,
+            &tickets,
+            HandshakeLimits::new(),
+        )
+        .await
+        .unwrap();
+
+        (client.await.unwrap().unwrap(), server)
+    }
+
+    async fn read_bytes(stream: &mut ScallopStream<DuplexStream>, len: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_resumption() {
+        let tickets = Arc::new(ScallopTickets::new());
+
+        // full handshake issues a ticket, early data is sent after it
+        let (client, mut server) = resumable(tickets.clone(), None, b"first").await;
+        assert!(!client.resumed() && !server.resumed());
+        assert_eq!(read_bytes(&mut server, 5).await, b"first");
+        let ticket = client.ticket().unwrap().clone();
+        assert_eq!(client.get_remote_static(), Some(ticket.server_static()));
+        assert!(!ticket.is_expired());
+
+        // resumption with early data
+        let (mut client, mut server) =
+            resumable(tickets.clone(), Some(ticket.clone()), b"hello").await;
+        assert!(client.resumed() && server.resumed());
+        assert_eq!(client.channel_binding(), server.channel_binding());
+        assert_eq!(client.get_remote_static(), Some(ticket.server_static()));
+        client.write_all(b" world").await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(read_bytes(&mut server, 11).await, b"hello world");
+        server.write_all(b"back").await.unwrap();
+        server.flush().await.unwrap();
+        assert_eq!(read_bytes(&mut client, 4).await, b"back");
+
+        // resumed sessions get a new ticket, old ones keep working until they expire
+        let next = client.ticket().unwrap().clone();
+        let (client, _) = resumable(tickets.clone(), Some(next), b"").await;
+        assert!(client.resumed());
+        let (client, _) = resumable(tickets.clone(), Some(ticket.clone()), b"").await;
+        assert!(client.resumed());
+
+        // tickets of other servers fall back to a full handshake, early data still arrives
+        let (client, mut server) = resumable(
+            Arc::new(ScallopTickets::new()),
+            Some(ticket.clone()),
+            b"again",
+        )
+        .await;
+        assert!(!client.resumed() && !server.resumed());
+        assert!(client.ticket().is_some());
+        assert_eq!(read_bytes(&mut server, 5).await, b"again");
+
+        // expired tickets are not even offered
+        let mut expired = ticket.clone();
+        expired.expires = std::time::Instant::now();
+        let (client, _) = resumable(tickets.clone(), Some(expired), b"").await;
+        assert!(!client.resumed());
+
+        // servers without tickets ignore the request
+        let (client, server) = tokio::io::duplex(10000);
+        let client = tokio::spawn(async move {
+            new_client_resumable_async(
+                client,
+                &secret(),
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                None,
+                b"",
+                HandshakeLimits::new(),
+            )
+            .await
+        });
+        let mut server = new_server_async(
+            server,
+            &secret(),
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            &ScallopProtocol::ALL,
+            HandshakeLimits::new(),
+        )
+        .await
+        .unwrap();
+        let mut client = client.await.unwrap().unwrap();
+        assert!(client.ticket().is_none());
+        client.write_all(b"hello").await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(read_bytes(&mut server, 5).await, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_resumption_replay() {
+        let tickets = Arc::new(ScallopTickets::new());
+        let (client, _) = resumable(tickets.clone(), None, b"").await;
+        let ticket = client.ticket().unwrap().clone();
+
+        // record the first flight of a resumption
+        let (mut client, mut wire) = tokio::io::duplex(100000);
+        tokio::spawn(async move {
+            resume_client(&mut client, &ticket, b"pay once", &HandshakeLimits::new()).await
+        });
+        let mut flight = vec![];
+        for _ in 0..2 {
+            let len = wire.read_u16().await.unwrap();
+            flight.extend_from_slice(&len.to_be_bytes());
+            let start = flight.len();
+            flight.resize(start + len as usize, 0);
+            wire.read_exact(&mut flight[start..]).await.unwrap();
+        }
+
+        // accepted once, the replay is rejected and has to complete a full handshake
+        for accepted in [true, false] {
+            let (mut client, server) = tokio::io::duplex(100000);
+            let tickets = tickets.clone();
+            let server = tokio::spawn(async move {
+                new_server_resumable_async(
+                    server,
+                    &secret(),
+                    None::<PolicyAuthStore>,
+                    None::<AttestationAuther>,
+                    &ScallopProtocol::ALL,
+                    &tickets,
+                    HandshakeLimits::new(),
+                )
+                .await
+            });
+            client.write_all(&flight).await.unwrap();
+
+            let mut response = [0u8; 4];
+            client.read_exact(&mut response).await.unwrap();
+            assert_eq!(response[..3], [0, 2, RESUMPTION_VERSION]);
+            assert_eq!(response[3] != 0, accepted);
+
+            if accepted {
+                let mut server = server.await.unwrap().unwrap();
+                assert_eq!(read_bytes(&mut server, 8).await, b"pay once");
+            } else {
+                drop(client);
+                assert!(server.await.unwrap().is_err());
+            }
+        }
+
+        // capacity bounds the window, resumptions beyond it fall back
+        let tickets = Arc::new(ScallopTickets::new().capacity(1));
+        let (client, _) = resumable(tickets.clone(), None, b"").await;
+        let ticket = client.ticket().unwrap().clone();
+        let (client, _) = resumable(tickets.clone(), Some(ticket.clone()), b"").await;
+        assert!(client.resumed());
+        let (client, _) = resumable(tickets.clone(), Some(ticket), b"").await;
+        assert!(!client.resumed());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_timeout() {
+        // client connects and never says anything
+        let (_client, server) = tokio::io::duplex(10000);
+        let err = new_server_async(
+            server,
+            &secret(),
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            &ScallopProtocol::ALL,
+            HandshakeLimits::new().timeout(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ScallopError::HandshakeTimeout));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_message_limit() {
+        let (mut client, server) = tokio::io::duplex(10000);
+        let server = tokio::spawn(async move {
+            new_server_async(
+                server,
+                &secret(),
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            )
+            .await
+        });
+
+        // empty offer followed by the largest length possible, rejected before reading it
+        client.write_all(&[0, 0, 0xff, 0xff]).await.unwrap();
+        assert!(matches!(
+            server.await.unwrap(),
+            Err(ScallopError::MessageTooLarge {
+                len: 65535,
+                max: 1024
+            })
+        ));
+
+        // same for resumptions, whether the ticket is valid or not
+        let (mut client, server) = tokio::io::duplex(10000);
+        let server = tokio::spawn(async move {
+            new_server_resumable_async(
+                server,
+                &secret(),
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                &ScallopTickets::new(),
+                HandshakeLimits::new().max_message(2000),
+            )
+            .await
+        });
+
+        client
+            .write_all(&[0, 2, RESUMPTION_VERSION, 1, 0xff, 0xff])
+            .await
+            .unwrap();
+        assert!(matches!(
+            server.await.unwrap(),
+            Err(ScallopError::MessageTooLarge {
+                len: 65535,
+                max: 2000
+            })
+        ));
+    }
+
+    // authers do not have to be Send
+    struct RcAuther(std::rc::Rc<Vec<u8>>);
+
+    impl ScallopAuther for RcAuther {
+        async fn new_auth(&mut self) -> Box<[u8]> {
+            let auth = self.0.clone();
+            tokio::task::yield_now().await;
+            auth.as_slice().into()
+        }
+    }
+
+    // accepts the attestation of RcAuther
+    struct RcStore;
+
+    impl ScallopAuthStore for RcStore {
+        fn contains(&self, _key: &[u8; 32]) -> bool {
+            false
+        }
+
+        fn get(&self, _key: &[u8; 32]) -> Option<&([u8; 48], [u8; 48], [u8; 48])> {
+            None
+        }
+
+        fn set(&mut self, _key: [u8; 32], _pcrs: ([u8; 48], [u8; 48], [u8; 48])) {}
+
+        fn verify(
+            &mut self,
+            attestation: &[u8],
+            _key: &[u8; 32],
+        ) -> Option<([u8; 48], [u8; 48], [u8; 48])> {
+            (attestation == b"rc auth").then_some(([0; 48], [1; 48], [2; 48]))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_send_auther() {
+        let (client_stream, server_stream) = tokio::io::duplex(10000);
+        let (client_secret, server_secret) = (secret(), secret());
+        let (client, server) = tokio::join!(
+            new_client_async(
+                client_stream,
+                &client_secret,
+                None::<PolicyAuthStore>,
+                Some(RcAuther(std::rc::Rc::new(b"rc auth".to_vec()))),
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            ),
+            new_server_async(
+                server_stream,
+                &server_secret,
+                Some(RcStore),
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            ),
+        );
+        client.unwrap();
+        server.unwrap();
+    }
+
+    struct JunkAuther(usize);
+
+    impl ScallopAuther for JunkAuther {
+        async fn new_auth(&mut self) -> Box<[u8]> {
+            vec![0u8; self.0].into()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attestation_limit() {
+        let (client_stream, server_stream) = tokio::io::duplex(10000);
+        let client = tokio::spawn(async move {
+            new_client_async(
+                client_stream,
+                &secret(),
+                None::<PolicyAuthStore>,
+                Some(JunkAuther(500)),
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            )
+            .await
+        });
+
+        // auth is asked for, attestations are limited separately from other messages
+        let err = new_server_async(
+            server_stream,
+            &secret(),
+            Some(PolicyAuthStore::new(
+                crate::attestation::AttestationPolicy::new(),
+            )),
+            None::<AttestationAuther>,
+            &ScallopProtocol::ALL,
+            HandshakeLimits::new().max_attestation(100),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ScallopError::MessageTooLarge { max, .. } if max == 100 + 3 + TAGLEN
+        ));
+        // the client only waits for the server finish if it asks for auth
+        client.await.unwrap().unwrap();
+    }
+}
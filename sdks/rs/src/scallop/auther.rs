@@ -0,0 +1,237 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::Uri;
+use hyper_util::client::legacy::connect::Connect;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::attestation::{AttestationError, FreshAttestation, FreshAttestationClient};
+
+use super::ScallopAuther;
+
+/// [`ScallopAuther`] serving attestations from the customizable routes of an
+/// `attestation/server` compatible server
+///
+/// Attestations are requested with the Scallop static public key as the public key and
+/// cached, a background task replaces the cached attestation every refresh interval so
+/// handshakes never wait on the attestation server. If a refresh fails, the previous
+/// attestation keeps being served and the refresh is retried after a tenth of the interval.
+/// The interval should be comfortably below the max age the remotes accept.
+///
+/// The task stops once the auther and all its clones are dropped.
+///
+/// ```ignore
+/// // over tcp
+/// let auther = AttestationAuther::new("http://127.0.0.1:1350".parse()?, static_key).await?;
+///
+/// // over vsock, or any other transport, with a custom connector
+/// let client = FreshAttestationClient::with_connector(vsock_connector).retries(3);
+/// let auther = AttestationAuther::with_client(client, endpoint, static_key, Duration::from_secs(60)).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AttestationAuther {
+    current: watch::Receiver<Arc<FreshAttestation>>,
+    _refresh: Arc<RefreshTask>,
+}
+
+// aborts the refresh task on drop
+#[derive(Debug)]
+struct RefreshTask(JoinHandle<()>);
+
+impl Drop for RefreshTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl AttestationAuther {
+    /// Auther fetching over plain http from `endpoint`, e.g. `http://127.0.0.1:1350`,
+    /// refreshing every minute
+    ///
+    /// Attestations are verified against the AWS Nitro Enclaves root, use
+    /// [`AttestationAuther::with_client`] for anything else.
+    pub async fn new(endpoint: Uri, static_key: [u8; 32]) -> Result<Self, AttestationError> {
+        Self::with_client(
+            FreshAttestationClient::new(),
+            endpoint,
+            static_key,
+            Duration::from_secs(60),
+        )
+        .await
+    }
+
+    /// Auther fetching with the given client, which determines the transport, timeouts,
+    /// retries and the policy our own attestations are checked against
+    ///
+    /// Fails if the first attestation cannot be fetched.
+    pub async fn with_client<C>(
+        client: FreshAttestationClient<C>,
+        endpoint: Uri,
+        static_key: [u8; 32],
+        refresh: Duration,
+    ) -> Result<Self, AttestationError>
+    where
+        C: Connect + Clone + Send + Sync + 'static,
+    {
+        let client = client.public_key(static_key);
+        let (tx, rx) = watch::channel(Arc::new(client.fetch(&endpoint).await?));
+
+        let task = tokio::spawn(async move {
+            let mut delay = refresh;
+            loop {
+                tokio::time::sleep(delay).await;
+                match client.fetch(&endpoint).await {
+                    Ok(fresh) => {
+                        tx.send_replace(Arc::new(fresh));
+                        delay = refresh;
+                    }
+                    // keep serving the previous attestation
+                    Err(_) => delay = refresh / 10,
+                }
+            }
+        });
+
+        Ok(Self {
+            current: rx,
+            _refresh: Arc::new(RefreshTask(task)),
+        })
+    }
+
+    /// Attestation currently being served
+    pub fn attestation(&self) -> Arc<FreshAttestation> {
+        self.current.borrow().clone()
+    }
+}
+
+impl ScallopAuther for AttestationAuther {
+    async fn new_auth(&mut self) -> Box<[u8]> {
+        self.current.borrow().attestation.as_slice().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use axum::extract::{Query, State};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::attestation::builder::mock_attestation;
+    use crate::attestation::{AttestationDocument, AttestationPolicy, TrustAnchors};
+
+    #[derive(Clone, Default)]
+    struct Server {
+        requests: Arc<AtomicUsize>,
+        failing: Arc<AtomicBool>,
+    }
+
+    async fn handle_raw(
+        State(server): State<Server>,
+        Query(query): Query<HashMap<String, String>>,
+    ) -> Result<Vec<u8>, StatusCode> {
+        server.requests.fetch_add(1, Ordering::SeqCst);
+        if server.failing.load(Ordering::SeqCst) {
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+
+        let public_key = hex::decode(&query["public_key"]).unwrap();
+        let nonce = hex::decode(&query["nonce"]).unwrap();
+        Ok(mock_attestation(Some(&public_key), None, Some(&nonce)))
+    }
+
+    async fn serve(server: Server) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/attestation/raw", get(handle_raw))
+            .with_state(server);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        addr
+    }
+
+    async fn auther(addr: SocketAddr) -> Result<AttestationAuther, AttestationError> {
+        AttestationAuther::with_client(
+            FreshAttestationClient::new()
+                .policy(AttestationPolicy::new().trust_anchors(TrustAnchors::oyster_mock())),
+            format!("http://{addr}").parse().unwrap(),
+            [7u8; 32],
+            Duration::from_millis(200),
+        )
+        .await
+    }
+
+    // fetching and verifying is slow in debug builds, poll instead of sleeping for fixed times
+    async fn eventually(mut condition: impl FnMut() -> bool) {
+        for _ in 0..100 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("condition not reached");
+    }
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let server = Server::default();
+        let addr = serve(server.clone()).await;
+        let requests = || server.requests.load(Ordering::SeqCst);
+
+        let mut auther = auther(addr).await.unwrap();
+        assert_eq!(requests(), 1);
+
+        let first = auther.new_auth().await;
+        let doc = AttestationDocument::from_cose(&first).unwrap();
+        assert_eq!(doc.public_key.unwrap().as_slice(), [7u8; 32]);
+        assert_eq!(*first, *auther.attestation().attestation);
+
+        // served from the cache until the refresh
+        assert_eq!(auther.new_auth().await, first);
+        assert_eq!(requests(), 1);
+
+        eventually(|| *auther.attestation().attestation != *first).await;
+        let second = auther.new_auth().await;
+        assert_ne!(second, first);
+
+        // failed refreshes keep the previous attestation and retry sooner
+        server.failing.store(true, Ordering::SeqCst);
+        let before = requests();
+        eventually(|| requests() >= before + 3).await;
+        assert_eq!(auther.new_auth().await, second);
+
+        server.failing.store(false, Ordering::SeqCst);
+        eventually(|| *auther.attestation().attestation != *second).await;
+
+        // refreshes stop once every clone is dropped
+        let clone = auther.clone();
+        drop(auther);
+        let before = requests();
+        eventually(|| requests() > before).await;
+
+        drop(clone);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let before = requests();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(requests(), before);
+    }
+
+    #[tokio::test]
+    async fn test_initial_failure() {
+        let server = Server::default();
+        server.failing.store(true, Ordering::SeqCst);
+        let addr = serve(server.clone()).await;
+
+        let err = auther(addr).await.unwrap_err();
+        assert!(
+            matches!(err, AttestationError::HttpStatus(status) if status == StatusCode::INTERNAL_SERVER_ERROR)
+        );
+    }
+}
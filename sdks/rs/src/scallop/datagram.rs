@@ -0,0 +1,951 @@
+// Scallop over datagrams
+//
+// Same IX handshake and optional attestations as streams, with every message in a
+// datagram of its own. Datagrams can be lost, duplicated and reordered, so
+// - transport messages carry their nonce and are decrypted without any state
+// - receivers drop nonces they have seen before or which fall behind the replay window
+// - the client retransmits its last handshake message until the server answers and the
+//   server answers retransmissions with its last handshake message
+//
+// Every datagram starts with its type:
+// - 0x01 client hello, protocol id | -> e, s
+// - 0x02 server hello, <- e, ee, se, s, es with the auth request (u8) as payload
+// - 0x03 client finish, nonce (u64) | transport message with
+//   auth request (u8) | attestation as payload
+// - 0x04 server finish, nonce (u64) | transport message with the attestation as payload,
+//   sent even without one so the client knows the handshake is done
+// - 0x05 data, nonce (u64) | transport message
+//
+// Finish messages use nonce 0 in both directions, data starts at nonce 1.
+// Handshakes use "ScallopDatagram" | protocol id as the prologue, there is no negotiation,
+// servers ignore clients using protocols they do not support.
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use snow::StatelessTransportState;
+use tokio::net::UdpSocket;
+use tokio::time::{sleep_until, timeout_at, Instant};
+
+use super::exporter::Exporter;
+use super::{
+    ScallopAuthStore, ScallopAuther, ScallopError, ScallopProtocol, MAX_ATTESTATION, MAX_PAYLOAD,
+    TAGLEN,
+};
+
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const CLIENT_FINISH: u8 = 3;
+const SERVER_FINISH: u8 = 4;
+const DATA: u8 = 5;
+
+// type | nonce
+const HEADER_LEN: usize = 9;
+// largest payload of an IPv4 UDP datagram
+const MAX_DATAGRAM: usize = 65507;
+// nonces this far behind the latest one are dropped
+const REPLAY_WINDOW: u64 = 1024;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn prologue(protocol: ScallopProtocol) -> Vec<u8> {
+    [b"ScallopDatagram".as_slice(), &[protocol.id()]].concat()
+}
+
+// type | nonce | transport message
+fn seal(
+    noise: &StatelessTransportState,
+    kind: u8,
+    nonce: u64,
+    payload: &[u8],
+) -> Result<Vec<u8>, snow::Error> {
+    let mut packet = vec![0u8; HEADER_LEN + payload.len() + TAGLEN];
+    packet[0] = kind;
+    packet[1..HEADER_LEN].copy_from_slice(&nonce.to_be_bytes());
+    let len = noise.write_message(nonce, payload, &mut packet[HEADER_LEN..])?;
+    packet.truncate(HEADER_LEN + len);
+
+    Ok(packet)
+}
+
+// nonce and length of the payload, None if the packet is not authentic
+fn open(
+    noise: &StatelessTransportState,
+    packet: &[u8],
+    payload: &mut [u8],
+) -> Option<(u64, usize)> {
+    if packet.len() < HEADER_LEN + TAGLEN {
+        return None;
+    }
+    let nonce = u64::from_be_bytes(packet[1..HEADER_LEN].try_into().unwrap());
+    let len = noise
+        .read_message(nonce, &packet[HEADER_LEN..], payload)
+        .ok()?;
+
+    Some((nonce, len))
+}
+
+// sliding window of accepted nonces
+struct ReplayWindow {
+    // one past the highest nonce accepted
+    next: u64,
+    bits: [u64; REPLAY_WINDOW as usize / 64],
+}
+
+impl ReplayWindow {
+    fn new() -> Self {
+        Self {
+            next: 0,
+            bits: [0; REPLAY_WINDOW as usize / 64],
+        }
+    }
+
+    fn slot(nonce: u64) -> (usize, u64) {
+        let index = nonce % REPLAY_WINDOW;
+        ((index / 64) as usize, 1 << (index % 64))
+    }
+
+    // false if the nonce has been accepted before or is too old to tell
+    fn accept(&mut self, nonce: u64) -> bool {
+        if nonce < self.next {
+            let (word, bit) = Self::slot(nonce);
+            if self.next - nonce > REPLAY_WINDOW || self.bits[word] & bit != 0 {
+                return false;
+            }
+            self.bits[word] |= bit;
+            return true;
+        }
+
+        // slots of skipped nonces are reused from nonces which fall out of the window
+        if nonce - self.next >= REPLAY_WINDOW {
+            self.bits = [0; REPLAY_WINDOW as usize / 64];
+        } else {
+            for skipped in self.next..nonce {
+                let (word, bit) = Self::slot(skipped);
+                self.bits[word] &= !bit;
+            }
+        }
+        let (word, bit) = Self::slot(nonce);
+        self.bits[word] |= bit;
+        self.next = nonce + 1;
+
+        true
+    }
+}
+
+/// Timing of datagram handshakes
+#[derive(Debug, Clone, Copy)]
+pub struct DatagramConfig {
+    retransmit: Duration,
+    timeout: Duration,
+}
+
+impl Default for DatagramConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DatagramConfig {
+    /// Retransmit after 250ms, give up after 10s
+    pub fn new() -> Self {
+        Self {
+            retransmit: Duration::from_millis(250),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Delay before the first retransmission, doubled after every retransmission
+    pub fn retransmit(mut self, retransmit: Duration) -> Self {
+        self.retransmit = retransmit;
+        self
+    }
+
+    /// Time the whole handshake can take, counted from the client hello on servers
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Scallop session over a connected UDP socket
+///
+/// Every [`send`](Self::send) is delivered as a single datagram, at most once, in no
+/// particular order, or not at all. Datagrams which are not authentic or have been
+/// received before are dropped by [`recv`](Self::recv).
+///
+/// Servers answer retransmitted handshake messages from `recv`, so they should keep
+/// receiving once the handshake is done.
+pub struct ScallopDatagram {
+    socket: UdpSocket,
+    noise: StatelessTransportState,
+    protocol: ScallopProtocol,
+    remote_static: [u8; 32],
+    exporter: Exporter,
+    send_nonce: AtomicU64,
+    replay: Mutex<ReplayWindow>,
+    // client finish which completed the handshake and the server finish, the latter is
+    // resent for retransmissions of the former until the client sends data
+    finish: Mutex<Option<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl std::fmt::Debug for ScallopDatagram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScallopDatagram")
+            .field("socket", &self.socket)
+            .field("protocol", &self.protocol)
+            .field("remote_static", &hex::encode(self.remote_static))
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScallopDatagram {
+    fn new(
+        socket: UdpSocket,
+        noise: StatelessTransportState,
+        protocol: ScallopProtocol,
+        remote_static: [u8; 32],
+        exporter: Exporter,
+        finish: Option<(Vec<u8>, Vec<u8>)>,
+    ) -> Self {
+        let mut replay = ReplayWindow::new();
+        // taken by the finish message
+        replay.accept(0);
+
+        Self {
+            socket,
+            noise,
+            protocol,
+            remote_static,
+            exporter,
+            send_nonce: AtomicU64::new(1),
+            replay: Mutex::new(replay),
+            finish: Mutex::new(finish),
+        }
+    }
+
+    pub fn get_remote_static(&self) -> Option<[u8; 32]> {
+        Some(self.remote_static)
+    }
+
+    /// Protocol used for the handshake
+    pub fn protocol(&self) -> ScallopProtocol {
+        self.protocol
+    }
+
+    /// See [`ScallopStream::channel_binding`](super::ScallopStream::channel_binding)
+    pub fn channel_binding(&self) -> &[u8] {
+        self.exporter.channel_binding()
+    }
+
+    /// See [`ScallopStream::export_keying_material`](super::ScallopStream::export_keying_material)
+    pub fn export_keying_material(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), ScallopError> {
+        self.exporter.export(label, context, out)
+    }
+
+    /// Underlying socket, connected to the remote
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Send a datagram of up to 64000 bytes
+    pub async fn send(&self, payload: &[u8]) -> io::Result<()> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "datagram too big",
+            ));
+        }
+
+        // u64::MAX is never used, like in every other Noise transport
+        let nonce = self
+            .send_nonce
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                (x < u64::MAX).then_some(x + 1)
+            })
+            .map_err(|_| io::Error::other("sending nonces exhausted"))?;
+
+        let packet = seal(&self.noise, DATA, nonce, payload).map_err(io::Error::other)?;
+        self.socket.send(&packet).await?;
+
+        Ok(())
+    }
+
+    /// Receive the next datagram, anything which does not fit in `buf` is discarded like
+    /// with UDP sockets
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut packet = vec![0u8; MAX_DATAGRAM];
+        let mut payload = vec![0u8; MAX_DATAGRAM];
+
+        loop {
+            let len = self.socket.recv(&mut packet).await?;
+            let packet = &packet[..len];
+
+            match packet.first() {
+                Some(&DATA) => {}
+                Some(&CLIENT_FINISH) => {
+                    // the server finish was lost, only answered for an exact retransmission
+                    // so that spoofed datagrams are not reflected as a much larger one
+                    let finish = lock(&self.finish)
+                        .as_ref()
+                        .filter(|(client, _)| client[..] == *packet)
+                        .map(|(_, server)| server.clone());
+                    if let Some(finish) = finish {
+                        self.socket.send(&finish).await?;
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let Some((nonce, len)) = open(&self.noise, packet, &mut payload) else {
+                continue;
+            };
+            if !lock(&self.replay).accept(nonce) {
+                continue;
+            }
+
+            // the client only sends data once it has the server finish
+            lock(&self.finish).take();
+
+            let len = len.min(buf.len());
+            buf[..len].copy_from_slice(&payload[..len]);
+
+            return Ok(len);
+        }
+    }
+}
+
+// sends the message until the remote answers with a datagram of the expected type
+async fn exchange(
+    socket: &UdpSocket,
+    message: &[u8],
+    expected: u8,
+    buf: &mut [u8],
+    mut retransmit: Duration,
+    deadline: Instant,
+) -> Result<usize, ScallopError> {
+    loop {
+        socket.send(message).await?;
+
+        let wait = (Instant::now() + retransmit).min(deadline);
+        loop {
+            match timeout_at(wait, socket.recv(buf)).await {
+                Ok(Ok(len)) if len > 0 && buf[0] == expected => return Ok(len),
+                Ok(Ok(_)) => continue,
+                // the server is not listening yet
+                Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                    sleep_until(wait).await;
+                    break;
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(_) => break,
+            }
+        }
+
+        if Instant::now() >= deadline {
+            return Err(ScallopError::HandshakeTimeout);
+        }
+        retransmit *= 2;
+    }
+}
+
+/// Client handshake over a UDP socket connected to the server
+pub async fn new_client_datagram(
+    socket: UdpSocket,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    mut auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+    protocol: ScallopProtocol,
+    config: DatagramConfig,
+) -> Result<ScallopDatagram, ScallopError> {
+    let deadline = Instant::now() + config.timeout;
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    let mut payload = vec![0u8; MAX_DATAGRAM];
+
+    let mut noise = protocol
+        .builder(secret, &prologue(protocol))?
+        .build_initiator()
+        .map_err(ScallopError::InitFailed)?;
+
+    //---- -> e, s start ----//
+
+    let mut hello = vec![0u8; 1024];
+    hello[0] = CLIENT_HELLO;
+    hello[1] = protocol.id();
+    let len = noise.write_message(&[], &mut hello[2..])?;
+    hello.truncate(2 + len);
+
+    //---- -> e, s end ----//
+
+    //---- <- e, ee, se, s, es start ----//
+
+    let len = exchange(
+        &socket,
+        &hello,
+        SERVER_HELLO,
+        &mut buf,
+        config.retransmit,
+        deadline,
+    )
+    .await?;
+    let len = noise.read_message(&buf[1..len], &mut payload)?;
+
+    // handshake payload should be the auth request
+    if len != 1 || payload[0] > 1 {
+        return Err(ScallopError::InvalidPayload);
+    }
+    let should_send_auth = payload[0] == 1;
+
+    //---- <- e, ee, se, s, es end ----//
+
+    if should_send_auth && auther.is_none() {
+        return Err(ScallopError::NoAuther);
+    }
+
+    // safe to unwrap since IX should have key by now
+    let remote_static: [u8; 32] = noise.get_remote_static().unwrap().try_into().unwrap();
+    let should_ask_auth = auth_store
+        .as_ref()
+        .is_some_and(|store| !store.contains(&remote_static));
+
+    let exporter = Exporter::new(&mut noise, protocol);
+    let noise = noise.into_stateless_transport_mode()?;
+
+    //---- -> CLIENTFIN start ----//
+
+    let mut fin = vec![should_ask_auth as u8];
+    if should_send_auth {
+        // safe to unwrap since it has been checked above
+        let auth = auther.unwrap().new_auth().await;
+        if auth.len() > MAX_ATTESTATION {
+            return Err(ScallopError::AttestationTooLarge {
+                len: auth.len(),
+                max: MAX_ATTESTATION,
+            });
+        }
+        fin.extend_from_slice(&auth);
+    }
+    let fin = seal(&noise, CLIENT_FINISH, 0, &fin)?;
+
+    //---- -> CLIENTFIN end ----//
+
+    //---- <- SERVERFIN start ----//
+
+    let len = exchange(
+        &socket,
+        &fin,
+        SERVER_FINISH,
+        &mut buf,
+        config.retransmit,
+        deadline,
+    )
+    .await?;
+    let Some((0, len)) = open(&noise, &buf[..len], &mut payload) else {
+        return Err(ScallopError::InvalidPayload);
+    };
+
+    if should_ask_auth {
+        let Some(pcrs) = auth_store
+            .as_mut()
+            .unwrap()
+            .verify(&payload[..len], &remote_static)
+        else {
+            return Err(ScallopError::InvalidAttestation);
+        };
+
+        auth_store.unwrap().set(remote_static, pcrs);
+    } else if len != 0 {
+        return Err(ScallopError::UnexpectedAttestation);
+    }
+
+    //---- <- SERVERFIN end ----//
+
+    Ok(ScallopDatagram::new(
+        socket,
+        noise,
+        protocol,
+        remote_static,
+        exporter,
+        None,
+    ))
+}
+
+/// Server handshake over an unconnected UDP socket, which is then connected to the
+/// first client sending a valid hello
+pub async fn new_server_datagram(
+    socket: UdpSocket,
+    secret: &[u8; 32],
+    // will not auth remote if None
+    mut auth_store: Option<impl ScallopAuthStore>,
+    // will not respond to auth requests if None
+    auther: Option<impl ScallopAuther>,
+    protocols: &[ScallopProtocol],
+    config: DatagramConfig,
+) -> Result<ScallopDatagram, ScallopError> {
+    let mut buf = vec![0u8; MAX_DATAGRAM];
+    let mut payload = vec![0u8; MAX_DATAGRAM];
+
+    //---- -> e, s start ----//
+
+    // anything but a valid hello is ignored until a client shows up
+    let (hello, protocol, mut noise) = loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        if len < 2 || buf[0] != CLIENT_HELLO {
+            continue;
+        }
+        let Some(protocol) = ScallopProtocol::from_id(buf[1]).filter(|x| protocols.contains(x))
+        else {
+            continue;
+        };
+
+        let mut noise = protocol
+            .builder(secret, &prologue(protocol))?
+            .build_responder()
+            .map_err(ScallopError::InitFailed)?;
+        // client hello has no payload
+        if noise.read_message(&buf[2..len], &mut payload) != Ok(0) {
+            continue;
+        }
+
+        socket.connect(peer).await?;
+        break (buf[..len].to_vec(), protocol, noise);
+    };
+    let deadline = Instant::now() + config.timeout;
+
+    //---- -> e, s end ----//
+
+    // safe to unwrap since IX should have key by now
+    let remote_static: [u8; 32] = noise.get_remote_static().unwrap().try_into().unwrap();
+    let should_ask_auth = auth_store
+        .as_ref()
+        .is_some_and(|store| !store.contains(&remote_static));
+
+    //---- <- e, ee, se, s, es start ----//
+
+    let mut server_hello = vec![0u8; 1024];
+    server_hello[0] = SERVER_HELLO;
+    let len = noise.write_message(&[should_ask_auth as u8], &mut server_hello[1..])?;
+    server_hello.truncate(1 + len);
+
+    let exporter = Exporter::new(&mut noise, protocol);
+    let noise = noise.into_stateless_transport_mode()?;
+
+    //---- <- e, ee, se, s, es end ----//
+
+    //---- -> CLIENTFIN start ----//
+
+    socket.send(&server_hello).await?;
+    let (client_fin, len) = loop {
+        let len = timeout_at(deadline, socket.recv(&mut buf))
+            .await
+            .map_err(|_| ScallopError::HandshakeTimeout)??;
+
+        match buf[..len].first() {
+            // the server hello was lost
+            Some(&CLIENT_HELLO) if buf[..len] == hello[..] => {
+                socket.send(&server_hello).await?;
+            }
+            Some(&CLIENT_FINISH) => {
+                if let Some((0, payload_len)) = open(&noise, &buf[..len], &mut payload) {
+                    break (buf[..len].to_vec(), payload_len);
+                }
+            }
+            _ => {}
+        }
+    };
+
+    // payload should have the auth request followed by the attestation if asked for
+    if len == 0 || payload[0] > 1 {
+        return Err(ScallopError::InvalidPayload);
+    }
+    let should_send_auth = payload[0] == 1;
+
+    if should_ask_auth {
+        let Some(pcrs) = auth_store
+            .as_mut()
+            .unwrap()
+            .verify(&payload[1..len], &remote_static)
+        else {
+            return Err(ScallopError::InvalidAttestation);
+        };
+
+        auth_store.unwrap().set(remote_static, pcrs);
+    } else if len != 1 {
+        return Err(ScallopError::UnexpectedAttestation);
+    }
+
+    //---- -> CLIENTFIN end ----//
+
+    //---- <- SERVERFIN start ----//
+
+    let auth = if should_send_auth {
+        let Some(mut auther) = auther else {
+            return Err(ScallopError::NoAuther);
+        };
+        let auth = auther.new_auth().await;
+        if auth.len() > MAX_ATTESTATION {
+            return Err(ScallopError::AttestationTooLarge {
+                len: auth.len(),
+                max: MAX_ATTESTATION,
+            });
+        }
+        auth
+    } else {
+        Box::new([])
+    };
+
+    let fin = seal(&noise, SERVER_FINISH, 0, &auth)?;
+    socket.send(&fin).await?;
+
+    //---- <- SERVERFIN end ----//
+
+    Ok(ScallopDatagram::new(
+        socket,
+        noise,
+        protocol,
+        remote_static,
+        exporter,
+        Some((client_fin, fin)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use snow::Builder;
+
+    use super::*;
+    use crate::attestation::builder::mock_attestation;
+    use crate::attestation::{AttestationPolicy, TrustAnchors};
+    use crate::scallop::{AttestationAuther, PolicyAuthStore};
+
+    struct MockAuther(Vec<u8>);
+
+    impl ScallopAuther for MockAuther {
+        async fn new_auth(&mut self) -> Box<[u8]> {
+            self.0.as_slice().into()
+        }
+    }
+
+    fn keypair() -> ([u8; 32], [u8; 32]) {
+        let keys = Builder::new("Noise_IX_25519_ChaChaPoly_BLAKE2b".parse().unwrap())
+            .generate_keypair()
+            .unwrap();
+        (
+            keys.private.try_into().unwrap(),
+            keys.public.try_into().unwrap(),
+        )
+    }
+
+    fn store() -> PolicyAuthStore {
+        PolicyAuthStore::new(
+            AttestationPolicy::new()
+                .pcr(0, [0u8; 48])
+                .trust_anchors(TrustAnchors::oyster_mock()),
+        )
+    }
+
+    async fn socket() -> UdpSocket {
+        UdpSocket::bind("127.0.0.1:0").await.unwrap()
+    }
+
+    // relays datagrams between a client and the server, dropping the datagrams whose
+    // index in each direction is listed and sending every data datagram twice
+    async fn lossy_relay(
+        server: SocketAddr,
+        drop_up: &'static [usize],
+        drop_down: &'static [usize],
+    ) -> SocketAddr {
+        let relay = socket().await;
+        let addr = relay.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut up, mut down) = (0, 0);
+            let mut client = None;
+            let mut buf = vec![0u8; MAX_DATAGRAM];
+            loop {
+                let (len, from) = relay.recv_from(&mut buf).await.unwrap();
+                let (to, count, drops) = if from == server {
+                    (client.unwrap(), &mut down, drop_down)
+                } else {
+                    client = Some(from);
+                    (server, &mut up, drop_up)
+                };
+
+                *count += 1;
+                if drops.contains(&(*count - 1)) {
+                    continue;
+                }
+                relay.send_to(&buf[..len], to).await.unwrap();
+                if buf[0] == DATA {
+                    relay.send_to(&buf[..len], to).await.unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut window = ReplayWindow::new();
+
+        assert!(window.accept(5));
+        assert!(!window.accept(5));
+        // reordered
+        assert!(window.accept(3));
+        assert!(!window.accept(3));
+        assert!(window.accept(4));
+
+        // far ahead, anything behind the window is dropped
+        assert!(window.accept(5 + REPLAY_WINDOW));
+        assert!(!window.accept(5));
+        assert!(window.accept(6));
+        assert!(!window.accept(6));
+        assert!(window.accept(5 + REPLAY_WINDOW - 1));
+
+        // jumps past the whole window
+        assert!(window.accept(10 * REPLAY_WINDOW));
+        assert!(!window.accept(9 * REPLAY_WINDOW));
+        assert!(window.accept(9 * REPLAY_WINDOW + 1));
+    }
+
+    #[tokio::test]
+    async fn test_datagram() {
+        let (client_secret, client_public) = keypair();
+        let (server_secret, server_public) = keypair();
+        let (mut client_store, mut server_store) = (store(), store());
+
+        let server = socket().await;
+        let client = socket().await;
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+
+        let server = tokio::spawn(async move {
+            let server = new_server_datagram(
+                server,
+                &server_secret,
+                Some(&mut server_store),
+                Some(MockAuther(mock_attestation(
+                    Some(&server_public),
+                    None,
+                    None,
+                ))),
+                &ScallopProtocol::ALL,
+                DatagramConfig::new(),
+            )
+            .await
+            .unwrap();
+            assert!(server_store.contains(&client_public));
+
+            // echo
+            let mut buf = [0u8; 1024];
+            for _ in 0..3 {
+                let len = server.recv(&mut buf).await.unwrap();
+                server.send(&buf[..len]).await.unwrap();
+            }
+
+            (
+                server.get_remote_static().unwrap(),
+                server.channel_binding().to_vec(),
+            )
+        });
+
+        let client = new_client_datagram(
+            client,
+            &client_secret,
+            Some(&mut client_store),
+            Some(MockAuther(mock_attestation(
+                Some(&client_public),
+                None,
+                None,
+            ))),
+            ScallopProtocol::Noise_IX_25519_AESGCM_SHA256,
+            DatagramConfig::new(),
+        )
+        .await
+        .unwrap();
+        assert!(client_store.contains(&server_public));
+        assert_eq!(client.get_remote_static().unwrap(), server_public);
+
+        let mut buf = [0u8; 1024];
+        for message in [b"one".as_slice(), b"two", b"three"] {
+            client.send(message).await.unwrap();
+            let len = client.recv(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], message);
+        }
+
+        assert_eq!(
+            server.await.unwrap(),
+            (client_public, client.channel_binding().to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_datagram_loss() {
+        let server = socket().await;
+        // first client hello, first server hello and usually the server finish are lost
+        let relay = lossy_relay(server.local_addr().unwrap(), &[0], &[0, 2]).await;
+        let client = socket().await;
+        client.connect(relay).await.unwrap();
+        let config = DatagramConfig::new().retransmit(Duration::from_millis(20));
+
+        let server = tokio::spawn(async move {
+            let server = new_server_datagram(
+                server,
+                &keypair().0,
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                config,
+            )
+            .await
+            .unwrap();
+
+            let mut buf = [0u8; 1024];
+            let mut received = vec![];
+            while received.len() < 3 {
+                let len = server.recv(&mut buf).await.unwrap();
+                received.push(buf[..len].to_vec());
+            }
+            received
+        });
+
+        let client = new_client_datagram(
+            client,
+            &keypair().0,
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b,
+            config,
+        )
+        .await
+        .unwrap();
+
+        // every datagram is relayed twice, duplicates are dropped
+        for message in [b"one".as_slice(), b"two", b"three"] {
+            client.send(message).await.unwrap();
+        }
+        assert_eq!(
+            server.await.unwrap(),
+            [b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_datagram_spoofed_finish() {
+        let server = socket().await;
+        let client = socket().await;
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+
+        let server = tokio::spawn(async move {
+            let server = new_server_datagram(
+                server,
+                &keypair().0,
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                DatagramConfig::new(),
+            )
+            .await
+            .unwrap();
+
+            let mut buf = [0u8; 1024];
+            let len = server.recv(&mut buf).await.unwrap();
+            buf[..len].to_vec()
+        });
+
+        let client = new_client_datagram(
+            client,
+            &keypair().0,
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b,
+            DatagramConfig::new(),
+        )
+        .await
+        .unwrap();
+
+        // only an exact retransmission of the client finish gets the server finish again
+        let mut spoofed = vec![CLIENT_FINISH; 64];
+        spoofed[1..HEADER_LEN].copy_from_slice(&0u64.to_be_bytes());
+        for packet in [vec![CLIENT_FINISH], spoofed] {
+            client.socket().send(&packet).await.unwrap();
+        }
+        let mut buf = [0u8; MAX_DATAGRAM];
+        let answer =
+            tokio::time::timeout(Duration::from_millis(100), client.socket().recv(&mut buf)).await;
+        assert!(answer.is_err());
+
+        client.send(b"data").await.unwrap();
+        assert_eq!(server.await.unwrap(), b"data");
+    }
+
+    #[tokio::test]
+    async fn test_datagram_timeout() {
+        // nobody answers
+        let silent = socket().await;
+        let client = socket().await;
+        client.connect(silent.local_addr().unwrap()).await.unwrap();
+
+        let err = new_client_datagram(
+            client,
+            &keypair().0,
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b,
+            DatagramConfig::new()
+                .retransmit(Duration::from_millis(10))
+                .timeout(Duration::from_millis(100)),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ScallopError::HandshakeTimeout));
+    }
+
+    #[tokio::test]
+    async fn test_datagram_protocols() {
+        let server = socket().await;
+        let client = socket().await;
+        client.connect(server.local_addr().unwrap()).await.unwrap();
+
+        // hellos with protocols the server does not support are ignored
+        tokio::spawn(async move {
+            let _ = new_server_datagram(
+                server,
+                &keypair().0,
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &[ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b],
+                DatagramConfig::new(),
+            )
+            .await;
+        });
+
+        let err = new_client_datagram(
+            client,
+            &keypair().0,
+            None::<PolicyAuthStore>,
+            None::<AttestationAuther>,
+            ScallopProtocol::Noise_IX_25519_AESGCM_BLAKE2b,
+            DatagramConfig::new()
+                .retransmit(Duration::from_millis(10))
+                .timeout(Duration::from_millis(100)),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ScallopError::HandshakeTimeout));
+    }
+}
@@ -0,0 +1,99 @@
+// Channel binding and keying material exporter
+//
+// The channel binding is the Noise handshake hash, unique to the session but computable
+// by anyone who saw the handshake. Secrets are exported from
+// exporter secret = HMAC(k1 || k2, "scallop exporter" | handshake hash)
+// where k1 and k2 are the keys the handshake splits into for transport, which only the
+// two ends of the session know. Outputs are expanded like HKDF with
+// u16 label length | label | u16 context length | context | u16 output length
+// as the info, so different labels, contexts and lengths give unrelated outputs.
+// Rekeying does not change either value.
+
+use snow::params::HashChoice;
+use snow::resolvers::{CryptoResolver, DefaultResolver};
+use snow::types::Hash;
+use snow::HandshakeState;
+
+use super::{ScallopError, ScallopProtocol};
+
+const EXPORTER_LABEL: &[u8] = b"scallop exporter";
+
+fn hash(protocol: ScallopProtocol) -> Box<dyn Hash> {
+    let choice = match protocol {
+        ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b
+        | ScallopProtocol::Noise_IX_25519_AESGCM_BLAKE2b => HashChoice::Blake2b,
+        ScallopProtocol::Noise_IX_25519_ChaChaPoly_SHA256
+        | ScallopProtocol::Noise_IX_25519_AESGCM_SHA256 => HashChoice::SHA256,
+    };
+
+    DefaultResolver
+        .resolve_hash(&choice)
+        .expect("default resolver supports BLAKE2b and SHA256")
+}
+
+pub(super) struct Exporter {
+    protocol: ScallopProtocol,
+    handshake_hash: Box<[u8]>,
+    secret: Box<[u8]>,
+}
+
+impl Exporter {
+    // must be called once the handshake is finished, before switching to transport mode
+    pub(super) fn new(noise: &mut HandshakeState, protocol: ScallopProtocol) -> Self {
+        let handshake_hash: Box<[u8]> = noise.get_handshake_hash().into();
+        let (k1, k2) = noise.dangerously_get_raw_split();
+
+        let mut hash = hash(protocol);
+        let mut secret = vec![0u8; hash.hash_len()].into_boxed_slice();
+        hash.hmac(
+            &[k1, k2].concat(),
+            &[EXPORTER_LABEL, &handshake_hash].concat(),
+            &mut secret,
+        );
+
+        Self {
+            protocol,
+            handshake_hash,
+            secret,
+        }
+    }
+
+    pub(super) fn channel_binding(&self) -> &[u8] {
+        &self.handshake_hash
+    }
+
+    pub(super) fn export(
+        &self,
+        label: &[u8],
+        context: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), ScallopError> {
+        let mut hash = hash(self.protocol);
+        let hash_len = hash.hash_len();
+
+        if label.len() > u16::MAX as usize
+            || context.len() > u16::MAX as usize
+            || out.len() > (255 * hash_len).min(u16::MAX as usize)
+        {
+            return Err(ScallopError::InvalidExporterParameters);
+        }
+
+        let mut info = Vec::with_capacity(6 + label.len() + context.len());
+        info.extend_from_slice(&(label.len() as u16).to_be_bytes());
+        info.extend_from_slice(label);
+        info.extend_from_slice(&(context.len() as u16).to_be_bytes());
+        info.extend_from_slice(context);
+        info.extend_from_slice(&(out.len() as u16).to_be_bytes());
+
+        // T(i) = HMAC(secret, T(i - 1) | info | i)
+        let mut block = Vec::with_capacity(hash_len);
+        for (i, chunk) in out.chunks_mut(hash_len).enumerate() {
+            let input = [&block, &info, &[i as u8 + 1][..]].concat();
+            block.resize(hash_len, 0);
+            hash.hmac(&self.secret, &input, &mut block);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,592 @@
+// HTTP over Scallop
+//
+// Client side, ScallopConnector plugs into hyper_util clients and performs a handshake
+// on top of every connection the inner connector makes.
+//
+// Server side, serve accepts connections, runs the handshakes concurrently within the
+// configured limits and hands the resulting streams to an axum router.
+//
+// Both sides surface the identity of the remote as a ScallopPeer, in request extensions
+// on the server and in response extensions on the client.
+
+use std::cell::OnceCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::{Request, Uri};
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use tower_service::Service;
+
+use super::{
+    new_client_async, new_server_async, HandshakeLimits, ScallopAuthStore, ScallopAuther,
+    ScallopError, ScallopProtocol, ScallopStream, TransportLimits,
+};
+
+type Pcrs = ([u8; 48], [u8; 48], [u8; 48]);
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type AuthFuture = Pin<Box<dyn Future<Output = Box<[u8]>> + Send>>;
+
+/// Identity of the remote end of a Scallop connection
+///
+/// Inserted into the extensions of every request accepted by [`serve`] and of every
+/// response received over a [`ScallopConnector`], e.g. `Extension(peer): Extension<ScallopPeer>`
+/// in axum handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScallopPeer {
+    /// Noise static key of the remote
+    pub static_key: [u8; 32],
+    /// pcrs of the remote as verified by the auth store, `None` without an auth store
+    pub pcrs: Option<Pcrs>,
+}
+
+// ScallopAuther is not object safe, erase it behind a closure instead
+#[derive(Clone)]
+struct SharedAuther(Arc<dyn Fn() -> AuthFuture + Send + Sync>);
+
+impl ScallopAuther for SharedAuther {
+    // handshakes of connections have to be Send
+    #[allow(refining_impl_trait)]
+    fn new_auth(&mut self) -> impl Future<Output = Box<[u8]>> + Send {
+        (self.0)()
+    }
+}
+
+// per handshake view of the shared auth store, remembers the pcrs of the remote
+struct Handshake<'a> {
+    store: &'a Mutex<dyn ScallopAuthStore + Send>,
+    pcrs: OnceCell<Pcrs>,
+}
+
+impl ScallopAuthStore for Handshake<'_> {
+    fn contains(&self, key: &[u8; 32]) -> bool {
+        let store = self.store.lock().unwrap_or_else(PoisonError::into_inner);
+        if !store.contains(key) {
+            return false;
+        }
+        if let Some(pcrs) = store.get(key) {
+            let _ = self.pcrs.set(*pcrs);
+        }
+        true
+    }
+
+    fn get(&self, _key: &[u8; 32]) -> Option<&Pcrs> {
+        self.pcrs.get()
+    }
+
+    fn set(&mut self, key: [u8; 32], pcrs: Pcrs) {
+        self.pcrs = OnceCell::from(pcrs);
+        self.store
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .set(key, pcrs);
+    }
+
+    fn verify(&mut self, attestation: &[u8], key: &[u8; 32]) -> Option<Pcrs> {
+        self.store
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .verify(attestation, key)
+    }
+}
+
+/// Keys and authentication used for every connection made by a [`ScallopConnector`]
+/// or accepted by [`serve`]
+///
+/// ```ignore
+/// let config = ScallopConfig::new(secret)
+///     .auth_store(Arc::new(Mutex::new(PolicyAuthStore::new(policy))))
+///     .auther(move || fetch_attestation(public));
+/// ```
+#[derive(Clone)]
+pub struct ScallopConfig {
+    secret: [u8; 32],
+    auth_store: Option<Arc<Mutex<dyn ScallopAuthStore + Send>>>,
+    auther: Option<SharedAuther>,
+    protocols: Vec<ScallopProtocol>,
+    limits: TransportLimits,
+    handshake_limits: HandshakeLimits,
+}
+
+impl std::fmt::Debug for ScallopConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the secret
+        f.debug_struct("ScallopConfig")
+            .field("auth_store", &self.auth_store.is_some())
+            .field("auther", &self.auther.is_some())
+            .field("protocols", &self.protocols)
+            .field("limits", &self.limits)
+            .field("handshake_limits", &self.handshake_limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScallopConfig {
+    /// Config with the given Noise static secret, neither authenticating remotes
+    /// nor responding to auth requests
+    pub fn new(secret: [u8; 32]) -> Self {
+        Self {
+            secret,
+            auth_store: None,
+            auther: None,
+            protocols: vec![ScallopProtocol::Noise_IX_25519_ChaChaPoly_BLAKE2b],
+            limits: TransportLimits::new(),
+            handshake_limits: HandshakeLimits::new(),
+        }
+    }
+
+    /// Protocols offered by clients in order of preference, or accepted by servers,
+    /// defaults to only `Noise_IX_25519_ChaChaPoly_BLAKE2b` which every version supports
+    pub fn protocols(mut self, protocols: impl Into<Vec<ScallopProtocol>>) -> Self {
+        self.protocols = protocols.into();
+        self
+    }
+
+    /// Rekeying and nonce limits of every connection, defaults to never rekeying
+    pub fn limits(mut self, limits: TransportLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Timeout and message size limits of every handshake, see [`HandshakeLimits`]
+    pub fn handshake_limits(mut self, limits: HandshakeLimits) -> Self {
+        self.handshake_limits = limits;
+        self
+    }
+
+    /// Authenticate remotes using the store, e.g. a [`super::PolicyAuthStore`]
+    ///
+    /// The store is shared by all connections and locked only for individual lookups,
+    /// keep a clone of the handle to inspect it.
+    pub fn auth_store<S: ScallopAuthStore + Send + 'static>(
+        mut self,
+        auth_store: Arc<Mutex<S>>,
+    ) -> Self {
+        self.auth_store = Some(auth_store);
+        self
+    }
+
+    /// Respond to auth requests with the attestations produced by `new_auth`, called for
+    /// every handshake
+    ///
+    /// Connections are driven from any thread, so unlike [`ScallopAuther`] attestations
+    /// have to be produced by `Send` futures.
+    ///
+    /// ```ignore
+    /// let auther = AttestationAuther::new(endpoint, public).await?;
+    /// let config = ScallopConfig::new(secret).auther(move || {
+    ///     let mut auther = auther.clone();
+    ///     async move { auther.new_auth().await }
+    /// });
+    /// ```
+    pub fn auther<F, Fut>(mut self, new_auth: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Box<[u8]>> + Send + 'static,
+    {
+        self.auther = Some(SharedAuther(Arc::new(move || Box::pin(new_auth()))));
+        self
+    }
+
+    fn handshake(&self) -> Option<Handshake<'_>> {
+        self.auth_store.as_deref().map(|store| Handshake {
+            store,
+            pcrs: OnceCell::new(),
+        })
+    }
+
+    fn peer<B: AsyncRead + AsyncWrite + Unpin>(
+        stream: &ScallopStream<B>,
+        handshake: Option<Handshake>,
+    ) -> ScallopPeer {
+        ScallopPeer {
+            // safe to unwrap since IX always transmits the static key
+            static_key: stream.get_remote_static().unwrap(),
+            pcrs: handshake.and_then(|x| x.pcrs.get().copied()),
+        }
+    }
+
+    async fn client<B: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: B,
+    ) -> Result<(ScallopStream<B>, ScallopPeer), ScallopError> {
+        let mut handshake = self.handshake();
+        let mut stream = new_client_async(
+            stream,
+            &self.secret,
+            handshake.as_mut(),
+            self.auther.clone(),
+            &self.protocols,
+            self.handshake_limits,
+        )
+        .await?;
+        stream.set_limits(self.limits);
+        let peer = Self::peer(&stream, handshake);
+
+        Ok((stream, peer))
+    }
+
+    async fn server<B: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: B,
+    ) -> Result<(ScallopStream<B>, ScallopPeer), ScallopError> {
+        let mut handshake = self.handshake();
+        let mut stream = new_server_async(
+            stream,
+            &self.secret,
+            handshake.as_mut(),
+            self.auther.clone(),
+            &self.protocols,
+            self.handshake_limits,
+        )
+        .await?;
+        stream.set_limits(self.limits);
+        let peer = Self::peer(&stream, handshake);
+
+        Ok((stream, peer))
+    }
+}
+
+/// Connector for `hyper_util` clients which runs a Scallop handshake over every
+/// connection made by the inner connector
+///
+/// ```ignore
+/// let client = Client::builder(TokioExecutor::new())
+///     .build::<_, Empty<Bytes>>(ScallopConnector::new(config));
+/// let response = client.get("http://127.0.0.1:21000/hello".parse()?).await?;
+/// let peer = response.extensions().get::<ScallopPeer>();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ScallopConnector<C = HttpConnector> {
+    inner: C,
+    config: ScallopConfig,
+}
+
+impl ScallopConnector<HttpConnector> {
+    /// Connector over plain tcp
+    pub fn new(config: ScallopConfig) -> Self {
+        Self::with_connector(config, HttpConnector::new())
+    }
+}
+
+impl<C> ScallopConnector<C> {
+    /// Connector over a custom inner connector, e.g. to reach the server over vsock
+    pub fn with_connector(config: ScallopConfig, inner: C) -> Self {
+        Self { inner, config }
+    }
+}
+
+impl<C, B> Service<Uri> for ScallopConnector<C>
+where
+    C: Service<Uri, Response = TokioIo<B>>,
+    C::Error: Into<BoxError>,
+    C::Future: Send + 'static,
+    B: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Response = ScallopConnection<B>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.inner.call(uri);
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let stream = connecting.await.map_err(Into::into)?.into_inner();
+            let (stream, peer) = config.client(stream).await?;
+
+            Ok(ScallopConnection {
+                io: TokioIo::new(stream),
+                peer,
+            })
+        })
+    }
+}
+
+/// Connection made by a [`ScallopConnector`]
+#[derive(Debug)]
+pub struct ScallopConnection<B: AsyncRead + AsyncWrite + Unpin> {
+    io: TokioIo<ScallopStream<B>>,
+    peer: ScallopPeer,
+}
+
+impl<B: AsyncRead + AsyncWrite + Unpin> ScallopConnection<B> {
+    /// Identity of the server
+    pub fn peer(&self) -> &ScallopPeer {
+        &self.peer
+    }
+}
+
+impl<B: AsyncRead + AsyncWrite + Unpin> Connection for ScallopConnection<B> {
+    fn connected(&self) -> Connected {
+        Connected::new().extra(self.peer)
+    }
+}
+
+impl<B: AsyncRead + AsyncWrite + Unpin> hyper::rt::Read for ScallopConnection<B> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: hyper::rt::ReadBufCursor<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl<B: AsyncRead + AsyncWrite + Unpin> hyper::rt::Write for ScallopConnection<B> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
+
+/// Limits enforced by [`serve`]
+#[derive(Debug, Clone, Copy)]
+pub struct ServeLimits {
+    /// Maximum number of open connections, including those still in the handshake,
+    /// further connections wait in the listen backlog, defaults to 1024
+    pub max_connections: usize,
+    /// Maximum number of concurrent handshakes, defaults to 64
+    pub max_handshakes: usize,
+    /// Time allowed for a handshake once it starts, including fetching and verifying
+    /// attestations, defaults to 10s
+    pub handshake_timeout: Duration,
+}
+
+impl Default for ServeLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            max_handshakes: 64,
+            handshake_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Serve an axum router over Scallop
+///
+/// Every accepted connection is handled in its own task, so slow handshakes do not hold
+/// up others. Connections failing the handshake are dropped. Each request carries the
+/// [`ScallopPeer`] of its connection in its extensions.
+///
+/// Only returns if the listener fails.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: ScallopConfig,
+    limits: ServeLimits,
+) -> std::io::Result<()> {
+    let connections = Arc::new(Semaphore::new(limits.max_connections));
+    let handshakes = Arc::new(Semaphore::new(limits.max_handshakes));
+
+    loop {
+        // safe to unwrap since the semaphore is never closed
+        let connection = connections.clone().acquire_owned().await.unwrap();
+
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            // the remote went away before the accept, nothing to do
+            Err(e) if is_connection_error(&e) => continue,
+            Err(e) => return Err(e),
+        };
+
+        let app = app.clone();
+        let config = config.clone();
+        let handshakes = handshakes.clone();
+
+        tokio::spawn(async move {
+            let _connection = connection;
+
+            let handshake = async {
+                // safe to unwrap since the semaphore is never closed
+                let _handshake = handshakes.acquire().await.unwrap();
+                tokio::time::timeout(limits.handshake_timeout, config.server(stream)).await
+            };
+            let Ok(Ok((stream, peer))) = handshake.await else {
+                return;
+            };
+
+            let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(peer);
+                app.clone().call(request)
+            });
+
+            // errors are per connection and have nowhere to go
+            let _ = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+fn is_connection_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::ConnectionReset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+    use axum::Extension;
+    use http_body_util::{BodyExt, Empty};
+    use hyper::body::Bytes;
+    use hyper_util::client::legacy::Client;
+    use tokio::net::TcpStream;
+
+    use super::*;
+    use crate::attestation::builder::mock_attestation;
+    use crate::attestation::{AttestationPolicy, TrustAnchors};
+    use crate::scallop::PolicyAuthStore;
+
+    #[derive(Clone)]
+    struct MockAuther(Arc<Vec<u8>>);
+
+    impl MockAuther {
+        fn new(public_key: &[u8]) -> Self {
+            Self(Arc::new(mock_attestation(Some(public_key), None, None)))
+        }
+
+        fn shared(self) -> impl Fn() -> std::future::Ready<Box<[u8]>> + Send + Sync {
+            move || std::future::ready(self.0.as_slice().into())
+        }
+    }
+
+    impl ScallopAuther for MockAuther {
+        async fn new_auth(&mut self) -> Box<[u8]> {
+            self.0.as_slice().into()
+        }
+    }
+
+    fn keypair() -> ([u8; 32], [u8; 32]) {
+        let keys = snow::Builder::new("Noise_IX_25519_ChaChaPoly_BLAKE2b".parse().unwrap())
+            .generate_keypair()
+            .unwrap();
+        (
+            keys.private.try_into().unwrap(),
+            keys.public.try_into().unwrap(),
+        )
+    }
+
+    fn store() -> Arc<Mutex<PolicyAuthStore>> {
+        Arc::new(Mutex::new(PolicyAuthStore::new(
+            AttestationPolicy::new()
+                .pcr(0, [0u8; 48])
+                .trust_anchors(TrustAnchors::oyster_mock()),
+        )))
+    }
+
+    async fn whoami(Extension(peer): Extension<ScallopPeer>) -> String {
+        format!(
+            "{} {}",
+            hex::encode(peer.static_key),
+            hex::encode(peer.pcrs.unwrap().1)
+        )
+    }
+
+    async fn server(limits: ServeLimits) -> ([u8; 32], String) {
+        let (secret, public) = keypair();
+        let config = ScallopConfig::new(secret)
+            .auth_store(store())
+            .auther(MockAuther::new(&public).shared())
+            .protocols(ScallopProtocol::ALL);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/whoami", get(whoami));
+        tokio::spawn(serve(listener, app, config, limits));
+
+        (public, format!("http://{addr}/whoami"))
+    }
+
+    fn http_client(
+        auther: MockAuther,
+        secret: [u8; 32],
+        store: Arc<Mutex<PolicyAuthStore>>,
+    ) -> Client<ScallopConnector, Empty<Bytes>> {
+        let config = ScallopConfig::new(secret)
+            .auth_store(store)
+            .auther(auther.shared());
+        Client::builder(TokioExecutor::new()).build(ScallopConnector::new(config))
+    }
+
+    #[tokio::test]
+    async fn test_http() {
+        let (server_public, uri) = server(ServeLimits::default()).await;
+        let (secret, public) = keypair();
+        let store = store();
+        let client = http_client(MockAuther::new(&public), secret, store.clone());
+
+        for _ in 0..2 {
+            let response = client.get(uri.parse().unwrap()).await.unwrap();
+
+            // server identity in the response
+            let peer = *response.extensions().get::<ScallopPeer>().unwrap();
+            assert_eq!(peer.static_key, server_public);
+            assert_eq!(peer.pcrs, Some(([0u8; 48], [1u8; 48], [2u8; 48])));
+
+            // client identity in the request
+            let body = response.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(
+                body,
+                format!("{} {}", hex::encode(public), hex::encode([1u8; 48]))
+            );
+        }
+        assert!(store.lock().unwrap().contains(&server_public));
+
+        // client attesting to some other key
+        let (secret, _) = keypair();
+        let client = http_client(MockAuther::new(&public), secret, store.clone());
+        assert!(client.get(uri.parse().unwrap()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handshake_limits() {
+        let (_, uri) = server(ServeLimits {
+            max_handshakes: 1,
+            handshake_timeout: Duration::from_millis(200),
+            ..Default::default()
+        })
+        .await;
+
+        // stalled handshake holding the only slot until it times out
+        let addr = uri
+            .trim_start_matches("http://")
+            .trim_end_matches("/whoami");
+        let _stalled = TcpStream::connect(addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (secret, public) = keypair();
+        let client = http_client(MockAuther::new(&public), secret, store());
+        let start = std::time::Instant::now();
+        let response = client.get(uri.parse().unwrap()).await.unwrap();
+        assert!(response.status().is_success());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
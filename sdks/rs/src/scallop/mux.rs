@@ -0,0 +1,913 @@
+// Stream multiplexing over a single Scallop session
+//
+// Modelled on yamux, every frame starts with a 12 byte header:
+// version (u8) | type (u8) | flags (u16) | stream id (u32) | length (u32)
+//
+// Types:
+// - data, followed by length bytes of the stream
+// - window update, length is the number of additional bytes the sender can receive
+// - ping, length is an opaque value echoed back with the ACK flag
+// - go away, length is the reason, the sender does not take new streams after it
+//
+// Flags:
+// - SYN opens a stream, ACK acknowledges it
+// - FIN half closes a stream in the direction it is sent in
+// - RST resets a stream in both directions
+//
+// Streams opened by the initiator of the session have odd ids and streams opened by the
+// responder have even ids, so both sides can open streams without coordinating. Every
+// stream starts with a 256 KiB window in each direction, which receivers extend with
+// window updates as data is read, so a stream nobody reads never holds up the others.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+use std::task::{Context, Poll, Waker};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::AbortHandle;
+
+use super::ScallopStream;
+
+const VERSION: u8 = 0;
+
+const TYPE_DATA: u8 = 0;
+const TYPE_WINDOW_UPDATE: u8 = 1;
+const TYPE_PING: u8 = 2;
+const TYPE_GO_AWAY: u8 = 3;
+
+const FLAG_SYN: u16 = 1;
+const FLAG_ACK: u16 = 2;
+const FLAG_FIN: u16 = 4;
+const FLAG_RST: u16 = 8;
+
+const GO_AWAY_NORMAL: u32 = 0;
+const GO_AWAY_PROTOCOL_ERROR: u32 = 1;
+
+const HEADER_LEN: usize = 12;
+// window every stream starts with, larger windows are granted right after opening
+const INITIAL_WINDOW: u32 = 256 * 1024;
+// keeps large writes on one stream from holding up the others
+const MAX_FRAME: usize = 16384;
+// replies to the remote not written yet, reading stops once this many are queued
+const MAX_REPLIES: usize = 64;
+
+/// Configuration of a [`ScallopMux`]
+#[derive(Debug, Clone, Copy)]
+pub struct MuxConfig {
+    window: u32,
+    max_streams: usize,
+    backlog: usize,
+}
+
+impl Default for MuxConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MuxConfig {
+    /// 256 KiB windows, up to 1024 open streams and 128 streams waiting to be accepted
+    pub fn new() -> Self {
+        Self {
+            window: INITIAL_WINDOW,
+            max_streams: 1024,
+            backlog: 128,
+        }
+    }
+
+    /// Bytes every stream can receive before the application reads them, at least 256 KiB
+    pub fn window(mut self, window: u32) -> Self {
+        self.window = window.max(INITIAL_WINDOW);
+        self
+    }
+
+    /// Maximum number of streams open at once, opened by either side
+    pub fn max_streams(mut self, streams: usize) -> Self {
+        self.max_streams = streams;
+        self
+    }
+
+    /// Maximum number of streams opened by the remote and not accepted yet, further
+    /// streams are reset
+    pub fn backlog(mut self, backlog: usize) -> Self {
+        self.backlog = backlog.max(1);
+        self
+    }
+}
+
+fn frame(kind: u8, flags: u16, id: u32, length: u32) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN);
+    frame.push(VERSION);
+    frame.push(kind);
+    frame.extend_from_slice(&flags.to_be_bytes());
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.extend_from_slice(&length.to_be_bytes());
+    frame
+}
+
+fn data_frame(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut frame = frame(TYPE_DATA, 0, id, data.len() as u32);
+    frame.extend_from_slice(data);
+    frame
+}
+
+fn protocol_error(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+struct StreamState {
+    // received data not read yet
+    recv: VecDeque<u8>,
+    // bytes the remote can still send
+    recv_window: u32,
+    // bytes read since the last window update
+    consumed: u32,
+    // bytes we can still send
+    send_window: u32,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+    fin_received: bool,
+    fin_sent: bool,
+    reset: bool,
+}
+
+impl StreamState {
+    fn new(recv_window: u32) -> Self {
+        Self {
+            recv: VecDeque::new(),
+            recv_window,
+            consumed: 0,
+            send_window: INITIAL_WINDOW,
+            read_waker: None,
+            write_waker: None,
+            fin_received: false,
+            fin_sent: false,
+            reset: false,
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.reset || (self.fin_received && self.fin_sent)
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct Session {
+    streams: HashMap<u32, Arc<Mutex<StreamState>>>,
+    next_id: u32,
+    go_away_sent: bool,
+    go_away_received: bool,
+    closed: bool,
+}
+
+struct Shared {
+    config: MuxConfig,
+    initiator: bool,
+    // frames for the writer task, replies to the remote hold a permit until written
+    tx: mpsc::UnboundedSender<(Vec<u8>, Option<OwnedSemaphorePermit>)>,
+    session: Mutex<Session>,
+    reader: OnceLock<AbortHandle>,
+}
+
+impl Drop for Shared {
+    // nothing can use the session any more, the writer task ends on its own once the
+    // frame channel closes
+    fn drop(&mut self) {
+        if let Some(reader) = self.reader.get() {
+            reader.abort();
+        }
+    }
+}
+
+impl Shared {
+    fn send(&self, frame: Vec<u8>) -> io::Result<()> {
+        self.queue(frame, None)
+    }
+
+    fn queue(&self, frame: Vec<u8>, permit: Option<OwnedSemaphorePermit>) -> io::Result<()> {
+        self.tx
+            .send((frame, permit))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "session closed"))
+    }
+
+    // ids of streams opened by the remote have the other parity
+    fn is_remote_id(&self, id: u32) -> bool {
+        (id % 2 == 1) != self.initiator
+    }
+
+    fn remove(&self, id: u32) {
+        lock(&self.session).streams.remove(&id);
+    }
+
+    // resets every stream, the session is unusable
+    fn close(&self) {
+        let streams = {
+            let mut session = lock(&self.session);
+            session.closed = true;
+            std::mem::take(&mut session.streams)
+        };
+
+        for stream in streams.values() {
+            let mut stream = lock(stream);
+            stream.reset = true;
+            stream.wake();
+        }
+    }
+
+    // frames with SYN get a reply, sent with the permit taken for it
+    #[allow(clippy::too_many_arguments)]
+    fn handle(
+        self: &Arc<Self>,
+        kind: u8,
+        flags: u16,
+        id: u32,
+        length: u32,
+        body: Vec<u8>,
+        incoming: &mpsc::Sender<MuxStream>,
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> io::Result<()> {
+        match kind {
+            TYPE_DATA | TYPE_WINDOW_UPDATE => {}
+            TYPE_PING => {
+                if flags & FLAG_SYN != 0 {
+                    self.queue(frame(TYPE_PING, FLAG_ACK, 0, length), permit)?;
+                }
+                return Ok(());
+            }
+            TYPE_GO_AWAY => {
+                lock(&self.session).go_away_received = true;
+                return Ok(());
+            }
+            _ => return Err(protocol_error("unknown frame type")),
+        }
+
+        if id == 0 {
+            return Err(protocol_error("invalid stream id"));
+        }
+
+        let state = if flags & FLAG_SYN != 0 {
+            if !self.is_remote_id(id) {
+                return Err(protocol_error("invalid stream id"));
+            }
+
+            let mut session = lock(&self.session);
+            if session.streams.contains_key(&id) {
+                return Err(protocol_error("duplicate stream id"));
+            }
+            if session.go_away_sent || session.streams.len() >= self.config.max_streams {
+                drop(session);
+                return self.queue(frame(TYPE_WINDOW_UPDATE, FLAG_RST, id, 0), permit);
+            }
+
+            let state = Arc::new(Mutex::new(StreamState::new(self.config.window)));
+            session.streams.insert(id, state.clone());
+            drop(session);
+
+            self.queue(
+                frame(
+                    TYPE_WINDOW_UPDATE,
+                    FLAG_ACK,
+                    id,
+                    self.config.window - INITIAL_WINDOW,
+                ),
+                permit,
+            )?;
+
+            // a stream which does not fit in the backlog resets itself when dropped
+            let _ = incoming.try_send(MuxStream {
+                id,
+                state: state.clone(),
+                shared: self.clone(),
+            });
+
+            state
+        } else {
+            match lock(&self.session).streams.get(&id) {
+                Some(state) => state.clone(),
+                // frames in flight when the stream was closed
+                None => return Ok(()),
+            }
+        };
+
+        let mut stream = lock(&state);
+        if kind == TYPE_DATA {
+            if length > stream.recv_window {
+                return Err(protocol_error("stream window exceeded"));
+            }
+            stream.recv_window -= length;
+            stream.recv.extend(body);
+        } else {
+            stream.send_window = stream.send_window.saturating_add(length);
+        }
+        if flags & FLAG_FIN != 0 {
+            stream.fin_received = true;
+        }
+        if flags & FLAG_RST != 0 {
+            stream.reset = true;
+        }
+        stream.wake();
+
+        if stream.is_done() {
+            drop(stream);
+            self.remove(id);
+        }
+
+        Ok(())
+    }
+}
+
+// Ok on a clean end of the session
+async fn read_frames<R: AsyncRead + Unpin>(
+    io: &mut R,
+    shared: &Weak<Shared>,
+    incoming: &mpsc::Sender<MuxStream>,
+    replies: &Arc<Semaphore>,
+) -> io::Result<()> {
+    let mut header = [0u8; HEADER_LEN];
+    loop {
+        if io.read(&mut header[..1]).await? == 0 {
+            return Ok(());
+        }
+        io.read_exact(&mut header[1..]).await?;
+
+        if header[0] != VERSION {
+            return Err(protocol_error("unsupported version"));
+        }
+        let kind = header[1];
+        let flags = u16::from_be_bytes(header[2..4].try_into().unwrap());
+        let id = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        let length = u32::from_be_bytes(header[8..12].try_into().unwrap());
+
+        // a remote which does not read its replies is not read from either, waits
+        // without holding on to the session so it can still be dropped
+        let permit = if flags & FLAG_SYN != 0 {
+            replies.clone().acquire_owned().await.ok()
+        } else {
+            None
+        };
+
+        let Some(shared) = shared.upgrade() else {
+            return Ok(());
+        };
+
+        let mut body = Vec::new();
+        if kind == TYPE_DATA {
+            // no stream can take more than the window
+            if length > shared.config.window {
+                return Err(protocol_error("stream window exceeded"));
+            }
+            body.resize(length as usize, 0);
+            io.read_exact(&mut body).await?;
+        }
+
+        shared.handle(kind, flags, id, length, body, incoming, permit)?;
+    }
+}
+
+async fn read_loop<R: AsyncRead + Unpin>(
+    mut io: R,
+    shared: Weak<Shared>,
+    incoming: mpsc::Sender<MuxStream>,
+    replies: Arc<Semaphore>,
+) {
+    let result = read_frames(&mut io, &shared, &incoming, &replies).await;
+
+    if let Some(shared) = shared.upgrade() {
+        if matches!(&result, Err(e) if e.kind() == io::ErrorKind::InvalidData) {
+            let _ = shared.send(frame(TYPE_GO_AWAY, 0, 0, GO_AWAY_PROTOCOL_ERROR));
+        }
+        shared.close();
+    }
+}
+
+async fn write_loop<W: AsyncWrite + Unpin>(
+    mut io: W,
+    mut rx: mpsc::UnboundedReceiver<(Vec<u8>, Option<OwnedSemaphorePermit>)>,
+    shared: Weak<Shared>,
+) {
+    let result: io::Result<()> = async {
+        while let Some((frame, _permit)) = rx.recv().await {
+            io.write_all(&frame).await?;
+            // coalesce whatever else is queued into the same flush
+            while let Ok((frame, _permit)) = rx.try_recv() {
+                io.write_all(&frame).await?;
+            }
+            io.flush().await?;
+        }
+
+        // every handle is gone
+        io.write_all(&frame(TYPE_GO_AWAY, 0, 0, GO_AWAY_NORMAL))
+            .await?;
+        io.shutdown().await
+    }
+    .await;
+
+    if result.is_err() {
+        if let Some(shared) = shared.upgrade() {
+            shared.close();
+        }
+    }
+}
+
+/// Many bidirectional streams over a single Scallop session
+///
+/// Both ends of a session wrap their [`ScallopStream`] and can then open streams and
+/// accept the streams opened by the remote. Streams have their own flow control, data
+/// nobody reads only takes up the window of its own stream.
+///
+/// The session runs on background tasks and ends once the mux and every stream have
+/// been dropped, or when the remote goes away.
+///
+/// ```ignore
+/// let mux = ScallopMux::new(stream, MuxConfig::new());
+/// let mut stream = mux.open()?;
+/// stream.write_all(b"hello").await?;
+/// ```
+pub struct ScallopMux {
+    shared: Arc<Shared>,
+    incoming: tokio::sync::Mutex<mpsc::Receiver<MuxStream>>,
+}
+
+impl std::fmt::Debug for ScallopMux {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScallopMux")
+            .field("config", &self.shared.config)
+            .field("initiator", &self.shared.initiator)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScallopMux {
+    /// Multiplex streams over the session, must be called within a tokio runtime
+    pub fn new<Base: AsyncWrite + AsyncRead + Unpin + Send + 'static>(
+        stream: ScallopStream<Base>,
+        config: MuxConfig,
+    ) -> Self {
+        let initiator = stream.noise.is_initiator();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (incoming_tx, incoming_rx) = mpsc::channel(config.backlog);
+
+        let shared = Arc::new(Shared {
+            config,
+            initiator,
+            tx,
+            session: Mutex::new(Session {
+                streams: HashMap::new(),
+                next_id: if initiator { 1 } else { 2 },
+                go_away_sent: false,
+                go_away_received: false,
+                closed: false,
+            }),
+            reader: OnceLock::new(),
+        });
+
+        let (read_half, write_half) = tokio::io::split(stream);
+        let reader = tokio::spawn(read_loop(
+            read_half,
+            Arc::downgrade(&shared),
+            incoming_tx,
+            Arc::new(Semaphore::new(MAX_REPLIES)),
+        ));
+        let _ = shared.reader.set(reader.abort_handle());
+        tokio::spawn(write_loop(write_half, rx, Arc::downgrade(&shared)));
+
+        Self {
+            shared,
+            incoming: tokio::sync::Mutex::new(incoming_rx),
+        }
+    }
+
+    /// Open a new stream, the remote learns about it with the first frame so data can
+    /// be written right away
+    pub fn open(&self) -> io::Result<MuxStream> {
+        let mut session = lock(&self.shared.session);
+        if session.closed || session.go_away_sent || session.go_away_received {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "session closed"));
+        }
+        if session.streams.len() >= self.shared.config.max_streams {
+            return Err(io::Error::other("too many streams"));
+        }
+
+        let id = session.next_id;
+        session.next_id = id
+            .checked_add(2)
+            .ok_or_else(|| io::Error::other("stream ids exhausted"))?;
+
+        let state = Arc::new(Mutex::new(StreamState::new(self.shared.config.window)));
+        session.streams.insert(id, state.clone());
+        drop(session);
+
+        let stream = MuxStream {
+            id,
+            state,
+            shared: self.shared.clone(),
+        };
+        self.shared.send(frame(
+            TYPE_WINDOW_UPDATE,
+            FLAG_SYN,
+            id,
+            self.shared.config.window - INITIAL_WINDOW,
+        ))?;
+
+        Ok(stream)
+    }
+
+    /// Next stream opened by the remote, None once the session has ended
+    pub async fn accept(&self) -> Option<MuxStream> {
+        self.incoming.lock().await.recv().await
+    }
+
+    /// Stop taking new streams from the remote and tell it so, open streams carry on
+    pub fn go_away(&self) -> io::Result<()> {
+        lock(&self.shared.session).go_away_sent = true;
+        self.shared.send(frame(TYPE_GO_AWAY, 0, 0, GO_AWAY_NORMAL))
+    }
+}
+
+/// Stream of a [`ScallopMux`]
+///
+/// Writes are queued for the session as soon as the stream window allows, flushing is
+/// a no-op. Shutting down sends the end of the stream to the remote, which can keep
+/// writing until it shuts down too. Dropping a stream before the remote has finished
+/// writing resets it.
+pub struct MuxStream {
+    id: u32,
+    state: Arc<Mutex<StreamState>>,
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for MuxStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MuxStream")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MuxStream {
+    /// Id of the stream, odd for streams opened by the initiator of the session
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let mut state = lock(&self.state);
+        if !state.reset {
+            if !state.fin_received {
+                let _ = self
+                    .shared
+                    .send(frame(TYPE_WINDOW_UPDATE, FLAG_RST, self.id, 0));
+            } else if !state.fin_sent {
+                let _ = self
+                    .shared
+                    .send(frame(TYPE_WINDOW_UPDATE, FLAG_FIN, self.id, 0));
+            }
+        }
+        state.reset = true;
+        drop(state);
+
+        self.shared.remove(self.id);
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let stream = self.get_mut();
+        let mut state = lock(&stream.state);
+
+        if !state.recv.is_empty() {
+            let len = buf.remaining().min(state.recv.len());
+            let (front, back) = state.recv.as_slices();
+            let first = len.min(front.len());
+            buf.put_slice(&front[..first]);
+            buf.put_slice(&back[..len - first]);
+            state.recv.drain(..len);
+
+            // extend the window once half of it has been read
+            state.consumed += len as u32;
+            if state.consumed >= stream.shared.config.window / 2 && !state.fin_received {
+                let consumed = std::mem::take(&mut state.consumed);
+                state.recv_window += consumed;
+                stream
+                    .shared
+                    .send(frame(TYPE_WINDOW_UPDATE, 0, stream.id, consumed))?;
+            }
+
+            return Poll::Ready(Ok(()));
+        }
+
+        if state.reset {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "stream reset",
+            )));
+        }
+        if state.fin_received {
+            return Poll::Ready(Ok(()));
+        }
+
+        state.read_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let stream = self.get_mut();
+        let mut state = lock(&stream.state);
+
+        if state.reset {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "stream reset",
+            )));
+        }
+        if state.fin_sent {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "write after shutdown",
+            )));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        if state.send_window == 0 {
+            state.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf.len().min(state.send_window as usize).min(MAX_FRAME);
+        state.send_window -= len as u32;
+        stream.shared.send(data_frame(stream.id, &buf[..len]))?;
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // the writer task flushes whenever it runs out of frames
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let stream = self.get_mut();
+        let mut state = lock(&stream.state);
+
+        if state.reset {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "stream reset",
+            )));
+        }
+        if !state.fin_sent {
+            state.fin_sent = true;
+            stream
+                .shared
+                .send(frame(TYPE_WINDOW_UPDATE, FLAG_FIN, stream.id, 0))?;
+        }
+        if state.is_done() {
+            drop(state);
+            stream.shared.remove(stream.id);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use snow::Builder;
+    use tokio::io::DuplexStream;
+
+    use super::*;
+    use crate::scallop::{
+        new_client_async, new_server_async, AttestationAuther, HandshakeLimits, PolicyAuthStore,
+        ScallopProtocol,
+    };
+
+    fn secret() -> [u8; 32] {
+        Builder::new("Noise_IX_25519_ChaChaPoly_BLAKE2b".parse().unwrap())
+            .generate_keypair()
+            .unwrap()
+            .private
+            .try_into()
+            .unwrap()
+    }
+
+    async fn sessions() -> (ScallopStream<DuplexStream>, ScallopStream<DuplexStream>) {
+        let (client, server) = tokio::io::duplex(65536);
+        let (client_secret, server_secret) = (secret(), secret());
+        let (client, server) = tokio::join!(
+            new_client_async(
+                client,
+                &client_secret,
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            ),
+            new_server_async(
+                server,
+                &server_secret,
+                None::<PolicyAuthStore>,
+                None::<AttestationAuther>,
+                &ScallopProtocol::ALL,
+                HandshakeLimits::new(),
+            ),
+        );
+
+        (client.unwrap(), server.unwrap())
+    }
+
+    async fn muxes(config: MuxConfig) -> (ScallopMux, ScallopMux) {
+        let (client, server) = sessions().await;
+        (
+            ScallopMux::new(client, config),
+            ScallopMux::new(server, config),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_mux_streams() {
+        let (client, server) = muxes(MuxConfig::new()).await;
+
+        // echo every stream back
+        let echo = tokio::spawn(async move {
+            let mut ids = vec![];
+            while let Some(mut stream) = server.accept().await {
+                ids.push(stream.id());
+                tokio::spawn(async move {
+                    let mut buf = vec![];
+                    stream.read_to_end(&mut buf).await.unwrap();
+                    stream.write_all(&buf).await.unwrap();
+                    stream.shutdown().await.unwrap();
+                });
+            }
+            ids
+        });
+
+        let mut tasks = vec![];
+        for i in 0..8u8 {
+            let stream = client.open().unwrap();
+            assert_eq!(stream.id(), 2 * i as u32 + 1);
+            tasks.push(tokio::spawn(async move {
+                // larger than the window so updates are needed
+                let data = vec![i; 300 * 1024];
+                let (mut reader, mut writer) = tokio::io::split(stream);
+                let write = async {
+                    writer.write_all(&data).await.unwrap();
+                    writer.shutdown().await.unwrap();
+                };
+                let mut buf = vec![];
+                let read = reader.read_to_end(&mut buf);
+                let (_, read) = tokio::join!(write, read);
+                read.unwrap();
+                assert!(buf == data);
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // dropping the client ends the session
+        drop(client);
+        let mut ids = echo.await.unwrap();
+        ids.sort();
+        assert_eq!(ids, (0..8).map(|i| 2 * i + 1).collect::<Vec<u32>>());
+    }
+
+    #[tokio::test]
+    async fn test_mux_flow_control() {
+        let (client, server) = muxes(MuxConfig::new()).await;
+
+        let mut stalled = client.open().unwrap();
+        let mut stalled_remote = server.accept().await.unwrap();
+
+        // nobody reads, writes stop at the window
+        let data = vec![1u8; INITIAL_WINDOW as usize + 1];
+        let written = tokio::time::timeout(Duration::from_millis(200), stalled.write_all(&data));
+        assert!(written.await.is_err());
+
+        // other streams are not held up
+        let mut stream = client.open().unwrap();
+        stream.write_all(b"hello").await.unwrap();
+        let mut remote = server.accept().await.unwrap();
+        let mut buf = [0u8; 5];
+        remote.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        // reading opens the window again
+        let mut buf = vec![0u8; INITIAL_WINDOW as usize];
+        stalled_remote.read_exact(&mut buf).await.unwrap();
+        stalled.write_all(&[2u8; 10]).await.unwrap();
+        stalled.shutdown().await.unwrap();
+        let mut buf = vec![];
+        stalled_remote.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(buf.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_mux_close() {
+        let (client, server) = muxes(MuxConfig::new().backlog(1)).await;
+
+        // dropping a stream the remote is still writing to resets it
+        let stream = client.open().unwrap();
+        let mut remote = server.accept().await.unwrap();
+        drop(stream);
+        let mut buf = [0u8; 1];
+        let err = remote.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        // streams beyond the backlog are reset
+        let _queued = client.open().unwrap();
+        let mut dropped = client.open().unwrap();
+        let err = dropped.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+
+        // no new streams after going away, refused by the client once it hears about it
+        server.go_away().unwrap();
+        assert!(server.open().is_err());
+        if let Ok(mut refused) = client.open() {
+            let err = refused.read(&mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+        }
+
+        // the session ends once every handle is gone
+        drop((client, _queued, dropped));
+        assert!(server.accept().await.is_some());
+        assert!(server.accept().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mux_ping_backpressure() {
+        let (client, server) = sessions().await;
+        let _server = ScallopMux::new(server, MuxConfig::new());
+        let (mut reader, mut writer) = tokio::io::split(client);
+
+        // pings from a client which does not read the ACKs
+        const PINGS: u32 = 20_000;
+        let sent = Arc::new(AtomicU32::new(0));
+        let pinger = tokio::spawn({
+            let sent = sent.clone();
+            async move {
+                for chunk in (0..PINGS).collect::<Vec<_>>().chunks(100) {
+                    let pings: Vec<u8> = chunk
+                        .iter()
+                        .flat_map(|&i| frame(TYPE_PING, FLAG_SYN, 0, i))
+                        .collect();
+                    writer.write_all(&pings).await.unwrap();
+                    writer.flush().await.unwrap();
+                    sent.fetch_add(chunk.len() as u32, Ordering::Relaxed);
+                }
+                writer
+            }
+        });
+
+        // the server stops reading instead of queueing ACKs without bound
+        let mut last = u32::MAX;
+        loop {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let now = sent.load(Ordering::Relaxed);
+            if now == last {
+                break;
+            }
+            last = now;
+        }
+        assert!(last < PINGS);
+
+        // every ping is answered in order once the client reads
+        let mut acks = vec![0u8; PINGS as usize * HEADER_LEN];
+        reader.read_exact(&mut acks).await.unwrap();
+        for (i, ack) in acks.chunks(HEADER_LEN).enumerate() {
+            assert_eq!(ack, frame(TYPE_PING, FLAG_ACK, 0, i as u32));
+        }
+        pinger.await.unwrap();
+    }
+}
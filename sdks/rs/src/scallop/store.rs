@@ -0,0 +1,384 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::attestation::{AttestationError, AttestationPolicy, Clock, SystemClock};
+
+use super::ScallopAuthStore;
+
+type Pcrs = ([u8; 48], [u8; 48], [u8; 48]);
+
+#[derive(Debug)]
+struct Entry {
+    pcrs: Pcrs,
+    // milliseconds since the unix epoch
+    expires_at: u64,
+    // recency for lru eviction, bumped on every lookup
+    used: Cell<u64>,
+}
+
+/// [`ScallopAuthStore`] verifying remote attestations against an [`AttestationPolicy`]
+///
+/// An attestation is accepted only if it satisfies the policy and its public key
+/// is the Noise static key of the remote. Accepted keys are cached along with their
+/// pcrs for a ttl, after which the remote is asked to attest again. The cache holds
+/// up to `capacity` keys, evicting the least recently used one when full.
+///
+/// ```ignore
+/// let mut store = PolicyAuthStore::new(AttestationPolicy::new().pcr(0, pcr0).max_age(300000))
+///     .ttl(Duration::from_secs(600))
+///     .capacity(1000);
+/// let stream = new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+///     stream, &secret, Some(&mut store), Some(&mut auther),
+/// ).await?;
+/// let (pcr0, pcr1, pcr2) = store.get(&stream.get_remote_static().unwrap()).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PolicyAuthStore<C: Clock = SystemClock> {
+    policy: AttestationPolicy,
+    clock: C,
+    ttl: u64,
+    capacity: usize,
+    entries: HashMap<[u8; 32], Entry>,
+    counter: Cell<u64>,
+    last_error: Option<AttestationError>,
+}
+
+impl PolicyAuthStore<SystemClock> {
+    /// Store verifying against the given policy, the public key check is always added on top
+    pub fn new(policy: AttestationPolicy) -> Self {
+        Self::with_clock(policy, SystemClock)
+    }
+}
+
+impl<C: Clock> PolicyAuthStore<C> {
+    /// Same as [`PolicyAuthStore::new`], with verification and expiry as of the time given by the clock
+    pub fn with_clock(policy: AttestationPolicy, clock: C) -> Self {
+        Self {
+            policy,
+            clock,
+            ttl: 600000,
+            capacity: 1024,
+            entries: HashMap::new(),
+            counter: Cell::new(0),
+            last_error: None,
+        }
+    }
+
+    /// How long a verified key is trusted before the remote has to attest again, defaults to 10 minutes
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl.as_millis().try_into().unwrap_or(u64::MAX);
+        self
+    }
+
+    /// Maximum number of cached keys, defaults to 1024
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Number of cached keys, including expired ones which have not been purged yet
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no keys are cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forget a key, the remote will have to attest again on the next handshake
+    pub fn remove(&mut self, key: &[u8; 32]) -> Option<Pcrs> {
+        self.entries.remove(key).map(|x| x.pcrs)
+    }
+
+    /// Why the most recent attestation was rejected, the handshake only reports
+    /// `invalid attestation`
+    pub fn last_error(&self) -> Option<&AttestationError> {
+        self.last_error.as_ref()
+    }
+
+    fn lookup(&self, key: &[u8; 32]) -> Option<&Entry> {
+        let entry = self
+            .entries
+            .get(key)
+            .filter(|x| x.expires_at > self.clock.now_millis())?;
+        self.counter.set(self.counter.get() + 1);
+        entry.used.set(self.counter.get());
+        Some(entry)
+    }
+
+    fn check(&self, attestation: &[u8], key: &[u8; 32]) -> Result<Pcrs, AttestationError> {
+        let doc = self.policy.verify_at(attestation, &self.clock)?;
+
+        if doc.public_key.as_deref().map(|x| x.as_slice()) != Some(key.as_slice()) {
+            return Err(AttestationError::VerifyFailed(
+                "public key does not match static key".into(),
+            ));
+        }
+
+        let pcr = |index| -> Result<[u8; 48], AttestationError> {
+            doc.pcr(index)
+                .and_then(|x| x.try_into().ok())
+                .ok_or_else(|| AttestationError::ParseFailed(format!("pcr{index}")))
+        };
+
+        Ok((pcr(0)?, pcr(1)?, pcr(2)?))
+    }
+}
+
+impl<C: Clock> ScallopAuthStore for PolicyAuthStore<C> {
+    fn contains(&self, key: &[u8; 32]) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    fn get(&self, key: &[u8; 32]) -> Option<&([u8; 48], [u8; 48], [u8; 48])> {
+        self.lookup(key).map(|x| &x.pcrs)
+    }
+
+    fn set(&mut self, key: [u8; 32], pcrs: ([u8; 48], [u8; 48], [u8; 48])) {
+        let now = self.clock.now_millis();
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            // make room, expired entries first, then the least recently used one
+            self.entries.retain(|_, x| x.expires_at > now);
+            if self.entries.len() >= self.capacity {
+                // safe to unwrap since capacity is at least 1
+                let lru = *self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, x)| x.used.get())
+                    .unwrap()
+                    .0;
+                self.entries.remove(&lru);
+            }
+        }
+
+        self.counter.set(self.counter.get() + 1);
+        self.entries.insert(
+            key,
+            Entry {
+                pcrs,
+                expires_at: now.saturating_add(self.ttl),
+                used: Cell::new(self.counter.get()),
+            },
+        );
+    }
+
+    fn verify(
+        &mut self,
+        attestation: &[u8],
+        key: &[u8; 32],
+    ) -> Option<([u8; 48], [u8; 48], [u8; 48])> {
+        match self.check(attestation, key) {
+            Ok(pcrs) => Some(pcrs),
+            Err(e) => {
+                self.last_error = Some(e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::attestation::builder::mock_attestation;
+    use crate::attestation::{PolicyFailure, TrustAnchors};
+    use crate::scallop::{
+        new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b,
+        new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b, ScallopAuther,
+    };
+
+    // clock which can be moved forward from outside the store
+    #[derive(Clone, Default)]
+    struct TestClock(Arc<AtomicU64>);
+
+    impl Clock for TestClock {
+        fn now_millis(&self) -> u64 {
+            SystemClock.now_millis() + self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    fn policy() -> AttestationPolicy {
+        AttestationPolicy::new()
+            .pcr(0, [0u8; 48])
+            .pcr(1, [1u8; 48])
+            .trust_anchors(TrustAnchors::oyster_mock())
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut store = PolicyAuthStore::new(policy());
+
+        let pcrs = store
+            .verify(&mock_attestation(Some(&[7u8; 32]), None, None), &[7u8; 32])
+            .unwrap();
+        assert_eq!(pcrs, ([0u8; 48], [1u8; 48], [2u8; 48]));
+
+        // public key is not the static key
+        assert!(store
+            .verify(&mock_attestation(Some(&[7u8; 32]), None, None), &[8u8; 32])
+            .is_none());
+        assert!(matches!(
+            store.last_error(),
+            Some(AttestationError::VerifyFailed(e)) if e == "public key does not match static key"
+        ));
+
+        // no public key
+        assert!(store
+            .verify(&mock_attestation(None, None, None), &[7u8; 32])
+            .is_none());
+
+        // policy failure
+        let mut store = PolicyAuthStore::new(policy().pcr(2, [0u8; 48]));
+        assert!(store
+            .verify(&mock_attestation(Some(&[7u8; 32]), None, None), &[7u8; 32])
+            .is_none());
+        assert!(matches!(
+            store.last_error(),
+            Some(AttestationError::PolicyFailed(report))
+                if report.failures == [PolicyFailure::PcrMismatch { index: 2, actual: vec![2u8; 48] }]
+        ));
+    }
+
+    #[test]
+    fn test_expiry() {
+        let clock = TestClock::default();
+        let mut store =
+            PolicyAuthStore::with_clock(policy(), clock.clone()).ttl(Duration::from_secs(60));
+
+        store.set([1u8; 32], ([1u8; 48], [1u8; 48], [1u8; 48]));
+        assert!(store.contains(&[1u8; 32]));
+        assert_eq!(store.get(&[1u8; 32]).unwrap().0, [1u8; 48]);
+
+        clock.0.store(61000, Ordering::Relaxed);
+        assert!(!store.contains(&[1u8; 32]));
+        assert!(store.get(&[1u8; 32]).is_none());
+
+        // re-attesting refreshes the entry
+        store.set([1u8; 32], ([1u8; 48], [1u8; 48], [1u8; 48]));
+        assert!(store.contains(&[1u8; 32]));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let clock = TestClock::default();
+        let mut store = PolicyAuthStore::with_clock(policy(), clock.clone())
+            .ttl(Duration::from_secs(60))
+            .capacity(2);
+        let pcrs = ([0u8; 48], [0u8; 48], [0u8; 48]);
+
+        store.set([1u8; 32], pcrs);
+        store.set([2u8; 32], pcrs);
+        // 1 is now more recently used than 2
+        assert!(store.contains(&[1u8; 32]));
+        store.set([3u8; 32], pcrs);
+        assert_eq!(store.len(), 2);
+        assert!(store.contains(&[1u8; 32]));
+        assert!(!store.contains(&[2u8; 32]));
+        assert!(store.contains(&[3u8; 32]));
+
+        // 3 was looked up last
+        clock.0.store(30000, Ordering::Relaxed);
+        store.set([4u8; 32], pcrs);
+        assert!(!store.contains(&[1u8; 32]));
+
+        // expired entries go first regardless of recency
+        clock.0.store(59000, Ordering::Relaxed);
+        assert!(store.contains(&[3u8; 32]));
+        clock.0.store(61000, Ordering::Relaxed);
+        store.set([5u8; 32], pcrs);
+        assert!(!store.contains(&[3u8; 32]));
+        assert!(store.contains(&[4u8; 32]));
+        assert!(store.contains(&[5u8; 32]));
+
+        assert_eq!(store.remove(&[4u8; 32]), Some(pcrs));
+        assert_eq!(store.len(), 1);
+    }
+
+    struct Auther(Vec<u8>);
+
+    impl ScallopAuther for Auther {
+        async fn new_auth(&mut self) -> Box<[u8]> {
+            self.0.clone().into()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let builder = snow::Builder::new("Noise_IX_25519_ChaChaPoly_BLAKE2b".parse().unwrap());
+        let client_keys = builder.generate_keypair().unwrap();
+        let server_keys = builder.generate_keypair().unwrap();
+        let client_key: [u8; 32] = client_keys.public.try_into().unwrap();
+        let client_secret: [u8; 32] = client_keys.private.try_into().unwrap();
+        let server_secret: [u8; 32] = server_keys.private.try_into().unwrap();
+
+        let mut store = PolicyAuthStore::new(policy());
+
+        for _ in 0..2 {
+            let (client, server) = tokio::io::duplex(100000);
+            let mut auther = Auther(mock_attestation(Some(&client_key), None, None));
+
+            let client = tokio::spawn(async move {
+                let mut stream = new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+                    client,
+                    &client_secret,
+                    None::<&mut PolicyAuthStore>,
+                    Some(&mut auther),
+                )
+                .await
+                .unwrap();
+                stream.write_all(b"hello").await.unwrap();
+                stream.flush().await.unwrap();
+            });
+
+            let mut stream = new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+                server,
+                &server_secret,
+                Some(&mut store),
+                None::<&mut Auther>,
+            )
+            .await
+            .unwrap();
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"hello");
+            client.await.unwrap();
+
+            let remote = stream.get_remote_static().unwrap();
+            assert_eq!(remote, client_key);
+            assert_eq!(store.get(&remote), Some(&([0u8; 48], [1u8; 48], [2u8; 48])));
+        }
+
+        // client attesting to a key other than its static key
+        let (client, server) = tokio::io::duplex(100000);
+        let mut store = PolicyAuthStore::new(policy());
+        let mut auther = Auther(mock_attestation(Some(&[7u8; 32]), None, None));
+        let client = tokio::spawn(async move {
+            new_client_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+                client,
+                &client_secret,
+                None::<&mut PolicyAuthStore>,
+                Some(&mut auther),
+            )
+            .await
+        });
+        assert!(new_server_async_Noise_IX_25519_ChaChaPoly_BLAKE2b(
+            server,
+            &server_secret,
+            Some(&mut store),
+            None::<&mut Auther>,
+        )
+        .await
+        .is_err());
+        let _ = client.await.unwrap();
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use snow::params::CipherChoice;
+use snow::resolvers::{CryptoResolver, DefaultResolver};
+use snow::types::Cipher;
+
+use super::{ScallopError, ScallopProtocol};
+
+// protocol id | client static key | expiry | psk
+const SEALED_LEN: usize = 1 + 32 + 8 + 32;
+// nonce | sealed | tag
+pub(super) const TICKET_LEN: usize = 8 + SEALED_LEN + 16;
+const TICKET_AD: &[u8] = b"scallop ticket";
+
+/// Server side state for resuming sessions, shared by every handshake of a server
+///
+/// Tickets are the client static key and a fresh pre-shared key, encrypted under a key
+/// which never leaves the process, so restarting the server invalidates every ticket.
+/// They are issued during full handshakes to clients which ask for them and only
+/// accepted while the auth store, if any, still trusts the client, so resumption never
+/// outlives the attestation it was built on.
+///
+/// Early data sent with a ticket can be replayed by anyone on the path. Resumptions are
+/// accepted at most once per client ephemeral key, which is remembered until the ticket
+/// expires. Once `capacity` resumptions are remembered, further ones fall back to full
+/// handshakes until some expire. The window only covers this instance, servers sharing
+/// a static key behind a load balancer should not share tickets.
+///
+/// ```ignore
+/// let tickets = ScallopTickets::new().lifetime(Duration::from_secs(300));
+/// let stream = new_server_resumable_async(
+///     stream,
+///     &secret,
+///     store,
+///     auther,
+///     &protocols,
+///     &tickets,
+///     HandshakeLimits::new(),
+/// )
+/// .await?;
+/// ```
+pub struct ScallopTickets {
+    key: [u8; 32],
+    nonce: AtomicU64,
+    lifetime: Duration,
+    capacity: usize,
+    // client ephemeral keys of accepted resumptions and the expiry of their tickets
+    seen: Mutex<HashMap<[u8; 32], u64>>,
+}
+
+impl std::fmt::Debug for ScallopTickets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the key
+        f.debug_struct("ScallopTickets")
+            .field("lifetime", &self.lifetime)
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ScallopTickets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// decrypted contents of a ticket
+pub(super) struct OpenedTicket {
+    pub(super) protocol: ScallopProtocol,
+    pub(super) client_static: [u8; 32],
+    pub(super) expiry: u64,
+    pub(super) psk: [u8; 32],
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn cipher(key: &[u8; 32]) -> Box<dyn Cipher + Send> {
+    let mut cipher = DefaultResolver
+        .resolve_cipher(&CipherChoice::ChaChaPoly)
+        .expect("default resolver supports ChaChaPoly");
+    cipher.set(key);
+    cipher
+}
+
+impl ScallopTickets {
+    /// Tickets under a random key, valid for 10 minutes, remembering up to 65536
+    /// resumptions
+    pub fn new() -> Self {
+        Self {
+            key: rand::random(),
+            nonce: AtomicU64::new(0),
+            lifetime: Duration::from_secs(600),
+            capacity: 65536,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long tickets can be used after being issued, also the length of the
+    /// anti-replay window
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Maximum number of resumptions remembered for replay protection
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    fn seal(&self, protocol: ScallopProtocol, client_static: &[u8; 32], psk: &[u8; 32]) -> Vec<u8> {
+        let nonce = self.nonce.fetch_add(1, Ordering::Relaxed);
+
+        let mut sealed = [0u8; SEALED_LEN];
+        sealed[0] = protocol.id();
+        sealed[1..33].copy_from_slice(client_static);
+        sealed[33..41].copy_from_slice(&(now() + self.lifetime.as_secs()).to_be_bytes());
+        sealed[41..73].copy_from_slice(psk);
+
+        let mut ticket = vec![0u8; TICKET_LEN];
+        ticket[0..8].copy_from_slice(&nonce.to_be_bytes());
+        cipher(&self.key).encrypt(nonce, TICKET_AD, &sealed, &mut ticket[8..]);
+
+        ticket
+    }
+
+    // None if the ticket was not issued by us or has expired
+    pub(super) fn open(&self, ticket: &[u8]) -> Option<OpenedTicket> {
+        if ticket.len() != TICKET_LEN {
+            return None;
+        }
+
+        let nonce = u64::from_be_bytes(ticket[0..8].try_into().unwrap());
+        let mut sealed = [0u8; SEALED_LEN];
+        cipher(&self.key)
+            .decrypt(nonce, TICKET_AD, &ticket[8..], &mut sealed)
+            .ok()?;
+
+        let expiry = u64::from_be_bytes(sealed[33..41].try_into().unwrap());
+        if expiry <= now() {
+            return None;
+        }
+
+        Some(OpenedTicket {
+            protocol: ScallopProtocol::from_id(sealed[0])?,
+            client_static: sealed[1..33].try_into().unwrap(),
+            expiry,
+            psk: sealed[41..73].try_into().unwrap(),
+        })
+    }
+
+    // false if the resumption has been seen before or there is no room to remember it
+    pub(super) fn accept(&self, ephemeral: [u8; 32], expiry: u64) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+
+        if seen.contains_key(&ephemeral) {
+            return false;
+        }
+
+        if seen.len() >= self.capacity {
+            let now = now();
+            seen.retain(|_, expiry| *expiry > now);
+            if seen.len() >= self.capacity {
+                return false;
+            }
+        }
+
+        seen.insert(ephemeral, expiry);
+        true
+    }
+
+    // lifetime | ticket length | ticket | psk, appended to the server handshake payload
+    pub(super) fn grant(&self, protocol: ScallopProtocol, client_static: &[u8; 32]) -> Vec<u8> {
+        let psk: [u8; 32] = rand::random();
+        let ticket = self.seal(protocol, client_static, &psk);
+
+        let mut grant = Vec::with_capacity(6 + TICKET_LEN + 32);
+        let lifetime = self.lifetime.as_secs().min(u32::MAX as u64) as u32;
+        grant.extend_from_slice(&lifetime.to_be_bytes());
+        grant.extend_from_slice(&(ticket.len() as u16).to_be_bytes());
+        grant.extend_from_slice(&ticket);
+        grant.extend_from_slice(&psk);
+
+        grant
+    }
+}
+
+/// Ticket for resuming a session with a server, see [`super::new_client_resumable_async`]
+///
+/// Holds the key protecting early data of the resumed session, treat it like a secret.
+#[derive(Clone)]
+pub struct ScallopTicket {
+    pub(super) server_static: [u8; 32],
+    pub(super) protocol: ScallopProtocol,
+    pub(super) ticket: Box<[u8]>,
+    pub(super) psk: [u8; 32],
+    pub(super) expires: Instant,
+}
+
+impl std::fmt::Debug for ScallopTicket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the psk
+        f.debug_struct("ScallopTicket")
+            .field("server_static", &hex::encode(self.server_static))
+            .field("protocol", &self.protocol)
+            .field("expires", &self.expires)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScallopTicket {
+    // parses a grant from the server handshake payload
+    pub(super) fn from_grant(
+        grant: &[u8],
+        server_static: [u8; 32],
+        protocol: ScallopProtocol,
+    ) -> Result<Self, ScallopError> {
+        if grant.len() < 6 {
+            return Err(ScallopError::InvalidTicket);
+        }
+        let lifetime = u32::from_be_bytes(grant[0..4].try_into().unwrap());
+        let len = u16::from_be_bytes([grant[4], grant[5]]) as usize;
+        if grant.len() != 6 + len + 32 {
+            return Err(ScallopError::InvalidTicket);
+        }
+
+        Ok(Self {
+            server_static,
+            protocol,
+            ticket: grant[6..6 + len].into(),
+            psk: grant[6 + len..].try_into().unwrap(),
+            expires: Instant::now() + Duration::from_secs(lifetime.into()),
+        })
+    }
+
+    /// Static key of the server which issued the ticket
+    pub fn server_static(&self) -> [u8; 32] {
+        self.server_static
+    }
+
+    /// Protocol of the session the ticket resumes
+    pub fn protocol(&self) -> ScallopProtocol {
+        self.protocol
+    }
+
+    /// Whether the server will no longer accept the ticket
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+}
@@ -0,0 +1,247 @@
+//! WebAssembly bindings for decoding and verifying Oyster attestations
+//!
+//! Binary inputs are accepted either as a `Uint8Array` or as a hex string with
+//! an optional `0x` prefix. Results are plain JSON compatible objects with
+//! binary fields hex encoded.
+
+use oyster::attestation::{
+    eip712, AttestationDocument, AttestationError, AttestationPolicy, Clock, FixedClock,
+};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+pub use oyster::attestation::json::{Anchor, Document, KeyType, NamedAnchor, PcrValue, Policy};
+
+/// Outcome of verifying an attestation against a policy
+///
+/// `document` is only set on success, `failures` lists every failed policy check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Verification {
+    pub ok: bool,
+    pub document: Option<Document>,
+    pub error: Option<String>,
+    pub failures: Vec<String>,
+}
+
+fn from_hex(value: &str, name: &str) -> Result<Vec<u8>, String> {
+    let value = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(value).map_err(|e| format!("{name}: {e}"))
+}
+
+/// Decode an attestation without verifying it
+pub fn decode_document(attestation: &[u8]) -> Result<Document, AttestationError> {
+    AttestationDocument::from_cose(attestation).map(Into::into)
+}
+
+/// Verify an attestation against a policy, errors only if the policy itself is invalid
+pub fn verify_document(
+    attestation: &[u8],
+    policy: &Policy,
+    clock: &dyn Clock,
+) -> Result<Verification, String> {
+    let policy = AttestationPolicy::try_from(policy)?;
+
+    Ok(match policy.verify_at(attestation, clock) {
+        Ok(doc) => Verification {
+            ok: true,
+            document: Some(doc.into()),
+            error: None,
+            failures: Vec::new(),
+        },
+        Err(AttestationError::PolicyFailed(report)) => Verification {
+            ok: false,
+            document: None,
+            error: Some(AttestationError::PolicyFailed(report.clone()).to_string()),
+            failures: report.failures.iter().map(ToString::to_string).collect(),
+        },
+        Err(e) => Verification {
+            ok: false,
+            document: None,
+            error: Some(e.to_string()),
+            failures: Vec::new(),
+        },
+    })
+}
+
+// javascript clock, SystemTime is not available on wasm32-unknown-unknown
+struct DateClock;
+
+impl Clock for DateClock {
+    fn now_millis(&self) -> u64 {
+        js_sys::Date::now() as u64
+    }
+}
+
+fn to_bytes(value: &JsValue, name: &str) -> Result<Vec<u8>, JsError> {
+    if let Some(value) = value.as_string() {
+        return from_hex(&value, name).map_err(|e| JsError::new(&e));
+    }
+    if let Some(value) = value.dyn_ref::<js_sys::Uint8Array>() {
+        return Ok(value.to_vec());
+    }
+
+    Err(JsError::new(&format!(
+        "{name}: expected a Uint8Array or a hex string"
+    )))
+}
+
+fn to_millis(value: f64, name: &str) -> Result<u64, JsError> {
+    // anything beyond 2^53 cannot be represented exactly in javascript
+    if !(0.0..=9007199254740991.0).contains(&value) || value.fract() != 0.0 {
+        return Err(JsError::new(&format!(
+            "{name}: expected a non-negative integer"
+        )));
+    }
+    Ok(value as u64)
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Decode an attestation without verifying it
+#[wasm_bindgen]
+pub fn decode(attestation: JsValue) -> Result<JsValue, JsError> {
+    let attestation = to_bytes(&attestation, "attestation")?;
+    let document = decode_document(&attestation).map_err(|e| JsError::new(&e.to_string()))?;
+
+    to_js(&document)
+}
+
+/// Verify an attestation against a policy
+///
+/// `now` overrides the current time in milliseconds, e.g. to check a recorded
+/// attestation as of its own timestamp. Throws only on invalid input, a failed
+/// verification is reported in the result.
+#[wasm_bindgen]
+pub fn verify(attestation: JsValue, policy: JsValue, now: Option<f64>) -> Result<JsValue, JsError> {
+    let attestation = to_bytes(&attestation, "attestation")?;
+    let policy: Policy = if policy.is_undefined() || policy.is_null() {
+        Policy::default()
+    } else {
+        serde_wasm_bindgen::from_value(policy).map_err(|e| JsError::new(&format!("policy: {e}")))?
+    };
+
+    let result = match now {
+        Some(now) => verify_document(&attestation, &policy, &FixedClock(to_millis(now, "now")?)),
+        None => verify_document(&attestation, &policy, &DateClock),
+    }
+    .map_err(|e| JsError::new(&format!("policy: {e}")))?;
+
+    to_js(&result)
+}
+
+/// Hex encoded EIP-712 digest of an attestation as signed by the attestation verifier
+#[wasm_bindgen(js_name = computeDigest)]
+pub fn compute_digest(
+    public_key: JsValue,
+    pcr0: JsValue,
+    pcr1: JsValue,
+    pcr2: JsValue,
+    timestamp: f64,
+) -> Result<String, JsError> {
+    let digest = eip712::compute_digest(
+        &to_bytes(&public_key, "publicKey")?,
+        &to_bytes(&pcr0, "pcr0")?,
+        &to_bytes(&pcr1, "pcr1")?,
+        &to_bytes(&pcr2, "pcr2")?,
+        to_millis(timestamp, "timestamp")?,
+    );
+
+    Ok(hex::encode(digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PCR0: &str = "5fec1b73727425848d725d68f4a062c634061a035067bd0b9a6dc73e25ed5013dfe7ccbf8a7e9857eceb0841c4cb6ae6";
+
+    fn recorded() -> (Vec<u8>, u64) {
+        let attestation = std::fs::read("../rs/src/test/attestation.bin").unwrap();
+        let timestamp = decode_document(&attestation).unwrap().timestamp;
+        (attestation, timestamp)
+    }
+
+    #[test]
+    fn test_decode() {
+        let (attestation, timestamp) = recorded();
+        let document = decode_document(&attestation).unwrap();
+
+        assert_eq!(document.timestamp, timestamp);
+        assert_eq!(document.pcrs[&0], PCR0);
+        assert_eq!(document.pcrs.len(), 16);
+        assert_eq!(document.cabundle.len(), 4);
+
+        let value = serde_json::to_value(&document).unwrap();
+        assert_eq!(value["pcrs"]["0"], PCR0);
+        assert_eq!(value["moduleId"], document.module_id);
+        assert!(decode_document(b"not an attestation").is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let (attestation, timestamp) = recorded();
+        let clock = FixedClock(timestamp);
+
+        let policy: Policy = serde_json::from_value(json!({
+            "pcrs": { "0": format!("0x{PCR0}"), "1": [PCR0, "00"] },
+            "maxAge": 300000,
+            "trustAnchors": ["aws_nitro"],
+        }))
+        .unwrap();
+        let result = verify_document(&attestation, &policy, &clock).unwrap();
+        assert!(!result.ok);
+        assert_eq!(result.failures.len(), 1);
+        assert!(result.failures[0].contains("pcr1"));
+
+        let policy: Policy = serde_json::from_value(json!({
+            "pcrs": { "0": PCR0 },
+            "maxAge": 300000,
+        }))
+        .unwrap();
+        let result = verify_document(&attestation, &policy, &clock).unwrap();
+        assert!(result.ok);
+        assert_eq!(result.document.unwrap().timestamp, timestamp);
+
+        // root not trusted
+        let policy: Policy = serde_json::from_value(json!({
+            "trustAnchors": ["oyster_mock"],
+        }))
+        .unwrap();
+        let result = verify_document(&attestation, &policy, &clock).unwrap();
+        assert!(!result.ok);
+        assert!(result.failures.is_empty());
+        assert!(result.error.unwrap().contains("root"));
+
+        // custom pem roots
+        let policy: Policy = serde_json::from_value(json!({
+            "trustAnchors": [include_str!("../../rs/src/aws.cert")],
+        }))
+        .unwrap();
+        assert!(verify_document(&attestation, &policy, &clock).unwrap().ok);
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        let (attestation, timestamp) = recorded();
+        let clock = FixedClock(timestamp);
+
+        for policy in [
+            json!({ "pcrs": { "0": "zz" } }),
+            json!({ "pcrs": { "zero": PCR0 } }),
+            json!({ "userDataSha256": "00" }),
+            json!({ "trustAnchors": ["not a pem"] }),
+        ] {
+            let policy: Policy = serde_json::from_value(policy).unwrap();
+            assert!(verify_document(&attestation, &policy, &clock).is_err());
+        }
+
+        assert!(serde_json::from_value::<Policy>(json!({ "maxAgee": 1 })).is_err());
+        assert!(serde_json::from_value::<Policy>(json!({ "publicKeyType": "rsa" })).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+// run with `wasm-pack test --node` or `cargo test --target wasm32-unknown-unknown`
+// using wasm-bindgen-test-runner, node is the default environment
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Reflect, Uint8Array, JSON};
+use oyster_sdk_wasm::{compute_digest, decode, verify};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const ATTESTATION: &[u8] = include_bytes!("../../rs/src/test/attestation.bin");
+const TIMESTAMP: f64 = 1723012689640.0;
+const PCR0: &str = "5fec1b73727425848d725d68f4a062c634061a035067bd0b9a6dc73e25ed5013dfe7ccbf8a7e9857eceb0841c4cb6ae6";
+
+fn get(value: &JsValue, path: &[&str]) -> JsValue {
+    path.iter().fold(value.clone(), |value, key| {
+        Reflect::get(&value, &JsValue::from_str(key)).unwrap()
+    })
+}
+
+fn policy(json: &str) -> JsValue {
+    JSON::parse(json).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn decode_raw_and_hex() {
+    let raw = decode(Uint8Array::from(ATTESTATION).into()).unwrap();
+    let hex = decode(JsValue::from_str(&format!("0x{}", hex::encode(ATTESTATION)))).unwrap();
+
+    assert_eq!(get(&raw, &["timestamp"]).as_f64(), Some(TIMESTAMP));
+    assert_eq!(get(&raw, &["pcrs", "0"]).as_string().as_deref(), Some(PCR0));
+    // plain objects, serializable as is
+    assert_eq!(
+        JSON::stringify(&raw).unwrap().as_string(),
+        JSON::stringify(&hex).unwrap().as_string()
+    );
+
+    assert!(decode(JsValue::from_str("zz")).is_err());
+    assert!(decode(JsValue::from_f64(1.0)).is_err());
+}
+
+#[wasm_bindgen_test]
+fn verify_policy() {
+    let attestation = JsValue::from(Uint8Array::from(ATTESTATION));
+
+    let result = verify(
+        attestation.clone(),
+        policy(&format!(r#"{{ "pcrs": {{ "0": "{PCR0}" }}, "maxAge": 300000 }}"#)),
+        Some(TIMESTAMP),
+    )
+    .unwrap();
+    assert_eq!(get(&result, &["ok"]).as_bool(), Some(true));
+    assert_eq!(
+        get(&result, &["document", "timestamp"]).as_f64(),
+        Some(TIMESTAMP)
+    );
+
+    let result = verify(
+        attestation.clone(),
+        policy(r#"{ "pcrs": { "0": "00" }, "nonce": "00" }"#),
+        Some(TIMESTAMP),
+    )
+    .unwrap();
+    assert_eq!(get(&result, &["ok"]).as_bool(), Some(false));
+    assert_eq!(get(&result, &["failures", "length"]).as_f64(), Some(2.0));
+
+    // recorded certificates have expired since
+    let result = verify(attestation.clone(), JsValue::UNDEFINED, None).unwrap();
+    assert_eq!(get(&result, &["ok"]).as_bool(), Some(false));
+
+    assert!(verify(attestation, policy(r#"{ "maxAgee": 1 }"#), None).is_err());
+}
+
+#[wasm_bindgen_test]
+fn digest() {
+    let digest = compute_digest(
+        JsValue::from_str(&hex::encode([1u8; 64])),
+        Uint8Array::from(&[2u8; 48][..]).into(),
+        Uint8Array::from(&[3u8; 48][..]).into(),
+        Uint8Array::from(&[4u8; 48][..]).into(),
+        TIMESTAMP,
+    )
+    .unwrap();
+    assert_eq!(
+        digest,
+        hex::encode(oyster::attestation::eip712::compute_digest(
+            &[1; 64],
+            &[2; 48],
+            &[3; 48],
+            &[4; 48],
+            TIMESTAMP as u64
+        ))
+    );
+
+    assert!(compute_digest(
+        JsValue::from_str(""),
+        JsValue::from_str(""),
+        JsValue::from_str(""),
+        JsValue::from_str(""),
+        -1.0
+    )
+    .is_err());
+}